@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use token_ring::message::*;
+
+// Lines a peer reads from the server and from the previous peer.
+fuzz_target!(|line: &str| {
+    let _ = Hello::from_json_string(line);
+    let _ = StartFlag::from_json_string(line);
+    let _ = HotPotato::from_json_string(line);
+    let _ = FindHotPotato::from_json_string(line);
+    let _ = Reply::from_json_string(line);
+    let _ = Control::from_json_string(line);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use token_ring::message::*;
+
+// Lines a server reads from its peers.
+fuzz_target!(|line: &str| {
+    let _ = Hello::from_json_string(line);
+    if let Ok(request) = ServerRequest::from_json_string(line) {
+        let _ = request.to_response();
+    } else if let Ok(batch_request) = BatchRequest::from_json_string(line) {
+        let _ = batch_request.to_response();
+    } else {
+        let _ = PeerStatus::from_json_string(line);
+    }
+});
@@ -0,0 +1,386 @@
+use crate::server::{Server, ServerStats};
+use crate::*;
+use color_print::cformat;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, error::Error, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::{timeout_at, Instant},
+};
+
+/// Time the peers have to report their status.
+const REPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Commands of the admin protocol, one JSON line each, answered by an [`AdminResponse`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AdminRequest {
+    /// The connected peers and their addresses.
+    Peers,
+    /// The ring's order and the hot potato's holder, as reported by the peers.
+    Ring,
+    /// The peers keep the hot potato when it arrives until resumed.
+    Pause,
+    Resume,
+    /// Replaces the hot potato with a new one, thrown to `peer` or to the first peer.
+    Regenerate {
+        peer: Option<NodeId>,
+    },
+    /// Removes a peer from the ring, its previous peer throws to its next peer instead.
+    Evict {
+        peer: NodeId,
+    },
+    Stats,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AdminResponse {
+    Peers(BTreeMap<NodeId, String>),
+    Ring {
+        order: Vec<NodeId>,
+        holder: Option<NodeId>,
+        paused: bool,
+    },
+    Stats {
+        stats: ServerStats,
+        epoch: u64,
+        paused: bool,
+        peers: Vec<PeerStatus>,
+    },
+    Done(String),
+    Error(String),
+}
+
+impl AdminRequest {
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(request: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(request)?)
+    }
+}
+
+impl AdminResponse {
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(response: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(response)?)
+    }
+
+    pub fn print(&self) {
+        match self {
+            Self::Peers(peers) => {
+                log::info(&cformat!("<bold>{}</bold> peers connected.", peers.len()));
+                for (node_id, address) in peers {
+                    println!("  {node_id}  {address}");
+                }
+            }
+            Self::Ring {
+                order,
+                holder,
+                paused,
+            } => {
+                let ring = order
+                    .iter()
+                    .map(|node_id| match holder {
+                        Some(holder) if holder == node_id => {
+                            cformat!("<yellow, bold>[{}]</yellow, bold>", node_id)
+                        }
+                        _ => node_id.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                log::info(&format!("Ring: {ring}"));
+                if *paused {
+                    log::warning("The ring is paused.");
+                }
+            }
+            Self::Stats {
+                stats,
+                epoch,
+                paused,
+                peers,
+            } => {
+                log::info(&cformat!(
+                    "<bold>{}</bold> requests (<bold>{}</bold> failed), <bold>{}</bold> batches, epoch <bold>{}</bold>{}.",
+                    stats.requests,
+                    stats.failed_requests,
+                    stats.batches,
+                    epoch,
+                    if *paused { ", paused" } else { "" }
+                ));
+                log::info(&cformat!(
+                    "<bold>{}</bold> shared requests, <bold>{}</bold> regenerations, <bold>{}</bold> evictions.",
+                    stats.shared_requests,
+                    stats.regenerations,
+                    stats.evictions
+                ));
+                for (resource, requests) in &stats.resource_requests {
+                    println!("  {resource}  {requests} requests");
+                }
+                for peer in peers {
+                    println!(
+                        "  {}  queue {}  visits {}  {:.1} rotations/s{}",
+                        peer.node_id,
+                        peer.queue_length,
+                        peer.visits,
+                        peer.rotation_rate,
+                        if peer.holding.is_some() {
+                            "  holding"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            Self::Done(message) => log::info(message),
+            Self::Error(message) => log::error(message),
+        }
+    }
+}
+
+/// Whether an admin connecting from `address` may control the ring: over an authenticating
+/// transport anyone with a certificate of the ring's authority, otherwise only local admins.
+pub fn admits(address: &str, authenticated: bool) -> bool {
+    match address.parse::<SocketAddr>() {
+        Ok(address) => authenticated || address.ip().is_loopback(),
+        // Unix socket and in-memory connections can't come from another host
+        Err(_) => true,
+    }
+}
+
+/// Answers admin connections accepted on `listener`.
+pub async fn serve<L: Listener>(server: Arc<Mutex<Server>>, mut listener: L) {
+    let authenticated = listener.authenticates();
+    loop {
+        let (mut lines, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error(&format!("Couldn't accept admin connections anymore: {e}"));
+                return;
+            }
+        };
+        if !admits(&address, authenticated) {
+            log::warning(&cformat!(
+                "Refused an admin from <bold>{}</bold>, only local admins are allowed without TLS.",
+                address
+            ));
+            continue;
+        }
+        log::info(&cformat!("Admin connected from <bold>{}</bold>.", address));
+
+        let server = server.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(line)) = lines.next().await {
+                let response = match AdminRequest::from_json_string(&line) {
+                    Ok(request) => execute(&server, request)
+                        .await
+                        .unwrap_or_else(|e| AdminResponse::Error(e.to_string())),
+                    Err(e) => AdminResponse::Error(format!("Invalid admin request: {e}")),
+                };
+                let Ok(response) = response.to_json_string() else {
+                    break;
+                };
+                if lines.send(response).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+pub async fn execute(
+    server: &Mutex<Server>,
+    request: AdminRequest,
+) -> Result<AdminResponse, Box<dyn Error + Send + Sync>> {
+    match request {
+        AdminRequest::Peers => Ok(AdminResponse::Peers(server.lock().await.peers.clone())),
+        AdminRequest::Ring => {
+            let statuses = collect_statuses(server).await?;
+            let paused = server.lock().await.paused;
+
+            Ok(AdminResponse::Ring {
+                order: ring_order(&statuses),
+                holder: holder(&statuses),
+                paused,
+            })
+        }
+        AdminRequest::Pause | AdminRequest::Resume => {
+            let paused = request == AdminRequest::Pause;
+            let mut server = server.lock().await;
+            server.paused = paused;
+            server.broadcast(if paused {
+                &Control::Pause
+            } else {
+                &Control::Resume
+            })?;
+
+            Ok(AdminResponse::Done(
+                if paused {
+                    "Paused the ring."
+                } else {
+                    "Resumed the ring."
+                }
+                .to_string(),
+            ))
+        }
+        AdminRequest::Regenerate { peer } => {
+            let mut server = server.lock().await;
+            let holder = match peer {
+                Some(peer) if !server.controls.contains_key(&peer) => {
+                    return Err(format!("{peer} isn't connected.").into())
+                }
+                Some(peer) => peer,
+                None => server
+                    .controls
+                    .keys()
+                    .next()
+                    .cloned()
+                    .ok_or("No peer is connected.")?,
+            };
+            server.regenerate(&holder)?;
+
+            Ok(AdminResponse::Done(format!(
+                "Sent a hot potato of epoch {} to {holder}.",
+                server.epoch
+            )))
+        }
+        AdminRequest::Evict { peer } => evict(server, &peer).await,
+        AdminRequest::Stats => {
+            let statuses = collect_statuses(server).await?;
+            let server = server.lock().await;
+
+            Ok(AdminResponse::Stats {
+                stats: server.stats.clone(),
+                epoch: server.epoch,
+                paused: server.paused,
+                peers: statuses.into_values().collect(),
+            })
+        }
+    }
+}
+
+/// Connects to the admin protocol of the server at `address` and sends it `request`.
+pub async fn request<T: Transport>(
+    transport: &T,
+    address: &str,
+    request: &AdminRequest,
+) -> Result<AdminResponse, Box<dyn Error + Send + Sync>> {
+    let mut lines = transport.connect(address).await?;
+    lines.send(request.to_json_string()?).await?;
+
+    match lines.next().await {
+        Some(line) => AdminResponse::from_json_string(&line?),
+        None => Err("The server closed the admin connection.".into()),
+    }
+}
+
+async fn evict(
+    server: &Mutex<Server>,
+    peer: &NodeId,
+) -> Result<AdminResponse, Box<dyn Error + Send + Sync>> {
+    let statuses = collect_statuses(server).await?;
+    let status = statuses
+        .get(peer)
+        .ok_or_else(|| format!("{peer} didn't report its status."))?;
+
+    let mut server = server.lock().await;
+    let previous_peer = statuses
+        .values()
+        .find(|other| other.node_id != *peer && other.next_peer_id.as_ref() == Some(peer));
+    let next_peer = status.next_peer_id.clone().filter(|next| next != peer);
+
+    // close the ring around the evicted peer before it leaves
+    if let (Some(previous_peer), Some(next_peer)) = (previous_peer, &next_peer) {
+        let next_peer_address = server
+            .peers
+            .get(next_peer)
+            .cloned()
+            .ok_or_else(|| format!("{next_peer} isn't connected."))?;
+        server.send_to(
+            &previous_peer.node_id,
+            Control::Rewire { next_peer_address }.to_json_string()?,
+        )?;
+    }
+    server.send_to(peer, Control::Evict.to_json_string()?)?;
+    server.controls.remove(peer);
+    server.peers.remove(peer);
+    server.evicted.insert(peer.clone());
+    server.stats.evictions += 1;
+    log::warning(&cformat!("Evicted <bold>{}</bold>.", peer));
+
+    // the hot potatoes leave with the peer holding them, and so do the ones thrown to it after
+    // it reported or before its previous peer was rewired, the new epoch makes them stale
+    let holder = next_peer.or_else(|| server.controls.keys().next().cloned());
+    if let Some(holder) = holder {
+        server.regenerate(&holder)?;
+    }
+
+    Ok(AdminResponse::Done(format!("Evicted {peer}.")))
+}
+
+/// Asks every connected peer for its status and waits for their answers.
+async fn collect_statuses(
+    server: &Mutex<Server>,
+) -> Result<BTreeMap<NodeId, PeerStatus>, Box<dyn Error + Send + Sync>> {
+    let (status_tx, mut status_rx) = mpsc::unbounded_channel();
+    let expected = {
+        let mut server = server.lock().await;
+        server.status_waiters.push(status_tx);
+        server.broadcast(&Control::Report)?;
+        server.controls.len()
+    };
+
+    // reports asked for by other requests count as well, they're just as recent
+    let deadline = Instant::now() + REPORT_TIMEOUT;
+    let mut statuses = BTreeMap::new();
+    while statuses.len() < expected {
+        match timeout_at(deadline, status_rx.recv()).await {
+            Ok(Some((node_id, status))) => {
+                statuses.insert(node_id, status);
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    Ok(statuses)
+}
+
+/// The peers in the ring's order from the lowest node id, then those not linked to it.
+fn ring_order(statuses: &BTreeMap<NodeId, PeerStatus>) -> Vec<NodeId> {
+    let Some(first) = statuses.keys().next() else {
+        return Vec::new();
+    };
+
+    let mut order = vec![first.clone()];
+    while let Some(next) = statuses
+        .get(order.last().unwrap_or(first))
+        .and_then(|status| status.next_peer_id.as_ref())
+    {
+        if order.contains(next) || !statuses.contains_key(next) {
+            break;
+        }
+        order.push(next.clone());
+    }
+    order.extend(
+        statuses
+            .keys()
+            .filter(|node_id| !order.contains(node_id))
+            .cloned()
+            .collect::<Vec<_>>(),
+    );
+
+    order
+}
+
+/// The peer holding the hot potato of the latest epoch.
+fn holder(statuses: &BTreeMap<NodeId, PeerStatus>) -> Option<NodeId> {
+    statuses
+        .values()
+        .filter_map(|status| status.holding.map(|holding| (holding, &status.node_id)))
+        .max()
+        .map(|(_, node_id)| node_id.clone())
+}
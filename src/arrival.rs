@@ -0,0 +1,316 @@
+use crate::*;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::{error::Error, f64::consts::PI};
+
+/// A stochastic process that decides when requests arrive at a peer.
+pub trait ArrivalProcess: Send {
+    /// Returns the seconds until the next arrival and how many requests arrive together.
+    fn next_arrival(&mut self) -> (f64, usize);
+}
+
+pub struct Deterministic {
+    pub interval: f64,
+}
+
+pub struct UniformArrival {
+    pub rng: SmallRng,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Heavy-tailed inter-arrival times with the given scale (minimum) and shape.
+pub struct Pareto {
+    pub rng: SmallRng,
+    pub scale: f64,
+    pub shape: f64,
+}
+
+/// Poisson arrivals whose rate is picked by a hidden Markov chain cycling through `rates`,
+/// leaving state `i` at rate `switch_rates[i]`.
+pub struct MarkovModulatedPoisson {
+    pub rng: SmallRng,
+    pub rates: Vec<f64>,
+    pub switch_rates: Vec<f64>,
+    pub state: usize,
+}
+
+/// Poisson arrivals carrying a geometrically distributed number of requests.
+pub struct BatchPoisson {
+    pub poisson: Poisson<SmallRng>,
+    pub mean_batch: f64,
+}
+
+/// Poisson arrivals during `on` seconds followed by `off` seconds of silence.
+pub struct OnOff {
+    pub poisson: Poisson<SmallRng>,
+    pub on: f64,
+    pub off: f64,
+    pub elapsed: f64,
+}
+
+/// Poisson arrivals whose rate follows a sine wave with the given period (in seconds).
+pub struct Diurnal {
+    pub poisson: Poisson<SmallRng>,
+    pub period: f64,
+    pub amplitude: f64,
+    pub elapsed: f64,
+}
+
+fn exponential(rng: &mut SmallRng, rate: f64) -> f64 {
+    -(1.0f64 - rng.random::<f64>()).ln() / rate
+}
+
+/// Fails unless `value` is a finite number above `min`, or equal to it when `inclusive`.
+fn check(name: &str, value: f64, min: f64, inclusive: bool) -> Result<(), String> {
+    let above = if inclusive { value >= min } else { value > min };
+    if value.is_finite() && above {
+        Ok(())
+    } else {
+        let bound = if inclusive { "at least" } else { "above" };
+        Err(format!("The {name} must be {bound} {min}, not {value}."))
+    }
+}
+
+impl ArrivalProcess for Poisson<SmallRng> {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        (self.time_for_next_event(), 1)
+    }
+}
+
+impl Deterministic {
+    pub fn new(rate: f64) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("rate", rate, 0., false)?;
+
+        Ok(Self {
+            interval: 1. / rate,
+        })
+    }
+}
+
+impl ArrivalProcess for Deterministic {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        (self.interval, 1)
+    }
+}
+
+impl UniformArrival {
+    pub fn new(min: f64, max: f64, seed: &[u8; 32]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("minimum inter-arrival time", min, 0., true)?;
+        check("maximum inter-arrival time", max, min, true)?;
+
+        Ok(Self {
+            rng: SmallRng::from_seed(*seed),
+            min,
+            max,
+        })
+    }
+}
+
+impl ArrivalProcess for UniformArrival {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        (
+            self.min + (self.max - self.min) * self.rng.random::<f64>(),
+            1,
+        )
+    }
+}
+
+impl Pareto {
+    pub fn new(
+        scale: f64,
+        shape: f64,
+        seed: &[u8; 32],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("Pareto scale", scale, 0., false)?;
+        check("Pareto shape", shape, 0., false)?;
+
+        Ok(Self {
+            rng: SmallRng::from_seed(*seed),
+            scale,
+            shape,
+        })
+    }
+
+    /// Pareto arrivals with mean inter-arrival time `1 / rate`, the mean is only finite for a
+    /// `shape` above 1.
+    pub fn with_rate(
+        rate: f64,
+        shape: f64,
+        seed: &[u8; 32],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("rate", rate, 0., false)?;
+        check("Pareto shape", shape, 1., false)?;
+
+        Self::new((shape - 1.) / (shape * rate), shape, seed)
+    }
+}
+
+impl ArrivalProcess for Pareto {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        let u = 1.0f64 - self.rng.random::<f64>();
+        (self.scale / u.powf(1. / self.shape), 1)
+    }
+}
+
+impl MarkovModulatedPoisson {
+    pub fn new(
+        rates: Vec<f64>,
+        switch_rates: Vec<f64>,
+        seed: &[u8; 32],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if rates.is_empty() || rates.len() != switch_rates.len() {
+            return Err("Every state needs a rate and a switch rate.".into());
+        }
+        for (rate, switch_rate) in rates.iter().zip(&switch_rates) {
+            check("rate of a state", *rate, 0., true)?;
+            check("switch rate of a state", *switch_rate, 0., true)?;
+            // a state without either would never be left
+            check("total rate of a state", rate + switch_rate, 0., false)?;
+        }
+        if rates.iter().all(|rate| *rate == 0.) {
+            return Err("At least one state needs a positive rate.".into());
+        }
+
+        Ok(Self {
+            rng: SmallRng::from_seed(*seed),
+            rates,
+            switch_rates,
+            state: 0,
+        })
+    }
+}
+
+impl ArrivalProcess for MarkovModulatedPoisson {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        let mut time = 0.;
+
+        loop {
+            let rate = self.rates[self.state];
+            let switch_rate = self.switch_rates[self.state];
+            let total = rate + switch_rate;
+
+            time += exponential(&mut self.rng, total);
+            if self.rng.random::<f64>() * total < rate {
+                return (time, 1);
+            }
+            self.state = (self.state + 1) % self.rates.len();
+        }
+    }
+}
+
+impl BatchPoisson {
+    pub fn new(
+        rate: f64,
+        mean_batch: f64,
+        seed: &[u8; 32],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("rate", rate, 0., false)?;
+        check("mean batch size", mean_batch, 1., true)?;
+
+        Ok(Self {
+            poisson: Poisson::new(rate, seed),
+            mean_batch,
+        })
+    }
+}
+
+impl ArrivalProcess for BatchPoisson {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        let time = self.poisson.time_for_next_event();
+        let continue_probability = 1. - 1. / self.mean_batch.max(1.);
+        let mut batch = 1;
+
+        while self.poisson.rng.random::<f64>() < continue_probability {
+            batch += 1;
+        }
+
+        (time, batch)
+    }
+}
+
+impl OnOff {
+    pub fn new(
+        rate: f64,
+        on: f64,
+        off: f64,
+        seed: &[u8; 32],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("rate", rate, 0., false)?;
+        // requests only arrive during the on periods
+        check("on period", on, 0., false)?;
+        check("off period", off, 0., true)?;
+
+        Ok(Self {
+            poisson: Poisson::new(rate, seed),
+            on,
+            off,
+            elapsed: 0.,
+        })
+    }
+}
+
+impl ArrivalProcess for OnOff {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        let cycle = self.on + self.off;
+        let mut time = self.elapsed;
+
+        loop {
+            time += self.poisson.time_for_next_event();
+            let phase = time % cycle;
+
+            if phase < self.on {
+                break;
+            }
+            // memoryless, so restart from the beginning of the next on period
+            time += cycle - phase;
+        }
+
+        let delay = time - self.elapsed;
+        self.elapsed = time;
+        (delay, 1)
+    }
+}
+
+impl Diurnal {
+    pub fn new(
+        rate: f64,
+        period: f64,
+        amplitude: f64,
+        seed: &[u8; 32],
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        check("rate", rate, 0., false)?;
+        check("period", period, 0., false)?;
+        // the rate may drop to zero but not below
+        if !(-1. ..=1.).contains(&amplitude) {
+            return Err(format!("The amplitude must be between -1 and 1, not {amplitude}.").into());
+        }
+
+        Ok(Self {
+            poisson: Poisson::new(rate * (1. + amplitude.abs()), seed),
+            period,
+            amplitude,
+            elapsed: 0.,
+        })
+    }
+}
+
+impl ArrivalProcess for Diurnal {
+    fn next_arrival(&mut self) -> (f64, usize) {
+        // thinning of a Poisson process running at the peak rate
+        let mut time = self.elapsed;
+
+        loop {
+            time += self.poisson.time_for_next_event();
+            let current = (1. + self.amplitude * (2. * PI * time / self.period).sin())
+                / (1. + self.amplitude.abs());
+
+            if self.poisson.rng.random::<f64>() < current {
+                break;
+            }
+        }
+
+        let delay = time - self.elapsed;
+        self.elapsed = time;
+        (delay, 1)
+    }
+}
@@ -0,0 +1,147 @@
+use crate::*;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{collections::HashMap, error::Error, fmt, fs};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shared secret the hot potato is signed with, every node of the ring must use the same.
+#[derive(Clone)]
+pub struct TokenKey(Vec<u8>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenRejection {
+    Unsigned,
+    Forged,
+    /// Signed for another holder, e.g. a token sniffed on another link.
+    Misdirected {
+        holder: NodeId,
+    },
+    /// Already received with this or a later sequence.
+    Replayed {
+        last_sequence: u64,
+    },
+    /// From another epoch than the one the server announced last, e.g. of a replaced token
+    /// or of an earlier run of the server.
+    OtherEpoch {
+        epoch: u64,
+    },
+}
+
+/// Verifies received tokens and remembers the last sequence seen per resource and epoch to
+/// spot replays. Only tokens of `epoch`, the one the server announced last, are accepted.
+#[derive(Clone, Default)]
+pub struct TokenGuard {
+    pub key: Option<TokenKey>,
+    pub epoch: u64,
+    last_sequences: HashMap<(String, u64), u64>,
+}
+
+impl TokenKey {
+    pub fn new(secret: &[u8]) -> Self {
+        Self(secret.to_vec())
+    }
+
+    /// Reads the secret from a file, surrounding whitespace is ignored.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let secret = fs::read(path)?;
+        let secret = secret.trim_ascii();
+
+        if secret.is_empty() {
+            return Err(format!("The token secret in {path} is empty.").into());
+        }
+        Ok(Self::new(secret))
+    }
+
+    fn mac(&self, hot_potato: &HotPotato) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts keys of any size.");
+        // the token's JSON delimits every field, so no two tokens sign the same bytes
+        let unsigned = HotPotato {
+            mac: None,
+            ..hot_potato.clone()
+        };
+        let unsigned = serde_json::to_vec(&unsigned).expect("Tokens always serialize.");
+        mac.update(&unsigned);
+        mac
+    }
+
+    pub fn sign(&self, hot_potato: &mut HotPotato) {
+        hot_potato.mac = Some(hex::encode(self.mac(hot_potato).finalize().into_bytes()));
+    }
+
+    pub fn verify(&self, hot_potato: &HotPotato) -> Result<(), TokenRejection> {
+        let mac = hot_potato.mac.as_ref().ok_or(TokenRejection::Unsigned)?;
+        let mac = hex::decode(mac).map_err(|_| TokenRejection::Forged)?;
+
+        self.mac(hot_potato)
+            .verify_slice(&mac)
+            .map_err(|_| TokenRejection::Forged)
+    }
+}
+
+impl fmt::Display for TokenRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsigned => write!(f, "the token isn't signed"),
+            Self::Forged => write!(f, "the token's signature is invalid"),
+            Self::Misdirected { holder } => write!(f, "the token was signed for {holder}"),
+            Self::Replayed { last_sequence } => {
+                write!(
+                    f,
+                    "the token was replayed, last sequence was {last_sequence}"
+                )
+            }
+            Self::OtherEpoch { epoch } => write!(f, "the token isn't of epoch {epoch}"),
+        }
+    }
+}
+
+impl Error for TokenRejection {}
+
+impl TokenGuard {
+    pub fn new(key: Option<TokenKey>) -> Self {
+        Self {
+            key,
+            epoch: 0,
+            last_sequences: HashMap::new(),
+        }
+    }
+
+    /// Accepts `hot_potato` for `node_id` if it's of the current epoch, without checking its
+    /// signature when no key is configured.
+    pub fn check(
+        &mut self,
+        hot_potato: &HotPotato,
+        node_id: &NodeId,
+    ) -> Result<(), TokenRejection> {
+        if hot_potato.epoch != self.epoch {
+            return Err(TokenRejection::OtherEpoch { epoch: self.epoch });
+        }
+
+        if let Some(key) = &self.key {
+            key.verify(hot_potato)?;
+
+            if &hot_potato.holder != node_id {
+                return Err(TokenRejection::Misdirected {
+                    holder: hot_potato.holder.clone(),
+                });
+            }
+            let token = (hot_potato.resource.clone(), hot_potato.epoch);
+            if let Some(&last_sequence) = self.last_sequences.get(&token) {
+                if hot_potato.sequence <= last_sequence {
+                    return Err(TokenRejection::Replayed { last_sequence });
+                }
+            }
+            self.last_sequences.insert(token, hot_potato.sequence);
+        }
+
+        Ok(())
+    }
+
+    /// Signs `hot_potato` when a key is configured.
+    pub fn sign(&self, hot_potato: &mut HotPotato) {
+        if let Some(key) = &self.key {
+            key.sign(hot_potato);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use clap::{Parser, Subcommand};
+use std::{error::Error, process::ExitCode};
+use token_ring::{
+    admin::{self, AdminRequest, AdminResponse},
+    identity::NodeId,
+    tls, transport,
+};
+
+/// Inspects and steers a running server through its admin protocol (see the server's --admin).
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Address the server serves the admin protocol on.
+    #[arg(index = 1)]
+    address: String,
+
+    #[command(subcommand)]
+    command: Command,
+
+    #[arg(long, value_enum, default_value_t)]
+    transport: transport::TransportKind,
+
+    /// CA certificate (PEM), when the ring uses mutual TLS.
+    #[arg(long, requires_all = ["tls_certificate", "tls_key"])]
+    tls_ca: Option<String>,
+
+    /// Certificate (PEM) issued by the CA to authenticate with.
+    #[arg(long, requires = "tls_ca")]
+    tls_certificate: Option<String>,
+
+    /// Private key (PEM) of the certificate.
+    #[arg(long, requires = "tls_ca")]
+    tls_key: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the connected peers.
+    Peers,
+    /// Show the ring's order and the hot potato's holder.
+    Ring,
+    /// Stop the hot potato at the next peer it reaches.
+    Pause,
+    /// Let the hot potato go around again.
+    Resume,
+    /// Replace the hot potato with a new one.
+    Regenerate {
+        /// Peer the new hot potato is sent to, the first one by default.
+        peer: Option<String>,
+    },
+    /// Remove a peer from the ring.
+    Evict { peer: String },
+    /// Show the server's and the peers' statistics.
+    Stats,
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let request = match args.command {
+        Command::Peers => AdminRequest::Peers,
+        Command::Ring => AdminRequest::Ring,
+        Command::Pause => AdminRequest::Pause,
+        Command::Resume => AdminRequest::Resume,
+        Command::Regenerate { peer } => AdminRequest::Regenerate {
+            peer: peer.as_deref().map(NodeId::new),
+        },
+        Command::Evict { peer } => AdminRequest::Evict {
+            peer: NodeId::new(&peer),
+        },
+        Command::Stats => AdminRequest::Stats,
+    };
+    let tls = args
+        .tls_ca
+        .zip(args.tls_certificate)
+        .zip(args.tls_key)
+        .map(|((ca, certificate), key)| tls::TlsConfig::new(ca, certificate, key));
+
+    let response = match (args.transport, &tls) {
+        (transport::TransportKind::Tcp, None) => {
+            admin::request(&transport::Tcp, &args.address, &request).await?
+        }
+        (transport::TransportKind::Unix, None) => {
+            admin::request(&transport::Unix, &args.address, &request).await?
+        }
+        (transport::TransportKind::Tcp, Some(config)) => {
+            let transport = tls::Tls::new(transport::Tcp, config)?;
+            admin::request(&transport, &args.address, &request).await?
+        }
+        (transport::TransportKind::Unix, Some(config)) => {
+            let transport = tls::Tls::new(transport::Unix, config)?;
+            admin::request(&transport, &args.address, &request).await?
+        }
+    };
+
+    response.print();
+    Ok(match response {
+        AdminResponse::Error(_) => ExitCode::FAILURE,
+        _ => ExitCode::SUCCESS,
+    })
+}
@@ -0,0 +1,35 @@
+use clap::Parser;
+use std::error::Error;
+use token_ring::{identity, tls};
+
+/// Creates a local CA and a certificate for each node, for the --tls-* flags of the server and
+/// the peers.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Directory the PEM files are written to.
+    #[arg(index = 1)]
+    directory: String,
+
+    /// Ids of the nodes to issue certificates for.
+    #[arg(index = 2, required = true)]
+    node_ids: Vec<String>,
+}
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let node_ids = args
+        .node_ids
+        .iter()
+        .map(|id| identity::NodeId::new(id))
+        .collect::<Vec<_>>();
+    tls::generate_certificates(&args.directory, &node_ids)?;
+
+    println!(
+        "Wrote ca.pem and a certificate for {} nodes to {}.",
+        node_ids.len(),
+        args.directory
+    );
+    Ok(())
+}
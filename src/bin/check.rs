@@ -0,0 +1,41 @@
+use clap::Parser;
+use color_print::cformat;
+use std::{error::Error, process::ExitCode};
+use token_ring::{checker, log};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Event logs recorded by the server and the peers.
+    #[arg(index = 1, required = true)]
+    event_logs: Vec<String>,
+
+    /// Critical sections of other nodes a waiting node may be bypassed by.
+    #[arg(long)]
+    max_bypasses: Option<usize>,
+}
+
+fn main() -> Result<ExitCode, Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let mut events = Vec::new();
+    for path in &args.event_logs {
+        events.extend(checker::Event::from_json_file(path)?);
+    }
+
+    match checker::Checker::new(args.max_bypasses).check(&events) {
+        Ok(summary) => {
+            log::info(&cformat!(
+                "Checked <bold>{}</bold> events and <bold>{}</bold> critical sections, at most <bold>{}</bold> bypasses.",
+                summary.events,
+                summary.critical_sections,
+                summary.max_bypasses
+            ));
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(violation) => {
+            log::error(&format!("{violation}"));
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use clap::Parser;
+use std::{error::Error, io::Write, time::Duration};
+use terminal_size::{terminal_size, Height};
+use token_ring::{
+    dashboard, tls,
+    transport::{self, Listener, Transport},
+};
+use tokio::{sync::mpsc, time::interval};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Shows the state of a running ring, fed by the nodes started with --dashboard.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Address the nodes report to, with the same transport as the ring. Without TLS only nodes
+    /// on the same host are accepted.
+    #[arg(index = 1)]
+    address: String,
+
+    #[arg(long, value_enum, default_value_t)]
+    transport: transport::TransportKind,
+
+    /// CA certificate (PEM), when the ring uses mutual TLS.
+    #[arg(long, requires_all = ["tls_certificate", "tls_key"])]
+    tls_ca: Option<String>,
+
+    /// Certificate (PEM) issued by the CA to authenticate with.
+    #[arg(long, requires = "tls_ca")]
+    tls_certificate: Option<String>,
+
+    /// Private key (PEM) of the certificate.
+    #[arg(long, requires = "tls_ca")]
+    tls_key: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+    let tls = args
+        .tls_ca
+        .zip(args.tls_certificate)
+        .zip(args.tls_key)
+        .map(|((ca, certificate), key)| tls::TlsConfig::new(ca, certificate, key));
+
+    match (args.transport, &tls) {
+        (transport::TransportKind::Tcp, None) => {
+            show(transport::Tcp.bind(&args.address).await?).await
+        }
+        (transport::TransportKind::Unix, None) => {
+            show(transport::Unix.bind(&args.address).await?).await
+        }
+        (transport::TransportKind::Tcp, Some(config)) => {
+            let transport = tls::Tls::new(transport::Tcp, config)?;
+            show(transport.bind(&args.address).await?).await
+        }
+        (transport::TransportKind::Unix, Some(config)) => {
+            let transport = tls::Tls::new(transport::Unix, config)?;
+            show(transport.bind(&args.address).await?).await
+        }
+    }
+}
+
+/// Draws the events reported to `listener` until interrupted.
+async fn show<L: Listener>(listener: L) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let mut receiving = tokio::spawn(dashboard::receive(listener, event_tx));
+
+    let mut state = dashboard::Dashboard::new();
+    let mut refresh = interval(REFRESH_INTERVAL);
+    let mut stdout = std::io::stdout();
+
+    // hide the cursor while drawing, and show it again however the loop ends
+    print!("\x1b[?25l");
+    let result: Result<(), Box<dyn Error + Send + Sync>> = loop {
+        tokio::select! {
+            Some(event) = event_rx.recv() => state.apply(&event),
+            _ = refresh.tick() => {
+                let height = terminal_size().map_or(24, |(_, Height(h))| h as usize);
+                print!("\x1b[H\x1b[2J{}", state.render(height));
+                if let Err(e) = stdout.flush() {
+                    break Err(e.into());
+                }
+            }
+            received = &mut receiving => break match received {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(e)) => Err(e.into()),
+                Err(e) => Err(e.into()),
+            },
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+        }
+    };
+    println!("\x1b[?25h");
+
+    result
+}
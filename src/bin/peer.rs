@@ -1,34 +1,196 @@
 use clap::Parser;
-use std::{error::Error, time::Duration};
-use token_ring::{log, peer};
+use std::{error::Error, process};
+use token_ring::{config, dashboard, identity, log, peer, policy, tls, transport};
 use tokio::time::sleep;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[arg(index = 1)]
-    self_address: String,
+    self_address: Option<String>,
 
     #[arg(index = 2)]
-    server_address: String,
+    server_address: Option<String>,
 
     #[arg(index = 3)]
-    next_peer_address: String,
+    next_peer_address: Option<String>,
+
+    /// Accept requests submitted by clients on this address, with the same transport as the ring.
+    #[arg(long)]
+    submissions: Option<String>,
+
+    /// TOML config file, its keys can be overridden with TOKEN_RING_* environment variables
+    /// and by the flags.
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Maximum number of operations sent per hot potato visit.
+    #[arg(long)]
+    max_operations: Option<usize>,
+
+    /// Maximum time (in milliseconds) the hot potato is held per visit.
+    #[arg(long)]
+    max_hold_time: Option<u64>,
+
+    /// Quota multiplier for a peer, as NODE_ID=WEIGHT (can be repeated).
+    #[arg(long = "weight", value_parser = policy::parse_weight)]
+    weights: Vec<(identity::NodeId, usize)>,
+
+    /// Visits in a row low-priority work may let the hot potato go on to peers with
+    /// high-priority work.
+    #[arg(long)]
+    max_deferrals: Option<usize>,
+
+    /// Minimum time (in milliseconds) each hop of a hot potato nobody asked for takes.
+    #[arg(long)]
+    min_hop_delay: Option<u64>,
+
+    /// Maximum time (in milliseconds) the hop delay backs off to while the ring is idle.
+    #[arg(long)]
+    max_idle_delay: Option<u64>,
+
+    /// Wait up to this many milliseconds for the server responses before throwing the hot potato.
+    #[arg(long)]
+    pipeline_timeout: Option<u64>,
+
+    /// Send the requests of each visit as a single batch.
+    #[arg(long)]
+    batch: bool,
+
+    /// Roll back the whole batch if any of its requests fails (implies --batch).
+    #[arg(long)]
+    atomic: bool,
+
+    /// JSON file describing the generated workload (rates, operation mix, arrivals or a trace).
+    #[arg(long)]
+    workload: Option<String>,
+
+    #[arg(long, value_enum)]
+    transport: Option<transport::TransportKind>,
+
+    /// How the hot potato moves, `on-demand` keeps it at its last holder while the ring is idle.
+    #[arg(long, value_enum)]
+    algorithm: Option<config::Algorithm>,
+
+    /// CA certificate (PEM) every node trusts, enables mutual TLS on all links.
+    #[arg(long, requires_all = ["tls_certificate", "tls_key"])]
+    tls_ca: Option<String>,
+
+    /// Certificate (PEM) of this node, issued by the CA for its node id.
+    #[arg(long, requires = "tls_ca")]
+    tls_certificate: Option<String>,
+
+    /// Private key (PEM) of the node's certificate.
+    #[arg(long, requires = "tls_ca")]
+    tls_key: Option<String>,
+
+    /// File holding the secret the hot potato is signed with, shared by the whole ring.
+    #[arg(long)]
+    token_secret_file: Option<String>,
+
+    /// Stable id of this node, used in logs and recorded events.
+    #[arg(long)]
+    node_id: Option<String>,
+
+    /// File the node id is read from, or generated into when missing.
+    #[arg(long)]
+    node_id_file: Option<String>,
+
+    /// Record events to this file for the invariant checker.
+    #[arg(long)]
+    event_log: Option<String>,
+
+    /// Report events to the dashboard listening on this address.
+    #[arg(long)]
+    dashboard: Option<String>,
+
+    #[arg(long, value_enum)]
+    log_level: Option<log::Level>,
+}
+
+impl Args {
+    /// The config file and environment, overridden by the flags given.
+    fn into_config(self) -> Result<config::PeerConfig, config::ConfigError> {
+        let mut config = config::PeerConfig::load(self.config.as_deref())?;
+
+        config.address = self.self_address.or(config.address);
+        config.server_address = self.server_address.or(config.server_address);
+        config.next_peer_address = self.next_peer_address.or(config.next_peer_address);
+        config.submission_address = self.submissions.or(config.submission_address);
+        config.node_id = self.node_id.or(config.node_id);
+        config.node_id_file = self.node_id_file.or(config.node_id_file);
+        config.transport = self.transport.unwrap_or(config.transport);
+        config.algorithm = self.algorithm.unwrap_or(config.algorithm);
+        config.max_operations = self.max_operations.or(config.max_operations);
+        config.max_hold_time_ms = self.max_hold_time.or(config.max_hold_time_ms);
+        config.weights.extend(self.weights);
+        config.max_deferrals = self.max_deferrals.unwrap_or(config.max_deferrals);
+        config.min_hop_delay_ms = self.min_hop_delay.unwrap_or(config.min_hop_delay_ms);
+        config.max_idle_delay_ms = self.max_idle_delay.unwrap_or(config.max_idle_delay_ms);
+        config.pipeline_timeout_ms = self.pipeline_timeout.or(config.pipeline_timeout_ms);
+        if self.atomic {
+            config.batching = peer::Batching::AllOrNothing;
+        } else if self.batch {
+            config.batching = peer::Batching::PartialFailure;
+        }
+        if self.workload.is_some() {
+            config.workload = None;
+            config.workload_file = self.workload;
+        }
+        if let Some(((ca, certificate), key)) =
+            self.tls_ca.zip(self.tls_certificate).zip(self.tls_key)
+        {
+            config.tls = Some(tls::TlsConfig::new(ca, certificate, key));
+        }
+        config.auth.token_secret_file = self.token_secret_file.or(config.auth.token_secret_file);
+        config.log.event_log = self.event_log.or(config.log.event_log);
+        config.log.dashboard = self.dashboard.or(config.log.dashboard);
+        config.log.level = self.log_level.unwrap_or(config.log.level);
+
+        config.validate()?;
+        Ok(config)
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let args = Args::parse();
-    log::clear();
+    let config = match Args::parse().into_config() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error(&e.to_string());
+            process::exit(2);
+        }
+    };
+    config.log.apply();
 
-    let mut peer = peer::Peer::new(
-        args.self_address,
-        args.server_address,
-        args.next_peer_address,
-    );
+    match (config.transport, &config.tls) {
+        (transport::TransportKind::Tcp, None) => run(transport::Tcp, &config).await,
+        (transport::TransportKind::Unix, None) => run(transport::Unix, &config).await,
+        (transport::TransportKind::Tcp, Some(tls)) => {
+            run(tls::Tls::new(transport::Tcp, tls)?, &config).await
+        }
+        (transport::TransportKind::Unix, Some(tls)) => {
+            run(tls::Tls::new(transport::Unix, tls)?, &config).await
+        }
+    }
+}
+
+/// Runs the peer over `transport`, rejoining the ring whenever it loses it until evicted.
+async fn run<T: transport::Transport>(
+    transport: T,
+    config: &config::PeerConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let transport = transport::Limited::new(transport, config.codec.max_line_length);
+    let mut peer = config.to_peer()?;
+    if let Some(address) = &config.log.dashboard {
+        dashboard::report_to(&transport, address, &peer.node_id, &mut peer.recorder);
+    }
 
     loop {
-        peer.run().await;
-        sleep(Duration::from_secs(5)).await;
+        peer.run_with(&transport).await;
+        if peer.evicted {
+            return Ok(());
+        }
+        sleep(config.retry_delay()).await;
     }
 }
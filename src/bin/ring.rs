@@ -0,0 +1,87 @@
+use clap::Parser;
+use std::{env, error::Error, fs, path::PathBuf, process};
+use token_ring::{launcher, log, transport};
+use tokio_util::sync::CancellationToken;
+
+/// Brings up a server and a ring of peers on this machine, with their logs interleaved.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Number of peers in the ring.
+    #[arg(index = 1, required_unless_present = "topology")]
+    number_of_peers: Option<usize>,
+
+    /// TOML file describing the nodes of the ring, instead of the number of peers.
+    #[arg(long, conflicts_with = "number_of_peers")]
+    topology: Option<String>,
+
+    #[arg(long, value_enum)]
+    transport: Option<transport::TransportKind>,
+
+    /// Config file of the nodes that don't have their own.
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Address of a running dashboard every node reports to.
+    #[arg(long)]
+    dashboard: Option<String>,
+
+    /// Directory the events of every node are recorded to, for the invariant checker.
+    #[arg(long)]
+    event_logs: Option<String>,
+
+    /// Directory of the server and peer binaries, the launcher's own by default.
+    #[arg(long)]
+    binaries: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let mut topology = match (&args.topology, args.number_of_peers) {
+        (Some(path), _) => launcher::Topology::from_toml_file(path).unwrap_or_else(|e| {
+            log::error(&e.to_string());
+            process::exit(2);
+        }),
+        (None, Some(number_of_peers)) => launcher::Topology::ring(number_of_peers),
+        (None, None) => unreachable!("clap requires one of them"),
+    };
+    if topology.peers.is_empty() {
+        log::error("The ring needs at least one peer.");
+        process::exit(2);
+    }
+    if let Some(transport) = args.transport {
+        topology.transport = transport;
+    }
+    if args.config.is_some() {
+        topology.config = args.config;
+    }
+    if args.dashboard.is_some() {
+        topology.dashboard = args.dashboard;
+    }
+    let reservations = topology.allocate()?;
+
+    if let Some(directory) = &args.event_logs {
+        fs::create_dir_all(directory)?;
+    }
+    let binaries = match args.binaries {
+        Some(directory) => directory,
+        None => env::current_exe()?
+            .parent()
+            .ok_or("The launcher isn't in a directory.")?
+            .to_path_buf(),
+    };
+    let commands = topology.commands(&binaries, args.event_logs.as_deref());
+
+    let shutdown = CancellationToken::new();
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            shutdown.cancel();
+        });
+    }
+
+    launcher::launch(&commands, reservations, shutdown).await
+}
@@ -1,29 +1,142 @@
 use clap::Parser;
-use std::{error::Error, time::Duration};
-use token_ring::{log, server};
+use std::{error::Error, process};
+use token_ring::{config, dashboard, log, message, tls, transport};
 use tokio::time::sleep;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[arg(index = 1)]
-    self_address: String,
+    self_address: Option<String>,
 
     #[arg(index = 2)]
-    number_of_peers: usize,
+    number_of_peers: Option<usize>,
+
+    /// TOML config file, its keys can be overridden with TOKEN_RING_* environment variables
+    /// and by the flags.
+    #[arg(long)]
+    config: Option<String>,
+
+    #[arg(long, value_enum)]
+    transport: Option<transport::TransportKind>,
+
+    /// Serve the admin protocol on this address, with the same transport as the ring. Without TLS
+    /// only admins connecting from the same host are served.
+    #[arg(long)]
+    admin: Option<String>,
+
+    /// Named resource with its own token besides the default one, can be repeated.
+    #[arg(long = "resource")]
+    resources: Vec<String>,
+
+    /// Operations readers may request under shared access, the read-only ones by default.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    shared_operations: Option<Vec<message::Operation>>,
+
+    /// CA certificate (PEM) every node trusts, enables mutual TLS on all links.
+    #[arg(long, requires_all = ["tls_certificate", "tls_key"])]
+    tls_ca: Option<String>,
+
+    /// Certificate (PEM) of this node, issued by the CA for its node id.
+    #[arg(long, requires = "tls_ca")]
+    tls_certificate: Option<String>,
+
+    /// Private key (PEM) of the node's certificate.
+    #[arg(long, requires = "tls_ca")]
+    tls_key: Option<String>,
+
+    /// File holding the secret the hot potato is signed with, shared by the whole ring.
+    #[arg(long)]
+    token_secret_file: Option<String>,
+
+    /// Stable id of this node, used in logs and recorded events.
+    #[arg(long)]
+    node_id: Option<String>,
+
+    /// File the node id is read from, or generated into when missing.
+    #[arg(long)]
+    node_id_file: Option<String>,
+
+    /// Record events to this file for the invariant checker.
+    #[arg(long)]
+    event_log: Option<String>,
+
+    /// Report events to the dashboard listening on this address.
+    #[arg(long)]
+    dashboard: Option<String>,
+
+    #[arg(long, value_enum)]
+    log_level: Option<log::Level>,
+}
+
+impl Args {
+    /// The config file and environment, overridden by the flags given.
+    fn into_config(self) -> Result<config::ServerConfig, config::ConfigError> {
+        let mut config = config::ServerConfig::load(self.config.as_deref())?;
+
+        config.address = self.self_address.or(config.address);
+        config.number_of_peers = self.number_of_peers.or(config.number_of_peers);
+        config.node_id = self.node_id.or(config.node_id);
+        config.node_id_file = self.node_id_file.or(config.node_id_file);
+        config.transport = self.transport.unwrap_or(config.transport);
+        config.admin_address = self.admin.or(config.admin_address);
+        if !self.resources.is_empty() {
+            config.resources = self.resources;
+        }
+        config.shared_operations = self.shared_operations.or(config.shared_operations);
+        if let Some(((ca, certificate), key)) =
+            self.tls_ca.zip(self.tls_certificate).zip(self.tls_key)
+        {
+            config.tls = Some(tls::TlsConfig::new(ca, certificate, key));
+        }
+        config.auth.token_secret_file = self.token_secret_file.or(config.auth.token_secret_file);
+        config.log.event_log = self.event_log.or(config.log.event_log);
+        config.log.dashboard = self.dashboard.or(config.log.dashboard);
+        config.log.level = self.log_level.unwrap_or(config.log.level);
+
+        config.validate()?;
+        Ok(config)
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let args = Args::parse();
-    log::clear();
+    let config = match Args::parse().into_config() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error(&e.to_string());
+            process::exit(2);
+        }
+    };
+    config.log.apply();
 
-    let server = server::Server::new(args.self_address, args.number_of_peers);
+    match (config.transport, &config.tls) {
+        (transport::TransportKind::Tcp, None) => run(transport::Tcp, &config).await,
+        (transport::TransportKind::Unix, None) => run(transport::Unix, &config).await,
+        (transport::TransportKind::Tcp, Some(tls)) => {
+            run(tls::Tls::new(transport::Tcp, tls)?, &config).await
+        }
+        (transport::TransportKind::Unix, Some(tls)) => {
+            run(tls::Tls::new(transport::Unix, tls)?, &config).await
+        }
+    }
+}
+
+/// Runs the server over `transport`, again whenever it stops.
+async fn run<T: transport::Transport>(
+    transport: T,
+    config: &config::ServerConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let transport = transport::Limited::new(transport, config.codec.max_line_length);
+    let mut server = config.to_server()?;
+    if let Some(address) = &config.log.dashboard {
+        dashboard::report_to(&transport, address, &server.node_id, &mut server.recorder);
+    }
 
     loop {
-        sleep(Duration::from_secs(server.number_of_peers as u64)).await;
-        if let Err(e) = server.run().await {
+        if let Err(e) = server.run_with(&transport).await {
             eprintln!("{e}");
         }
+        sleep(config.restart_delay()).await;
     }
 }
@@ -0,0 +1,151 @@
+use clap::Parser;
+use color_print::cformat;
+use std::{error::Error, fs, time::Duration};
+use token_ring::{checker, log, peer, policy, simulation, workload};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(index = 1)]
+    number_of_peers: usize,
+
+    /// Master seed every random choice of the run is derived from.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Simulated time (in seconds).
+    #[arg(long, default_value_t = 60.)]
+    duration: f64,
+
+    /// Latency (in milliseconds) of throwing the hot potato to the next peer.
+    #[arg(long, default_value_t = 1.)]
+    hop_latency: f64,
+
+    /// One-way latency (in milliseconds) between a peer and the server.
+    #[arg(long, default_value_t = 1.)]
+    server_latency: f64,
+
+    #[arg(long)]
+    workload: Option<String>,
+
+    #[arg(long)]
+    max_operations: Option<usize>,
+
+    #[arg(long)]
+    max_hold_time: Option<u64>,
+
+    #[arg(long)]
+    pipeline_timeout: Option<u64>,
+
+    #[arg(long)]
+    batch: bool,
+
+    #[arg(long)]
+    atomic: bool,
+
+    /// Write the recorded events to this file.
+    #[arg(long)]
+    output: Option<String>,
+
+    /// Re-run the seed of a recorded report and check that it reproduces the same events.
+    #[arg(long)]
+    replay: Option<String>,
+
+    /// Check the safety and liveness invariants on the recorded events.
+    #[arg(long)]
+    check: bool,
+}
+
+/// A duration given in seconds on the command line, which may not be negative.
+fn seconds(flag: &str, seconds: f64) -> Result<Duration, Box<dyn Error + Send + Sync>> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|e| format!("Invalid {flag} {seconds}: {e}.").into())
+}
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let recorded = match &args.replay {
+        Some(path) => Some(simulation::SimulationReport::from_json_string(
+            &fs::read_to_string(path)?,
+        )?),
+        None => None,
+    };
+
+    let config = simulation::SimulationConfig {
+        number_of_peers: args.number_of_peers,
+        seed: recorded.as_ref().map_or(args.seed, |report| report.seed),
+        duration: seconds("--duration", args.duration)?,
+        hop_latency: seconds("--hop-latency", args.hop_latency / 1000.)?,
+        server_latency: seconds("--server-latency", args.server_latency / 1000.)?,
+        workload: match &args.workload {
+            Some(path) => workload::Workload::from_json_file(path)?,
+            None => workload::Workload::default(),
+        },
+        hold_policy: policy::HoldPolicy::new(
+            args.max_operations,
+            args.max_hold_time.map(Duration::from_millis),
+            Default::default(),
+        ),
+        request_mode: match args.pipeline_timeout {
+            Some(timeout) => peer::RequestMode::Pipelined {
+                timeout: Duration::from_millis(timeout),
+            },
+            None => peer::RequestMode::FireAndForget,
+        },
+        batching: match (args.batch, args.atomic) {
+            (_, true) => peer::Batching::AllOrNothing,
+            (true, false) => peer::Batching::PartialFailure,
+            (false, false) => peer::Batching::Disabled,
+        },
+    };
+
+    // the nodes' own logs would drown the summary
+    log::set_level(log::Level::Warning);
+    let report = simulation::Simulation::new(config)?.run()?;
+    log::set_level(log::Level::Debug);
+
+    log::info(&cformat!(
+        "Simulated <bold>{}</bold> rotations of the <yellow, bold>hot potato</yellow, bold> with seed <bold>{}</bold>.",
+        report.rotations,
+        report.seed
+    ));
+    for peer in 0..args.number_of_peers {
+        let (ok, err) =
+            report
+                .responses(peer)
+                .fold((0, 0), |(ok, err), response| match response.is_err() {
+                    true => (ok, err + 1),
+                    false => (ok + 1, err),
+                });
+        log::info(&cformat!(
+            "Peer <bold>{peer}</bold> received <bold>{ok}</bold> results and <bold>{err}</bold> errors."
+        ));
+    }
+
+    if args.check {
+        match checker::Checker::default().check(&report.events) {
+            Ok(summary) => log::info(&cformat!(
+                "No violations in <bold>{}</bold> critical sections (at most <bold>{}</bold> bypasses).",
+                summary.critical_sections,
+                summary.max_bypasses
+            )),
+            Err(violation) => log::error(&format!("{violation}")),
+        }
+    }
+
+    let report_string = report.to_json_string()?;
+
+    if let Some(recorded) = recorded {
+        match recorded.to_json_string()? == report_string {
+            true => log::info("The replay reproduced the recorded events."),
+            false => log::error("The replay diverged from the recorded events."),
+        }
+    }
+
+    if let Some(path) = args.output {
+        fs::write(path, report_string)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use clap::Parser;
+use std::{error::Error, fs, process::ExitCode, time::Duration};
+use token_ring::{
+    log,
+    message::{Access, Priority, ServerRequest, Submission},
+    submission, tls, transport,
+};
+use tokio::time::timeout;
+
+/// Submits operations to a running peer (see the peer's --submissions) and prints the responses
+/// once the peer held the hot potato and the server executed them.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Address the peer accepts submitted requests on.
+    #[arg(index = 1)]
+    address: String,
+
+    /// Operations such as "1 + 2", "7 - 3", "6 * 7" or "9 / 3", after `--` when one starts with
+    /// a minus sign.
+    #[arg(index = 2)]
+    operations: Vec<String>,
+
+    /// File of operations, one per line, `#` starts a comment.
+    #[arg(long)]
+    file: Option<String>,
+
+    /// Named resource whose token the operations wait for, the default one when omitted.
+    #[arg(long)]
+    resource: Option<String>,
+
+    /// Read-only operations, sent under a read lease shared with the other readers instead of
+    /// waiting for the hot potato.
+    #[arg(long)]
+    shared: bool,
+
+    /// High-priority operations ask for the hot potato ahead of the low-priority work of the
+    /// other peers.
+    #[arg(long, value_enum, default_value_t)]
+    priority: Priority,
+
+    /// Give up after this many seconds without the responses.
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    #[arg(long, value_enum, default_value_t)]
+    transport: transport::TransportKind,
+
+    /// CA certificate (PEM), when the ring uses mutual TLS.
+    #[arg(long, requires_all = ["tls_certificate", "tls_key"])]
+    tls_ca: Option<String>,
+
+    /// Certificate (PEM) issued by the CA to authenticate with.
+    #[arg(long, requires = "tls_ca")]
+    tls_certificate: Option<String>,
+
+    /// Private key (PEM) of the certificate.
+    #[arg(long, requires = "tls_ca")]
+    tls_key: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let mut requests = args
+        .operations
+        .iter()
+        .map(|operation| operation.parse::<ServerRequest>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(path) = &args.file {
+        requests.extend(submission::parse_operations(&fs::read_to_string(path)?)?);
+    }
+    if requests.is_empty() {
+        log::error("No operation to submit.");
+        return Ok(ExitCode::from(2));
+    }
+    let tls = args
+        .tls_ca
+        .zip(args.tls_certificate)
+        .zip(args.tls_key)
+        .map(|((ca, certificate), key)| tls::TlsConfig::new(ca, certificate, key));
+
+    let submission = Submission {
+        access: if args.shared {
+            Access::Shared
+        } else {
+            Access::Exclusive
+        },
+        priority: args.priority,
+        ..Submission::new(requests, &args.resource.unwrap_or_default())
+    };
+    let submitted = async {
+        match (args.transport, &tls) {
+            (transport::TransportKind::Tcp, None) => {
+                submission::submit(&transport::Tcp, &args.address, &submission).await
+            }
+            (transport::TransportKind::Unix, None) => {
+                submission::submit(&transport::Unix, &args.address, &submission).await
+            }
+            (transport::TransportKind::Tcp, Some(config)) => {
+                let transport = tls::Tls::new(transport::Tcp, config)?;
+                submission::submit(&transport, &args.address, &submission).await
+            }
+            (transport::TransportKind::Unix, Some(config)) => {
+                let transport = tls::Tls::new(transport::Unix, config)?;
+                submission::submit(&transport, &args.address, &submission).await
+            }
+        }
+    };
+    let responses = timeout(Duration::from_secs(args.timeout), submitted)
+        .await
+        .map_err(|_| "The peer didn't answer in time.")??;
+
+    for response in &responses {
+        response.print();
+    }
+    Ok(if responses.iter().any(|response| response.is_err()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
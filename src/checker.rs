@@ -0,0 +1,502 @@
+use crate::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc;
+
+const CONTEXT_EVENTS: usize = 8;
+
+pub type EventTx = mpsc::UnboundedSender<Event>;
+pub type EventRx = mpsc::UnboundedReceiver<Event>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EventKind {
+    TokenReceived {
+        epoch: u64,
+        sequence: u64,
+    },
+    TokenSent {
+        epoch: u64,
+        sequence: u64,
+    },
+    CriticalSectionEnter,
+    CriticalSectionExit,
+    RequestQueued,
+    /// Requests sent to the server during a visit.
+    RequestsSent(usize),
+    RequestExecuted,
+    ResponseReceived(ServerResponse),
+    /// A forged, misdirected or replayed token was dropped.
+    TokenRejected(String),
+    /// The same events for the tokens of named resources, checked independently of each other.
+    ResourceTokenReceived {
+        resource: String,
+        epoch: u64,
+        sequence: u64,
+    },
+    ResourceTokenSent {
+        resource: String,
+        epoch: u64,
+        sequence: u64,
+    },
+    ResourceCriticalSectionEnter(String),
+    ResourceCriticalSectionExit(String),
+    /// A read lease on a resource, the default one when empty, from when it's taken until its
+    /// reads are done. Readers may overlap each other but no critical section.
+    SharedSectionEnter(String),
+    SharedSectionExit(String),
+}
+
+/// Something that happened on a node, `at` is in microseconds since the Unix epoch.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub at: u64,
+    pub node: String,
+    pub kind: EventKind,
+}
+
+/// Appends the events of a node to a JSON lines file or a channel, does nothing when disabled.
+#[derive(Clone, Default)]
+pub struct EventRecorder {
+    pub node: String,
+    file: Option<Arc<Mutex<BufWriter<File>>>>,
+    channel: Option<EventTx>,
+    forward: Option<mpsc::Sender<Event>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Invariant {
+    MutualExclusion,
+    TokenUniqueness,
+    BoundedWaiting,
+}
+
+#[derive(Clone, Debug)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub message: String,
+    pub event: Box<Event>,
+    pub context: Vec<Event>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CheckSummary {
+    pub events: usize,
+    pub critical_sections: usize,
+    pub shared_sections: usize,
+    pub max_bypasses: usize,
+}
+
+/// Checks the safety and liveness of a recorded run. `max_bypasses` bounds how many critical
+/// sections of other nodes may happen while a node waits with queued requests, it defaults to
+/// the number of other nodes since a ring visits each of them once per rotation.
+#[derive(Clone, Debug, Default)]
+pub struct Checker {
+    pub max_bypasses: Option<usize>,
+}
+
+impl EventKind {
+    /// `TokenReceived` for the default resource, `ResourceTokenReceived` otherwise.
+    pub fn token_received(resource: &str, epoch: u64, sequence: u64) -> Self {
+        if resource.is_empty() {
+            Self::TokenReceived { epoch, sequence }
+        } else {
+            Self::ResourceTokenReceived {
+                resource: resource.to_string(),
+                epoch,
+                sequence,
+            }
+        }
+    }
+
+    pub fn token_sent(resource: &str, epoch: u64, sequence: u64) -> Self {
+        if resource.is_empty() {
+            Self::TokenSent { epoch, sequence }
+        } else {
+            Self::ResourceTokenSent {
+                resource: resource.to_string(),
+                epoch,
+                sequence,
+            }
+        }
+    }
+
+    pub fn critical_section_enter(resource: &str) -> Self {
+        if resource.is_empty() {
+            Self::CriticalSectionEnter
+        } else {
+            Self::ResourceCriticalSectionEnter(resource.to_string())
+        }
+    }
+
+    pub fn critical_section_exit(resource: &str) -> Self {
+        if resource.is_empty() {
+            Self::CriticalSectionExit
+        } else {
+            Self::ResourceCriticalSectionExit(resource.to_string())
+        }
+    }
+}
+
+impl Event {
+    pub fn new(at: u64, node: &str, kind: EventKind) -> Self {
+        Self {
+            at,
+            node: node.to_string(),
+            kind,
+        }
+    }
+
+    pub fn now(node: &str, kind: EventKind) -> Self {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_micros() as u64);
+
+        Self::new(at, node, kind)
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(event: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(event)?)
+    }
+
+    pub fn from_json_file(path: &str) -> Result<Vec<Self>, Box<dyn Error + Send + Sync>> {
+        fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Self::from_json_string)
+            .collect()
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>16} {:<24} {:?}", self.at, self.node, self.kind)
+    }
+}
+
+impl EventRecorder {
+    pub fn to_file(node: &str, path: &str) -> io::Result<Self> {
+        Ok(Self {
+            node: node.to_string(),
+            file: Some(Arc::new(Mutex::new(BufWriter::new(File::create(path)?)))),
+            channel: None,
+            forward: None,
+        })
+    }
+
+    pub fn to_channel(node: &str, channel: EventTx) -> Self {
+        Self {
+            node: node.to_string(),
+            file: None,
+            channel: Some(channel),
+            forward: None,
+        }
+    }
+
+    /// Also sends the events to `channel`, e.g. for the dashboard. The events that don't fit
+    /// while it's full are dropped rather than holding the node up.
+    pub fn forward_to(&mut self, channel: mpsc::Sender<Event>) {
+        self.forward = Some(channel);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some() || self.channel.is_some() || self.forward.is_some()
+    }
+
+    pub fn record(&self, kind: EventKind) {
+        if !self.is_enabled() {
+            return;
+        }
+        let event = Event::now(&self.node, kind);
+
+        if let Some(file) = &self.file {
+            if let (Ok(line), Ok(mut file)) = (event.to_json_string(), file.lock()) {
+                // flush right away so the log survives the process being killed
+                let _ = writeln!(file, "{line}").and_then(|_| file.flush());
+            }
+        }
+        if let Some(forward) = &self.forward {
+            let _ = forward.try_send(event.clone());
+        }
+        if let Some(channel) = &self.channel {
+            let _ = channel.send(event);
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?} violated: {}", self.invariant, self.message)?;
+        for event in &self.context {
+            writeln!(f, "    {event}")?;
+        }
+        write!(f, "  > {}", self.event)
+    }
+}
+
+impl Error for Violation {}
+
+impl Checker {
+    pub fn new(max_bypasses: Option<usize>) -> Self {
+        Self { max_bypasses }
+    }
+
+    /// Merges the events of every node in causal order and returns the first violation found.
+    pub fn check(&self, events: &[Event]) -> Result<CheckSummary, Violation> {
+        let events = causal_order(events);
+
+        let nodes = events
+            .iter()
+            .filter(|event| event.kind == EventKind::CriticalSectionEnter)
+            .map(|event| event.node.as_str())
+            .collect::<HashSet<_>>();
+        let max_bypasses = self.max_bypasses.unwrap_or(nodes.len().saturating_sub(1));
+
+        let mut summary = CheckSummary {
+            events: events.len(),
+            ..Default::default()
+        };
+        // by resource, the default one being ""
+        let mut in_critical_section: HashMap<&str, &str> = HashMap::new();
+        let mut readers: HashMap<&str, HashSet<&str>> = HashMap::new();
+        let mut holders: HashMap<(&str, u64), &str> = HashMap::new();
+        let mut sequences: HashMap<(&str, u64), u64> = HashMap::new();
+        let mut waiting: HashMap<&str, usize> = HashMap::new();
+
+        for (i, event) in events.iter().enumerate() {
+            let violation = |invariant, message: String| Violation {
+                invariant,
+                message,
+                event: Box::new(event.clone()),
+                context: events[i.saturating_sub(CONTEXT_EVENTS)..i].to_vec(),
+            };
+
+            let of_resource = |resource: &str| {
+                if resource.is_empty() {
+                    String::new()
+                } else {
+                    format!(" of resource {resource}")
+                }
+            };
+
+            if let EventKind::CriticalSectionEnter | EventKind::ResourceCriticalSectionEnter(_) =
+                &event.kind
+            {
+                let resource = match &event.kind {
+                    EventKind::ResourceCriticalSectionEnter(resource) => resource.as_str(),
+                    _ => "",
+                };
+                if let Some(reader) = readers.get(resource).and_then(|nodes| nodes.iter().next()) {
+                    return Err(violation(
+                        Invariant::MutualExclusion,
+                        format!(
+                            "{} entered while {reader} was reading{}",
+                            event.node,
+                            of_resource(resource)
+                        ),
+                    ));
+                }
+            }
+
+            match &event.kind {
+                EventKind::SharedSectionEnter(resource) => {
+                    if let Some(node) = in_critical_section.get(resource.as_str()) {
+                        return Err(violation(
+                            Invariant::MutualExclusion,
+                            format!(
+                                "{} started reading while {node} was in its critical section{}",
+                                event.node,
+                                of_resource(resource)
+                            ),
+                        ));
+                    }
+                    readers.entry(resource).or_default().insert(&event.node);
+                    summary.shared_sections += 1;
+                }
+                EventKind::SharedSectionExit(resource) => {
+                    if let Some(nodes) = readers.get_mut(resource.as_str()) {
+                        nodes.remove(event.node.as_str());
+                    }
+                }
+                EventKind::ResourceCriticalSectionEnter(resource) => {
+                    if let Some(node) = in_critical_section.get(resource.as_str()) {
+                        return Err(violation(
+                            Invariant::MutualExclusion,
+                            format!(
+                                "{} entered while {node} was in its critical section{}",
+                                event.node,
+                                of_resource(resource)
+                            ),
+                        ));
+                    }
+                    in_critical_section.insert(resource, &event.node);
+                    summary.critical_sections += 1;
+                }
+                EventKind::CriticalSectionEnter => {
+                    if let Some(node) = in_critical_section.get("") {
+                        return Err(violation(
+                            Invariant::MutualExclusion,
+                            format!(
+                                "{} entered while {node} was in its critical section",
+                                event.node
+                            ),
+                        ));
+                    }
+                    in_critical_section.insert("", &event.node);
+                    summary.critical_sections += 1;
+
+                    waiting.remove(event.node.as_str());
+                    for (node, bypasses) in waiting.iter_mut() {
+                        *bypasses += 1;
+                        summary.max_bypasses = summary.max_bypasses.max(*bypasses);
+
+                        if *bypasses > max_bypasses {
+                            return Err(violation(
+                                Invariant::BoundedWaiting,
+                                format!("{node} waited for more than {max_bypasses} critical sections of other nodes"),
+                            ));
+                        }
+                    }
+                }
+                EventKind::CriticalSectionExit | EventKind::ResourceCriticalSectionExit(_) => {
+                    let resource = match &event.kind {
+                        EventKind::ResourceCriticalSectionExit(resource) => resource.as_str(),
+                        _ => "",
+                    };
+                    if in_critical_section.get(resource) == Some(&event.node.as_str()) {
+                        in_critical_section.remove(resource);
+                    }
+                }
+                EventKind::TokenReceived { epoch, sequence }
+                | EventKind::ResourceTokenReceived {
+                    epoch, sequence, ..
+                } => {
+                    let resource = match &event.kind {
+                        EventKind::ResourceTokenReceived { resource, .. } => resource.as_str(),
+                        _ => "",
+                    };
+                    let token = (resource, *epoch);
+
+                    if let Some(holder) = holders.get(&token) {
+                        return Err(violation(
+                            Invariant::TokenUniqueness,
+                            format!(
+                                "{} received a token{} of epoch {epoch} still held by {holder}",
+                                event.node,
+                                of_resource(resource)
+                            ),
+                        ));
+                    }
+                    if sequences.get(&token).is_some_and(|last| sequence <= last) {
+                        return Err(violation(
+                            Invariant::TokenUniqueness,
+                            format!(
+                                "{} received the token{} of epoch {epoch} with an old sequence {sequence}",
+                                event.node,
+                                of_resource(resource)
+                            ),
+                        ));
+                    }
+                    holders.insert(token, &event.node);
+                    sequences.insert(token, *sequence);
+                }
+                EventKind::TokenSent { epoch, .. } | EventKind::ResourceTokenSent { epoch, .. } => {
+                    let resource = match &event.kind {
+                        EventKind::ResourceTokenSent { resource, .. } => resource.as_str(),
+                        _ => "",
+                    };
+                    if holders.get(&(resource, *epoch)) == Some(&event.node.as_str()) {
+                        holders.remove(&(resource, *epoch));
+                    }
+                }
+                EventKind::RequestQueued => {
+                    waiting.entry(&event.node).or_insert(0);
+                }
+                EventKind::RequestsSent(_)
+                | EventKind::RequestExecuted
+                | EventKind::ResponseReceived(_)
+                | EventKind::TokenRejected(_) => {}
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+/// The token a send or receive event is about, as its resource, epoch and sequence, and whether
+/// it was sent.
+fn thrown(kind: &EventKind) -> Option<(bool, &str, u64, u64)> {
+    match kind {
+        EventKind::TokenSent { epoch, sequence } => Some((true, "", *epoch, *sequence)),
+        EventKind::TokenReceived { epoch, sequence } => Some((false, "", *epoch, *sequence)),
+        EventKind::ResourceTokenSent {
+            resource,
+            epoch,
+            sequence,
+        } => Some((true, resource, *epoch, *sequence)),
+        EventKind::ResourceTokenReceived {
+            resource,
+            epoch,
+            sequence,
+        } => Some((false, resource, *epoch, *sequence)),
+        _ => None,
+    }
+}
+
+/// Merges the events by time, keeping each node's events in the order they were recorded and
+/// every token received after the send that threw it, whatever the clocks of the nodes say.
+fn causal_order(events: &[Event]) -> Vec<Event> {
+    let mut nodes = BTreeMap::<&str, VecDeque<&Event>>::new();
+    for event in events {
+        nodes.entry(&event.node).or_default().push_back(event);
+    }
+    let sends = events
+        .iter()
+        .filter_map(|event| thrown(&event.kind))
+        .filter(|(sent, ..)| *sent)
+        .map(|(_, resource, epoch, sequence)| (resource, epoch, sequence))
+        .collect::<HashSet<_>>();
+    let mut sent = HashSet::new();
+
+    let mut ordered = Vec::with_capacity(events.len());
+    while !nodes.is_empty() {
+        let ready = |event: &&Event| match thrown(&event.kind) {
+            Some((false, resource, epoch, sequence)) => {
+                let token = (resource, epoch, sequence);
+                !sends.contains(&token) || sent.contains(&token)
+            }
+            _ => true,
+        };
+        let heads = nodes.iter().map(|(node, queue)| (*node, queue[0]));
+        // a trace contradicting itself is merged by time alone
+        let next = heads
+            .clone()
+            .filter(|(_, event)| ready(event))
+            .min_by_key(|(_, event)| event.at)
+            .or_else(|| heads.min_by_key(|(_, event)| event.at))
+            .map(|(node, _)| node)
+            .expect("Nodes with events left.");
+
+        let queue = nodes.get_mut(next).expect("The node was just picked.");
+        let event = queue.pop_front().expect("Queues are never empty.");
+        if queue.is_empty() {
+            nodes.remove(next);
+        }
+        if let Some((true, resource, epoch, sequence)) = thrown(&event.kind) {
+            sent.insert((resource, epoch, sequence));
+        }
+        ordered.push(event.clone());
+    }
+    ordered
+}
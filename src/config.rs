@@ -0,0 +1,519 @@
+use crate::peer::{Batching, Peer, RequestMode};
+use crate::server::Server;
+use crate::tls::TlsConfig;
+use crate::*;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::HashMap, env, error::Error, fmt, fs, path::Path, time::Duration};
+use toml::{Table, Value};
+
+/// Environment variables starting with the prefix override the keys of the config file, `__`
+/// separates nested keys, e.g. `TOKEN_RING_TLS__CA`. Values are read as TOML and fall back to
+/// plain strings.
+pub const ENV_PREFIX: &str = "TOKEN_RING_";
+
+/// A config that couldn't be loaded, `key` is the dotted path of the offending key.
+#[derive(Clone, Debug)]
+pub struct ConfigError {
+    pub key: String,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    /// The hot potato circulates around the ring.
+    #[default]
+    Ring,
+    /// The hot potato rests at its last holder while no peer has work, the peers with work
+    /// send a demand to pull it.
+    OnDemand,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodecConfig {
+    pub max_line_length: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: log::Level,
+    /// Clear the terminal on start.
+    pub clear: bool,
+    /// Record events to this file for the invariant checker.
+    pub event_log: Option<String>,
+    /// Address of the dashboard the events are reported to.
+    pub dashboard: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// File holding the secret the hot potato is signed with.
+    pub token_secret_file: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeerConfig {
+    pub address: Option<String>,
+    pub server_address: Option<String>,
+    pub next_peer_address: Option<String>,
+    /// Address clients submit requests on, with the same transport as the ring.
+    pub submission_address: Option<String>,
+    pub node_id: Option<String>,
+    pub node_id_file: Option<String>,
+    pub transport: TransportKind,
+    pub algorithm: Algorithm,
+    /// Time to wait before rejoining the ring after losing it.
+    pub retry_delay_ms: u64,
+    pub max_operations: Option<usize>,
+    pub max_hold_time_ms: Option<u64>,
+    pub weights: HashMap<NodeId, usize>,
+    /// Visits in a row low-priority work may let the hot potato go to high-priority work.
+    pub max_deferrals: usize,
+    /// Time each hop of a hot potato nobody asked for takes at least.
+    pub min_hop_delay_ms: u64,
+    /// Bound of the hop delay, which doubles with every rotation that carried no work.
+    pub max_idle_delay_ms: u64,
+    pub pipeline_timeout_ms: Option<u64>,
+    pub batching: Batching,
+    pub workload: Option<Workload>,
+    pub workload_file: Option<String>,
+    pub tls: Option<TlsConfig>,
+    pub auth: AuthConfig,
+    pub log: LogConfig,
+    pub codec: CodecConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: Option<String>,
+    pub number_of_peers: Option<usize>,
+    pub node_id: Option<String>,
+    pub node_id_file: Option<String>,
+    pub transport: TransportKind,
+    /// Time to wait before restarting, one second per peer by default.
+    pub restart_delay_ms: Option<u64>,
+    /// Address the admin protocol is served on, with the same transport as the ring. Without TLS
+    /// only admins connecting from the same host are served.
+    pub admin_address: Option<String>,
+    /// Named resources, each with its own token besides the default one.
+    pub resources: Vec<String>,
+    /// Operations readers may request under shared access, the read-only ones when unset.
+    pub shared_operations: Option<Vec<Operation>>,
+    pub tls: Option<TlsConfig>,
+    pub auth: AuthConfig,
+    pub log: LogConfig,
+    pub codec: CodecConfig,
+}
+
+impl ConfigError {
+    pub fn new(key: &str, message: String) -> Self {
+        Self {
+            key: key.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "Invalid config: {}", self.message)
+        } else {
+            write!(f, "Invalid config key `{}`: {}", self.key, self.message)
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        Self {
+            max_line_length: transport::DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+}
+
+impl CodecConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_line_length == 0 {
+            return Err(ConfigError::new(
+                "codec.max_line_length",
+                "must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: log::Level::default(),
+            clear: true,
+            event_log: None,
+            dashboard: None,
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn apply(&self) {
+        log::set_level(self.level);
+        if self.clear {
+            log::clear();
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn token_key(&self) -> Result<Option<TokenKey>, Box<dyn Error + Send + Sync>> {
+        self.token_secret_file
+            .as_deref()
+            .map(TokenKey::from_file)
+            .transpose()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        file_exists("auth.token_secret_file", self.token_secret_file.as_deref())
+    }
+}
+
+impl Default for PeerConfig {
+    fn default() -> Self {
+        Self {
+            address: None,
+            server_address: None,
+            next_peer_address: None,
+            submission_address: None,
+            node_id: None,
+            node_id_file: None,
+            transport: TransportKind::default(),
+            algorithm: Algorithm::default(),
+            retry_delay_ms: 5000,
+            max_operations: None,
+            max_hold_time_ms: None,
+            weights: HashMap::new(),
+            max_deferrals: PriorityPolicy::default().max_deferrals,
+            min_hop_delay_ms: 0,
+            max_idle_delay_ms: 0,
+            pipeline_timeout_ms: None,
+            batching: Batching::default(),
+            workload: None,
+            workload_file: None,
+            tls: None,
+            auth: AuthConfig::default(),
+            log: LogConfig::default(),
+            codec: CodecConfig::default(),
+        }
+    }
+}
+
+impl PeerConfig {
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        load(path)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        required("address", &self.address)?;
+        required("server_address", &self.server_address)?;
+        required("next_peer_address", &self.next_peer_address)?;
+        node_id("node_id", self.node_id.as_deref())?;
+
+        if self.max_operations == Some(0) {
+            return Err(ConfigError::new(
+                "max_operations",
+                "must be at least 1".to_string(),
+            ));
+        }
+        if self.pipeline_timeout_ms == Some(0) {
+            return Err(ConfigError::new(
+                "pipeline_timeout_ms",
+                "must be positive".to_string(),
+            ));
+        }
+        if self.max_idle_delay_ms != 0 && self.max_idle_delay_ms < self.min_hop_delay_ms {
+            return Err(ConfigError::new(
+                "max_idle_delay_ms",
+                "must be at least min_hop_delay_ms".to_string(),
+            ));
+        }
+        for id in self.weights.keys() {
+            node_id(&format!("weights.{id}"), Some(id.as_str()))?;
+        }
+        if let Some((node_id, _)) = self.weights.iter().find(|(_, weight)| **weight == 0) {
+            return Err(ConfigError::new(
+                &format!("weights.{node_id}"),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if !self.weights.is_empty() && self.max_operations.is_none() {
+            return Err(ConfigError::new(
+                "weights",
+                "only apply with max_operations set".to_string(),
+            ));
+        }
+
+        if let Some(workload) = &self.workload {
+            if self.workload_file.is_some() {
+                return Err(ConfigError::new(
+                    "workload_file",
+                    "can't be combined with a [workload] table".to_string(),
+                ));
+            }
+            workload
+                .validate()
+                .map_err(|e| ConfigError::new("workload", e.to_string()))?;
+        }
+        file_exists("workload_file", self.workload_file.as_deref())?;
+        if let Some(path) = &self.workload_file {
+            Workload::from_json_file(path)
+                .and_then(|workload| workload.validate())
+                .map_err(|e| ConfigError::new("workload_file", format!("{path}: {e}")))?;
+        }
+
+        validate_tls(&self.tls)?;
+        self.auth.validate()?;
+        self.codec.validate()
+    }
+
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_millis(self.retry_delay_ms)
+    }
+
+    /// The peer the config describes, which must be valid.
+    pub fn to_peer(&self) -> Result<Peer, Box<dyn Error + Send + Sync>> {
+        let mut peer = Peer::new(
+            self.address.clone().unwrap_or_default(),
+            self.server_address.clone().unwrap_or_default(),
+            self.next_peer_address.clone().unwrap_or_default(),
+        );
+        peer.node_id = NodeId::resolve(self.node_id.clone(), self.node_id_file.as_deref())?;
+        peer.submission_address = self.submission_address.clone();
+
+        peer.hold_policy = HoldPolicy::new(
+            self.max_operations,
+            self.max_hold_time_ms.map(Duration::from_millis),
+            self.weights.clone(),
+        );
+        peer.priority_policy = PriorityPolicy::new(self.max_deferrals);
+        peer.pacing_policy = PacingPolicy::new(
+            Duration::from_millis(self.min_hop_delay_ms),
+            Duration::from_millis(self.max_idle_delay_ms),
+        );
+        peer.algorithm = self.algorithm;
+        if let Some(timeout) = self.pipeline_timeout_ms {
+            peer.request_mode = RequestMode::Pipelined {
+                timeout: Duration::from_millis(timeout),
+            };
+        }
+        peer.batching = self.batching;
+
+        peer.workload = match (&self.workload, &self.workload_file) {
+            (Some(workload), _) => workload.clone(),
+            (None, Some(path)) => Workload::from_json_file(path)?,
+            (None, None) => Workload::default(),
+        };
+        peer.token_guard = TokenGuard::new(self.auth.token_key()?);
+        if let Some(path) = &self.log.event_log {
+            peer.recorder = EventRecorder::to_file(peer.node_id.as_str(), path)?;
+        }
+
+        Ok(peer)
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        load(path)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        required("address", &self.address)?;
+        if required("number_of_peers", &self.number_of_peers)? == &0 {
+            return Err(ConfigError::new(
+                "number_of_peers",
+                "must be at least 1".to_string(),
+            ));
+        }
+        node_id("node_id", self.node_id.as_deref())?;
+        for (i, resource) in self.resources.iter().enumerate() {
+            if resource.is_empty() || resource.contains(char::is_whitespace) {
+                return Err(ConfigError::new(
+                    "resources",
+                    format!("`{resource}` isn't a valid resource name"),
+                ));
+            }
+            if self.resources[..i].contains(resource) {
+                return Err(ConfigError::new(
+                    "resources",
+                    format!("`{resource}` is listed twice"),
+                ));
+            }
+        }
+        if self
+            .shared_operations
+            .as_ref()
+            .is_some_and(|operations| operations.is_empty())
+        {
+            return Err(ConfigError::new(
+                "shared_operations",
+                "must list at least one operation".to_string(),
+            ));
+        }
+
+        validate_tls(&self.tls)?;
+        self.auth.validate()?;
+        self.codec.validate()
+    }
+
+    pub fn restart_delay(&self) -> Duration {
+        self.restart_delay_ms.map_or_else(
+            || Duration::from_secs(self.number_of_peers.unwrap_or_default() as u64),
+            Duration::from_millis,
+        )
+    }
+
+    /// The server the config describes, which must be valid.
+    pub fn to_server(&self) -> Result<Server, Box<dyn Error + Send + Sync>> {
+        let mut server = Server::new(
+            self.address.clone().unwrap_or_default(),
+            self.number_of_peers.unwrap_or_default(),
+        );
+        server.node_id = NodeId::resolve(self.node_id.clone(), self.node_id_file.as_deref())?;
+        server.token_key = self.auth.token_key()?;
+        server.admin_address = self.admin_address.clone();
+        server.resources = self.resources.clone();
+        if let Some(shared_operations) = &self.shared_operations {
+            server.shared_operations = shared_operations.iter().copied().collect();
+        }
+        if let Some(path) = &self.log.event_log {
+            server.recorder = EventRecorder::to_file(server.node_id.as_str(), path)?;
+        }
+
+        Ok(server)
+    }
+}
+
+/// Reads the config file at `path`, if any, with the overrides of the process' environment.
+pub fn load<T: DeserializeOwned>(path: Option<&str>) -> Result<T, ConfigError> {
+    let text = match path {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| ConfigError::new("", format!("couldn't read {path}: {e}")))?,
+        None => String::new(),
+    };
+
+    parse(&text, env::vars())
+}
+
+/// Parses a TOML config and applies the overrides found in `vars` (see [`ENV_PREFIX`]).
+pub fn parse<T: DeserializeOwned>(
+    text: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<T, ConfigError> {
+    let mut table = text
+        .parse::<Table>()
+        .map_err(|e| ConfigError::new("", e.to_string()))?;
+
+    let mut overridden = HashMap::new();
+    for (var, value) in vars {
+        let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let path = name
+            .to_lowercase()
+            .split("__")
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let key = path.join(".");
+
+        override_key(&mut table, &path, env_value(&value))
+            .map_err(|message| ConfigError::new(&key, format!("{message} (set by {var})")))?;
+        overridden.insert(key, var);
+    }
+
+    serde_path_to_error::deserialize(Value::Table(table)).map_err(|e| {
+        let key = match e.path().to_string() {
+            key if key == "." => String::new(),
+            key => key,
+        };
+        let message = e.inner().to_string().trim_end().to_string();
+        let message = match overridden.get(&key) {
+            Some(var) => format!("{message} (set by {var})"),
+            None => message,
+        };
+
+        ConfigError::new(&key, message)
+    })
+}
+
+fn override_key(table: &mut Table, path: &[String], value: Value) -> Result<(), String> {
+    let (key, parents) = path.split_last().ok_or("empty key")?;
+
+    let mut table = table;
+    for parent in parents {
+        table = match table
+            .entry(parent.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(table) => table,
+            _ => return Err(format!("`{parent}` isn't a table")),
+        };
+    }
+    table.insert(key.clone(), value);
+
+    Ok(())
+}
+
+fn env_value(raw: &str) -> Value {
+    format!("value = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn required<'a, T>(key: &str, value: &'a Option<T>) -> Result<&'a T, ConfigError> {
+    value.as_ref().ok_or_else(|| {
+        ConfigError::new(
+            key,
+            format!(
+                "is required, set it in the config file, as {ENV_PREFIX}{} or on the command line",
+                key.to_uppercase()
+            ),
+        )
+    })
+}
+
+fn node_id(key: &str, node_id: Option<&str>) -> Result<(), ConfigError> {
+    match node_id {
+        Some(id) if id.is_empty() || id.contains(char::is_whitespace) => Err(ConfigError::new(
+            key,
+            "must be non-empty and without whitespace".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn file_exists(key: &str, path: Option<&str>) -> Result<(), ConfigError> {
+    match path {
+        Some(path) if !Path::new(path).is_file() => {
+            Err(ConfigError::new(key, format!("{path} doesn't exist")))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn validate_tls(tls: &Option<TlsConfig>) -> Result<(), ConfigError> {
+    if let Some(tls) = tls {
+        file_exists("tls.ca", Some(&tls.ca))?;
+        file_exists("tls.certificate", Some(&tls.certificate))?;
+        file_exists("tls.key", Some(&tls.key))?;
+    }
+    Ok(())
+}
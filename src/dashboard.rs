@@ -0,0 +1,332 @@
+use crate::*;
+use color_print::cformat;
+use futures::{SinkExt, StreamExt};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::Write,
+    io,
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::sleep};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Events waiting to be sent to the dashboard, more are dropped until it catches up.
+const REPORT_BUFFER: usize = 1024;
+
+/// Window the rotation rate is measured over, in microseconds.
+const RATE_WINDOW: u64 = 5_000_000;
+
+const RECENT_ERRORS: usize = 5;
+const RECENT_OPERATIONS: usize = 64;
+
+/// What the dashboard knows about a node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeStatus {
+    pub queue_length: usize,
+    pub visits: usize,
+    pub requests_executed: usize,
+    pub responses: usize,
+    pub errors: usize,
+}
+
+/// State of the ring rebuilt from the events the nodes report.
+#[derive(Clone, Debug, Default)]
+pub struct Dashboard {
+    pub nodes: BTreeMap<String, NodeStatus>,
+    /// Node holding the hot potato, `None` while it is thrown.
+    pub holder: Option<String>,
+    /// Next peer of each peer, learned from consecutive throws.
+    pub next_peers: HashMap<String, String>,
+    pub recent_operations: VecDeque<(String, ServerResponse)>,
+    pub recent_errors: VecDeque<(String, String)>,
+    last_received: Option<(u64, u64, String)>,
+    throws: VecDeque<u64>,
+    last_event_at: u64,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(&mut self, event: &Event) {
+        self.last_event_at = self.last_event_at.max(event.at);
+        let node = self.nodes.entry(event.node.clone()).or_default();
+
+        match &event.kind {
+            EventKind::TokenReceived { epoch, sequence } => {
+                if let Some((last_epoch, last_sequence, previous)) = &self.last_received {
+                    if last_epoch == epoch
+                        && last_sequence + 1 == *sequence
+                        && *previous != event.node
+                    {
+                        self.next_peers.insert(previous.clone(), event.node.clone());
+                    }
+                }
+                self.last_received = Some((*epoch, *sequence, event.node.clone()));
+                self.holder = Some(event.node.clone());
+            }
+            EventKind::TokenSent { .. } => {
+                if self.holder.as_ref() == Some(&event.node) {
+                    self.holder = None;
+                }
+                self.throws.push_back(event.at);
+            }
+            EventKind::CriticalSectionEnter | EventKind::ResourceCriticalSectionEnter(_) => {
+                node.visits += 1
+            }
+            // the ring is learned from the default token only
+            EventKind::CriticalSectionExit
+            | EventKind::ResourceCriticalSectionExit(_)
+            | EventKind::ResourceTokenReceived { .. }
+            | EventKind::ResourceTokenSent { .. }
+            | EventKind::SharedSectionEnter(_)
+            | EventKind::SharedSectionExit(_) => {}
+            EventKind::RequestQueued => node.queue_length += 1,
+            EventKind::RequestsSent(requests) => {
+                node.queue_length = node.queue_length.saturating_sub(*requests)
+            }
+            EventKind::RequestExecuted => node.requests_executed += 1,
+            EventKind::ResponseReceived(response) => {
+                node.responses += 1;
+                if let ServerResponse::Err(_, _, message) = response {
+                    node.errors += 1;
+                    push_bounded(
+                        &mut self.recent_errors,
+                        (event.node.clone(), message.clone()),
+                        RECENT_ERRORS,
+                    );
+                }
+                push_bounded(
+                    &mut self.recent_operations,
+                    (event.node.clone(), response.clone()),
+                    RECENT_OPERATIONS,
+                );
+            }
+            EventKind::TokenRejected(reason) => {
+                node.errors += 1;
+                push_bounded(
+                    &mut self.recent_errors,
+                    (
+                        event.node.clone(),
+                        format!("Rejected a hot potato: {reason}."),
+                    ),
+                    RECENT_ERRORS,
+                );
+            }
+        }
+
+        while self
+            .throws
+            .front()
+            .is_some_and(|at| *at + RATE_WINDOW < self.last_event_at)
+        {
+            self.throws.pop_front();
+        }
+    }
+
+    /// The peers in the ring's order, as far as it is known.
+    pub fn ring(&self) -> Vec<String> {
+        let peers = self
+            .nodes
+            .iter()
+            .filter(|(_, status)| status.visits > 0)
+            .map(|(node, _)| node.clone())
+            .chain(self.next_peers.keys().cloned())
+            .collect::<HashSet<_>>();
+        let Some(first) = peers.iter().min() else {
+            return Vec::new();
+        };
+
+        let mut ring = vec![first.clone()];
+        while let Some(next) = self.next_peers.get(ring.last().unwrap_or(first)) {
+            if ring.contains(next) {
+                break;
+            }
+            ring.push(next.clone());
+        }
+
+        // peers whose place isn't known yet go last
+        let mut unplaced = peers
+            .into_iter()
+            .filter(|peer| !ring.contains(peer))
+            .collect::<Vec<_>>();
+        unplaced.sort();
+        ring.extend(unplaced);
+        ring
+    }
+
+    /// Rotations of the hot potato per second over the last few seconds.
+    pub fn rotation_rate(&self) -> f64 {
+        let peers = self.ring().len();
+        if peers == 0 {
+            return 0.;
+        }
+
+        self.throws.len() as f64 / peers as f64 / (RATE_WINDOW as f64 / 1_000_000.)
+    }
+
+    /// The dashboard as ANSI text fitting `height` lines.
+    pub fn render(&self, height: usize) -> String {
+        let ring = self.ring();
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "{}",
+            cformat!(
+                "<bold>Token ring</bold>  <bold>{}</bold> peers, <bold>{:.2}</bold> rotations/s",
+                ring.len(),
+                self.rotation_rate()
+            )
+        );
+        let _ = writeln!(out);
+
+        let diagram = ring
+            .iter()
+            .map(|peer| match &self.holder {
+                Some(holder) if holder == peer => {
+                    cformat!("<yellow, bold>[{}]</yellow, bold>", peer)
+                }
+                _ => format!(" {peer} "),
+            })
+            .collect::<Vec<_>>();
+        let back = ring
+            .first()
+            .map_or(String::new(), |first| format!(" -> ({first})"));
+        let _ = writeln!(out, "  {}{back}", diagram.join(" -> "));
+        let _ = writeln!(out);
+
+        let width = self.nodes.keys().map(String::len).max().unwrap_or(4).max(4);
+        let _ = writeln!(
+            out,
+            "{}",
+            cformat!(
+                "<bold>  {:<width$}  {:>6}  {:>8}  {:>9}  {:>9}  {:>6}</bold>",
+                "node",
+                "queue",
+                "visits",
+                "executed",
+                "responses",
+                "errors"
+            )
+        );
+        for (node, status) in &self.nodes {
+            let _ = writeln!(
+                out,
+                "  {node:<width$}  {:>6}  {:>8}  {:>9}  {:>9}  {:>6}",
+                status.queue_length,
+                status.visits,
+                status.requests_executed,
+                status.responses,
+                status.errors
+            );
+        }
+        let _ = writeln!(out);
+
+        let _ = writeln!(out, "{}", cformat!("<bold>Errors</bold>"));
+        for (node, message) in &self.recent_errors {
+            let _ = writeln!(
+                out,
+                "  {node:<width$}  {}",
+                cformat!("<red>{}</red>", message)
+            );
+        }
+        let _ = writeln!(out);
+
+        let used = out.lines().count() + 1;
+        let _ = writeln!(out, "{}", cformat!("<bold>Recent operations</bold>"));
+        let shown = height
+            .saturating_sub(used + 1)
+            .min(self.recent_operations.len());
+        for (node, response) in self.recent_operations.iter().rev().take(shown) {
+            let _ = writeln!(out, "  {node:<width$}  {}", describe(response));
+        }
+
+        out
+    }
+}
+
+/// Sends what `recorder` records to the dashboard listening on `address` as well, over
+/// `transport`. The events recorded while it can't be reached or keep up are dropped.
+pub fn report_to<T: Transport>(
+    transport: &T,
+    address: &str,
+    node_id: &NodeId,
+    recorder: &mut EventRecorder,
+) {
+    let (event_tx, mut event_rx) = mpsc::channel::<Event>(REPORT_BUFFER);
+    let transport = transport.clone();
+    let address = address.to_string();
+
+    recorder.node = node_id.to_string();
+    recorder.forward_to(event_tx);
+
+    tokio::spawn(async move {
+        loop {
+            match transport.connect(&address).await {
+                Ok(mut lines) => loop {
+                    let Some(event) = event_rx.recv().await else {
+                        return;
+                    };
+                    let Ok(line) = event.to_json_string() else {
+                        continue;
+                    };
+                    if lines.send(line).await.is_err() {
+                        break;
+                    }
+                },
+                Err(_) => while event_rx.try_recv().is_ok() {},
+            }
+            if event_rx.is_closed() {
+                return;
+            }
+            sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+/// Accepts the nodes reporting to the dashboard and passes their events on, the same nodes as
+/// [`admin::admits`] are accepted.
+pub async fn receive<L: Listener>(mut listener: L, events: EventTx) -> io::Result<()> {
+    let authenticated = listener.authenticates();
+    loop {
+        let (mut lines, address) = listener.accept().await?;
+        if !admin::admits(&address, authenticated) {
+            log::warning(&cformat!(
+                "Refused reports from <bold>{}</bold>, only local nodes are allowed without TLS.",
+                address
+            ));
+            continue;
+        }
+        let events = events.clone();
+
+        tokio::spawn(async move {
+            while let Some(Ok(line)) = lines.next().await {
+                if let Ok(event) = Event::from_json_string(&line) {
+                    if events.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn describe(response: &ServerResponse) -> String {
+    match response {
+        ServerResponse::Add(a, b, result) => format!("{a} + {b} = {result}"),
+        ServerResponse::Sub(a, b, result) => format!("{a} - {b} = {result}"),
+        ServerResponse::Mul(a, b, result) => format!("{a} * {b} = {result}"),
+        ServerResponse::Div(a, b, result) => format!("{a} / {b} = {result}"),
+        ServerResponse::Err(_, _, message) => cformat!("<red>{}</red>", message),
+    }
+}
+
+fn push_bounded<T>(items: &mut VecDeque<T>, item: T, capacity: usize) {
+    if items.len() == capacity {
+        items.pop_front();
+    }
+    items.push_back(item);
+}
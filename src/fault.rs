@@ -0,0 +1,273 @@
+use crate::*;
+use futures::{SinkExt, StreamExt};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rustls::pki_types::CertificateDer;
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{duplex, AsyncRead, AsyncWrite, DuplexStream, ReadBuf},
+    time::sleep,
+};
+use tokio_util::sync::CancellationToken;
+
+const FAULTY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Faults injected on a link, probabilities are per message and per direction.
+#[derive(Clone, Debug, Default)]
+pub struct FaultConfig {
+    pub drop: f64,
+    pub duplicate: f64,
+    pub reorder: f64,
+    pub corrupt: f64,
+    pub delay: Option<(Duration, Duration)>,
+    pub sever: f64,
+    pub sever_after: Option<Duration>,
+    pub sever_after_messages: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct FaultStats {
+    pub dropped: AtomicUsize,
+    pub duplicated: AtomicUsize,
+    pub reordered: AtomicUsize,
+    pub corrupted: AtomicUsize,
+    pub delayed: AtomicUsize,
+    pub severed: AtomicUsize,
+}
+
+/// Wraps a transport and injects faults on the messages of every link. Faults are injected by
+/// the connecting end, so a link is identified by the address it connects to and links without
+/// their own config use `default`.
+#[derive(Clone)]
+pub struct Faulty<T: Transport> {
+    pub inner: T,
+    pub default: FaultConfig,
+    pub links: Arc<HashMap<String, FaultConfig>>,
+    pub stats: Arc<FaultStats>,
+    seed: u64,
+    connections: Arc<AtomicU64>,
+}
+
+/// The proxy end of a faulty link, authenticated like the link it stands for.
+struct Proxied {
+    stream: DuplexStream,
+    certificate: Option<CertificateDer<'static>>,
+}
+
+impl<T: Transport> Faulty<T> {
+    pub fn new(inner: T, default: FaultConfig, seed: u64) -> Self {
+        Self {
+            inner,
+            default,
+            links: Arc::new(HashMap::new()),
+            stats: Arc::new(FaultStats::default()),
+            seed,
+            connections: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn with_link(mut self, address: &str, config: FaultConfig) -> Self {
+        Arc::make_mut(&mut self.links).insert(address.to_string(), config);
+        self
+    }
+
+    fn config_for(&self, address: &str) -> FaultConfig {
+        self.links
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// Puts a proxy between `lines` and the returned connection that mangles the messages
+    /// flowing in both directions.
+    fn inject(&self, lines: Connection, address: &str) -> Connection {
+        let config = self.config_for(address);
+        let connection_number = self.connections.fetch_add(1, Ordering::Relaxed);
+        let (local, remote) = duplex(FAULTY_BUFFER_SIZE);
+        let severed = CancellationToken::new();
+        let certificate = lines.get_ref().peer_certificate();
+
+        let (inner_writer, inner_reader) = lines.split::<String>();
+        let (proxy_writer, proxy_reader) = connection(remote).split::<String>();
+
+        for (direction, reader, writer) in [
+            (0, inner_reader, proxy_writer),
+            (1, proxy_reader, inner_writer),
+        ] {
+            let config = config.clone();
+            let stats = self.stats.clone();
+            let severed = severed.clone();
+            let rng = SmallRng::seed_from_u64(
+                self.seed ^ (connection_number << 1 | direction).wrapping_mul(0x9E3779B97F4A7C15),
+            );
+
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = Self::pump(reader, writer, config, rng, stats, severed.clone()) => {}
+                    _ = severed.cancelled() => {}
+                }
+                severed.cancel();
+            });
+        }
+
+        // severed in time even if no message comes along
+        if let Some(after) = config.sever_after {
+            let stats = self.stats.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = sleep(after) => {
+                        stats.severed.fetch_add(1, Ordering::Relaxed);
+                        severed.cancel();
+                    }
+                    _ = severed.cancelled() => {}
+                }
+            });
+        }
+
+        connection(Proxied {
+            stream: local,
+            certificate,
+        })
+    }
+
+    async fn pump(
+        mut reader: futures::stream::SplitStream<Connection>,
+        mut writer: futures::stream::SplitSink<Connection, String>,
+        config: FaultConfig,
+        mut rng: SmallRng,
+        stats: Arc<FaultStats>,
+        severed: CancellationToken,
+    ) {
+        let mut held_back: Option<String> = None;
+        let mut messages = 0;
+
+        while let Some(Ok(mut line)) = reader.next().await {
+            messages += 1;
+
+            if config
+                .sever_after_messages
+                .is_some_and(|after| messages > after)
+                || rng.random_bool(config.sever.clamp(0., 1.))
+            {
+                stats.severed.fetch_add(1, Ordering::Relaxed);
+                severed.cancel();
+                return;
+            }
+
+            if rng.random_bool(config.drop.clamp(0., 1.)) {
+                stats.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            if rng.random_bool(config.corrupt.clamp(0., 1.)) {
+                stats.corrupted.fetch_add(1, Ordering::Relaxed);
+                line = Self::corrupt(&mut rng, &line);
+            }
+
+            if let Some((min, max)) = config.delay {
+                stats.delayed.fetch_add(1, Ordering::Relaxed);
+                sleep(min + (max.saturating_sub(min)).mul_f64(rng.random::<f64>())).await;
+            }
+
+            if held_back.is_none() && rng.random_bool(config.reorder.clamp(0., 1.)) {
+                // send it after the next message
+                stats.reordered.fetch_add(1, Ordering::Relaxed);
+                held_back = Some(line);
+                continue;
+            }
+
+            let mut outgoing = vec![line.clone()];
+            if rng.random_bool(config.duplicate.clamp(0., 1.)) {
+                stats.duplicated.fetch_add(1, Ordering::Relaxed);
+                outgoing.push(line);
+            }
+            outgoing.extend(held_back.take());
+
+            for line in outgoing {
+                if writer.send(line).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        // no next message to send it after
+        if let Some(line) = held_back {
+            let _ = writer.send(line).await;
+        }
+    }
+
+    fn corrupt(rng: &mut SmallRng, line: &str) -> String {
+        let mut bytes = line.as_bytes().to_vec();
+
+        match rng.random_range(0..3) {
+            0 => bytes.truncate(rng.random_range(0..=bytes.len())),
+            1 if !bytes.is_empty() => {
+                let i = rng.random_range(0..bytes.len());
+                bytes[i] = rng.random_range(b' '..=b'~');
+            }
+            _ => bytes.extend_from_slice(b"}garbage"),
+        }
+
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl AsyncRead for Proxied {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Proxied {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+impl Stream for Proxied {
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        self.certificate.clone()
+    }
+}
+
+impl<T: Transport> Transport for Faulty<T> {
+    type Listener = T::Listener;
+
+    fn authenticates(&self) -> bool {
+        self.inner.authenticates()
+    }
+
+    async fn bind(&self, address: &str) -> io::Result<T::Listener> {
+        self.inner.bind(address).await
+    }
+
+    async fn connect(&self, address: &str) -> io::Result<Connection> {
+        let lines = self.inner.connect(address).await?;
+        Ok(self.inject(lines, address))
+    }
+}
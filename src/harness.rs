@@ -0,0 +1,371 @@
+use crate::fault::{FaultConfig, Faulty};
+use crate::lock::{LocalRequests, RingLock};
+use crate::peer::{Batching, Peer, RequestMode};
+use crate::server::Server;
+use crate::*;
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+use tokio::{sync::mpsc, task::JoinSet, time::timeout_at};
+use tokio_util::sync::CancellationToken;
+
+/// Brings up a server and a ring of peers in the current process, lets the hot potato go around
+/// `rotations` times and tears everything down again.
+#[derive(Clone)]
+pub struct Harness {
+    pub number_of_peers: usize,
+    pub rotations: usize,
+    pub timeout: Duration,
+    pub workload: Workload,
+    pub hold_policy: HoldPolicy,
+    pub request_mode: RequestMode,
+    pub batching: Batching,
+    pub token_key: Option<TokenKey>,
+}
+
+pub struct HarnessReport {
+    pub server_id: NodeId,
+    pub server_address: String,
+    pub peer_ids: Vec<NodeId>,
+    pub peer_addresses: Vec<String>,
+    pub rotations: usize,
+    pub elapsed: Duration,
+    pub events: Vec<Event>,
+    pub responses: HashMap<NodeId, Vec<ServerResponse>>,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self {
+            number_of_peers: 3,
+            rotations: 10,
+            timeout: Duration::from_secs(10),
+            workload: Workload::default(),
+            hold_policy: HoldPolicy::default(),
+            request_mode: RequestMode::default(),
+            batching: Batching::default(),
+            token_key: None,
+        }
+    }
+}
+
+impl Harness {
+    pub fn new(number_of_peers: usize, rotations: usize) -> Self {
+        Self {
+            number_of_peers,
+            rotations,
+            ..Default::default()
+        }
+    }
+
+    /// Runs the ring over TCP with every node bound to an ephemeral port of `127.0.0.1`.
+    pub async fn run(&self) -> Result<HarnessReport, Box<dyn Error + Send + Sync>> {
+        let addresses = vec!["127.0.0.1:0".to_string(); self.number_of_peers + 1];
+        self.run_with(&Tcp, addresses).await
+    }
+
+    /// Runs the ring binding the server to `addresses[0]` and the peers to the rest. The server
+    /// is known as `server` and the peers as `peer-0`, `peer-1`, ... in the ring's order.
+    pub async fn run_with<T: Transport>(
+        &self,
+        transport: &T,
+        addresses: Vec<String>,
+    ) -> Result<HarnessReport, Box<dyn Error + Send + Sync>> {
+        let transports = vec![transport.clone(); self.number_of_peers + 1];
+        self.run_with_transports(transports, addresses).await
+    }
+
+    /// Same as [`Harness::run_with`], with a transport per node in the same order as the
+    /// addresses, e.g. to give each node its own certificate.
+    pub async fn run_with_transports<T: Transport>(
+        &self,
+        transports: Vec<T>,
+        addresses: Vec<String>,
+    ) -> Result<HarnessReport, Box<dyn Error + Send + Sync>> {
+        if addresses.len() != self.number_of_peers + 1 || transports.len() != addresses.len() {
+            return Err(
+                "Expected an address and a transport for the server and for each peer.".into(),
+            );
+        }
+
+        let started = Instant::now();
+        let deadline = tokio::time::Instant::now() + self.timeout;
+
+        let mut builder = RingBuilder::new(self.number_of_peers);
+        builder.server.token_key = self.token_key.clone();
+        builder.peer.hold_policy = self.hold_policy.clone();
+        builder.peer.request_mode = self.request_mode.clone();
+        builder.peer.batching = self.batching;
+        builder.peer.token_guard = TokenGuard::new(self.token_key.clone());
+        builder.peer.workload = self.workload.clone();
+        let mut ring = builder.start_with_transports(transports, addresses).await?;
+
+        // every peer throws the hot potato once per rotation
+        let throws = self.rotations * self.number_of_peers;
+        let mut events = Vec::new();
+        let mut thrown = 0;
+
+        while thrown < throws {
+            match timeout_at(deadline, ring.events.recv()).await {
+                Ok(Some(event)) => {
+                    if matches!(event.kind, EventKind::TokenSent { .. }) {
+                        thrown += 1;
+                    }
+                    events.push(event);
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    ring.shutdown.cancel();
+                    return Err(format!(
+                        "Only {} of {} rotations happened before the timeout.",
+                        thrown / self.number_of_peers.max(1),
+                        self.rotations
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let server_address = ring.server_address.clone();
+        let peer_addresses = ring.peer_addresses.clone();
+        events.extend(ring.stop().await);
+
+        let mut responses: HashMap<NodeId, Vec<ServerResponse>> = HashMap::new();
+        for event in &events {
+            if let EventKind::ResponseReceived(response) = &event.kind {
+                responses
+                    .entry(NodeId::new(&event.node))
+                    .or_default()
+                    .push(response.clone());
+            }
+        }
+
+        Ok(HarnessReport {
+            server_id: NodeId::new("server"),
+            server_address,
+            peer_ids: (0..self.number_of_peers)
+                .map(|i| NodeId::new(&format!("peer-{i}")))
+                .collect(),
+            peer_addresses,
+            rotations: thrown / self.number_of_peers.max(1),
+            elapsed: started.elapsed(),
+            events,
+            responses,
+        })
+    }
+}
+
+/// Brings up a server and a ring of peers in the current process and leaves them running until
+/// [`Ring::stop`]. The server is known as `server` and the peers as `peer-0`, `peer-1`, ... in
+/// the ring's order, each configured as a copy of `server` and `peer`.
+#[derive(Clone)]
+pub struct RingBuilder {
+    pub number_of_peers: usize,
+    pub server: Server,
+    pub peer: Peer,
+    /// The server also serves the admin protocol on this address when set.
+    pub admin_address: Option<String>,
+    /// The first peer also accepts submissions on this address when set.
+    pub submission_address: Option<String>,
+}
+
+/// A ring started by a [`RingBuilder`].
+pub struct Ring {
+    pub server_address: String,
+    pub peer_addresses: Vec<String>,
+    pub admin_address: Option<String>,
+    pub submission_address: Option<String>,
+    /// The events recorded by the server and every peer.
+    pub events: EventRx,
+    pub shutdown: CancellationToken,
+    pub nodes: JoinSet<()>,
+    local_requests: Vec<LocalRequests>,
+}
+
+impl RingBuilder {
+    pub fn new(number_of_peers: usize) -> Self {
+        Self {
+            number_of_peers,
+            server: Server::new(String::new(), number_of_peers),
+            peer: Peer::new(String::new(), String::new(), String::new()),
+            admin_address: None,
+            submission_address: None,
+        }
+    }
+
+    /// Starts the ring over TCP with every node bound to an ephemeral port of `127.0.0.1`.
+    pub async fn start(&self) -> Result<Ring, Box<dyn Error + Send + Sync>> {
+        let addresses = vec!["127.0.0.1:0".to_string(); self.number_of_peers + 1];
+        self.start_with(&Tcp, addresses).await
+    }
+
+    /// Starts the ring in memory with every hop taking `hop_latency`, so the ring waits on its
+    /// links and a paused clock moves on while the hot potato goes around. The nodes are bound
+    /// to their names.
+    pub async fn start_in_memory(
+        &self,
+        hop_latency: Duration,
+    ) -> Result<Ring, Box<dyn Error + Send + Sync>> {
+        let config = FaultConfig {
+            delay: Some((hop_latency, hop_latency)),
+            ..Default::default()
+        };
+        let transport = Faulty::new(Memory::default(), config, 0);
+        let addresses = std::iter::once("server".to_string())
+            .chain((0..self.number_of_peers).map(|i| format!("peer-{i}")))
+            .collect();
+        self.start_with(&transport, addresses).await
+    }
+
+    /// Starts the ring binding the server to `addresses[0]` and the peers to the rest.
+    pub async fn start_with<T: Transport>(
+        &self,
+        transport: &T,
+        addresses: Vec<String>,
+    ) -> Result<Ring, Box<dyn Error + Send + Sync>> {
+        let transports = vec![transport.clone(); self.number_of_peers + 1];
+        self.start_with_transports(transports, addresses).await
+    }
+
+    /// Same as [`RingBuilder::start_with`], with a transport per node in the same order as the
+    /// addresses.
+    pub async fn start_with_transports<T: Transport>(
+        &self,
+        transports: Vec<T>,
+        addresses: Vec<String>,
+    ) -> Result<Ring, Box<dyn Error + Send + Sync>> {
+        if addresses.len() != self.number_of_peers + 1 || transports.len() != addresses.len() {
+            return Err(
+                "Expected an address and a transport for the server and for each peer.".into(),
+            );
+        }
+
+        let shutdown = CancellationToken::new();
+        let (event_tx, events) = mpsc::unbounded_channel();
+        let mut nodes = JoinSet::new();
+
+        let mut server = self.server.clone();
+        server.own_address = addresses[0].clone();
+        server.number_of_peers = self.number_of_peers;
+        server.node_id = NodeId::new("server");
+        server.recorder = EventRecorder::to_channel(server.node_id.as_str(), event_tx.clone());
+        let server_listener = server.bind(&transports[0]).await?;
+        let admin_listener = match &self.admin_address {
+            Some(address) => Some(transports[0].bind(address).await?),
+            None => None,
+        };
+        let admin_address = admin_listener
+            .as_ref()
+            .map(|listener| listener.local_address())
+            .transpose()?;
+
+        // bind every peer first, so the ring can be wired with the addresses actually bound
+        let mut peers = Vec::with_capacity(self.number_of_peers);
+        for (i, (address, transport)) in addresses[1..].iter().zip(&transports[1..]).enumerate() {
+            let mut peer = self.peer.clone();
+            peer.node_id = NodeId::new(&format!("peer-{i}"));
+            peer.address = address.clone();
+            peer.server_address = server.own_address.clone();
+            peer.local_requests = LocalRequests::new();
+            peer.recorder = EventRecorder::to_channel(peer.node_id.as_str(), event_tx.clone());
+            let listener = peer.bind(transport).await?;
+            peers.push((peer, listener, transport.clone()));
+        }
+
+        let peer_addresses = peers
+            .iter()
+            .map(|(peer, _, _)| peer.address.clone())
+            .collect::<Vec<_>>();
+        for (i, (peer, _, _)) in peers.iter_mut().enumerate() {
+            peer.next_peer_address = peer_addresses[(i + 1) % peer_addresses.len()].clone();
+        }
+        let local_requests = peers
+            .iter()
+            .map(|(peer, _, _)| peer.local_requests.clone())
+            .collect();
+
+        let mut submission_listener = match (&self.submission_address, transports.get(1)) {
+            (Some(address), Some(transport)) => Some(transport.bind(address).await?),
+            _ => None,
+        };
+        let submission_address = submission_listener
+            .as_ref()
+            .map(|listener| listener.local_address())
+            .transpose()?;
+
+        let server_address = server.own_address.clone();
+        {
+            let shutdown = shutdown.clone();
+            nodes.spawn(async move {
+                if let Err(e) = server
+                    .serve_with_admin(server_listener, admin_listener, shutdown)
+                    .await
+                {
+                    log::error(&format!("{e}"));
+                }
+            });
+        }
+        for (mut peer, listener, transport) in peers {
+            let shutdown = shutdown.clone();
+            let submission_listener = submission_listener.take();
+            nodes.spawn(async move {
+                peer.serve_with_submissions(&transport, listener, submission_listener, shutdown)
+                    .await
+            });
+        }
+
+        Ok(Ring {
+            server_address,
+            peer_addresses,
+            admin_address,
+            submission_address,
+            events,
+            shutdown,
+            nodes,
+            local_requests,
+        })
+    }
+}
+
+impl Ring {
+    /// A lock on the default resource through each peer, in the ring's order.
+    pub fn locks(&self) -> Vec<RingLock> {
+        self.resource_locks("")
+    }
+
+    /// A lock on `resource` through each peer, in the ring's order.
+    pub fn resource_locks(&self, resource: &str) -> Vec<RingLock> {
+        self.local_requests
+            .iter()
+            .map(|local_requests| local_requests.handle(resource))
+            .collect()
+    }
+
+    /// Moves the events recorded so far to `events`, returns how many tokens were thrown
+    /// meanwhile.
+    pub fn drain(&mut self, events: &mut Vec<Event>) -> usize {
+        let mut throws = 0;
+        while let Ok(event) = self.events.try_recv() {
+            if matches!(
+                event.kind,
+                EventKind::TokenSent { .. } | EventKind::ResourceTokenSent { .. }
+            ) {
+                throws += 1;
+            }
+            events.push(event);
+        }
+        throws
+    }
+
+    /// Shuts every node down and returns the events recorded since the last [`Ring::drain`].
+    pub async fn stop(mut self) -> Vec<Event> {
+        self.shutdown.cancel();
+        while self.nodes.join_next().await.is_some() {}
+
+        let mut events = Vec::new();
+        self.drain(&mut events);
+        events
+    }
+}
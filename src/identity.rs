@@ -0,0 +1,110 @@
+use crate::*;
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, fs, io, path::Path};
+
+/// Identifies a node independently of the address it happens to bind.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct NodeId(pub String);
+
+impl NodeId {
+    pub fn new(id: &str) -> Self {
+        Self(id.to_string())
+    }
+
+    pub fn generate() -> Self {
+        Self(format!("{:016x}", rand::rng().random::<u64>()))
+    }
+
+    /// Reads the id stored in `path`, or generates one and stores it there so the node keeps
+    /// its id across restarts.
+    pub fn load_or_generate<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        match fs::read_to_string(path) {
+            Ok(id) if !id.trim().is_empty() => Ok(Self(id.trim().to_string())),
+            Ok(_) => Self::generate_into(path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::generate_into(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn generate_into(path: &Path) -> io::Result<Self> {
+        let id = Self::generate();
+        fs::write(path, format!("{id}\n"))?;
+
+        Ok(id)
+    }
+
+    /// Uses `id` if given, else the id persisted in `path`, else a fresh one.
+    pub fn resolve(id: Option<String>, path: Option<&str>) -> io::Result<Self> {
+        match (id, path) {
+            (Some(id), _) => Ok(Self(id)),
+            (None, Some(path)) => Self::load_or_generate(path),
+            (None, None) => Ok(Self::generate()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Handshake of the connecting end: introduces itself, then learns who it connected to. The
+/// other end of an `authenticated` connection must have presented a certificate.
+pub async fn greet(
+    lines: &mut Connection,
+    hello: &Hello,
+    authenticated: bool,
+) -> Result<Hello, Box<dyn Error + Send + Sync>> {
+    lines.send(hello.to_json_string()?).await?;
+    receive_hello(lines, authenticated).await
+}
+
+/// Handshake of the accepting end: learns who connected, then introduces itself.
+pub async fn answer(
+    lines: &mut Connection,
+    hello: &Hello,
+    authenticated: bool,
+) -> Result<Hello, Box<dyn Error + Send + Sync>> {
+    let remote = receive_hello(lines, authenticated).await?;
+    lines.send(hello.to_json_string()?).await?;
+
+    Ok(remote)
+}
+
+/// Receives the handshake of the other end, which must claim the node id its certificate was
+/// issued for when the connection is authenticated.
+async fn receive_hello(
+    lines: &mut Connection,
+    authenticated: bool,
+) -> Result<Hello, Box<dyn Error + Send + Sync>> {
+    let hello = match lines.next().await {
+        Some(Ok(line)) => Hello::from_json_string(&line)
+            .map_err(|e| format!("Expected a handshake but received {line:?}: {e}"))?,
+        Some(Err(e)) => return Err(e.into()),
+        None => return Err("The connection closed during the handshake.".into()),
+    };
+
+    match lines.get_ref().peer_certificate() {
+        Some(certificate) if !tls::certifies(&certificate, &hello.node_id) => Err(format!(
+            "{} claimed to be {} without a certificate for it.",
+            hello.address, hello.node_id
+        )
+        .into()),
+        None if authenticated => Err(format!(
+            "{} claimed to be {} but its certificate was lost on the way.",
+            hello.address, hello.node_id
+        )
+        .into()),
+        _ => Ok(hello),
+    }
+}
@@ -0,0 +1,313 @@
+use crate::config::{self, ConfigError};
+use crate::*;
+use color_print::cformat;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs, io,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    sync::mpsc,
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+
+/// How long the nodes have to exit after SIGTERM before they're killed.
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// A ring to run on this machine: the server and the peers in the ring's order, each peer
+/// throws the hot potato to the next one and the last one to the first.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Topology {
+    pub transport: TransportKind,
+    /// Config file of the nodes that don't have their own.
+    pub config: Option<String>,
+    /// Address of the dashboard every node reports to.
+    pub dashboard: Option<String>,
+    pub server: NodeSpec,
+    pub peers: Vec<NodeSpec>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeSpec {
+    /// `server` and `peer-<i>` by default.
+    pub node_id: Option<String>,
+    /// A free address is allocated when missing.
+    pub address: Option<String>,
+    pub config: Option<String>,
+    /// Extra command line arguments of the node.
+    pub args: Vec<String>,
+}
+
+/// The TCP ports allocated to the nodes, each held until its node is started so no other
+/// process gets it meanwhile, and the Unix sockets allocated to them, removed on drop.
+#[derive(Debug, Default)]
+pub struct Reservations {
+    listeners: HashMap<NodeId, TcpListener>,
+    sockets: Vec<PathBuf>,
+}
+
+/// How to start one node of the ring.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeCommand {
+    pub node_id: NodeId,
+    pub program: PathBuf,
+    pub args: Vec<String>,
+}
+
+impl Topology {
+    /// A ring of `number_of_peers` peers with default settings.
+    pub fn ring(number_of_peers: usize) -> Self {
+        Self {
+            peers: vec![NodeSpec::default(); number_of_peers],
+            ..Default::default()
+        }
+    }
+
+    pub fn from_toml_file(path: &str) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ConfigError::new("", format!("couldn't read {path}: {e}")))?;
+        let topology: Self = config::parse(&text, [])?;
+
+        if topology.peers.is_empty() {
+            return Err(ConfigError::new(
+                "peers",
+                "at least one peer is required".to_string(),
+            ));
+        }
+        Ok(topology)
+    }
+
+    /// Gives every node without one its default id and a free address. The TCP ports stay
+    /// reserved until [`launch`] starts their nodes.
+    pub fn allocate(&mut self) -> io::Result<Reservations> {
+        self.server
+            .node_id
+            .get_or_insert_with(|| "server".to_string());
+        for (i, peer) in self.peers.iter_mut().enumerate() {
+            peer.node_id.get_or_insert_with(|| format!("peer-{i}"));
+        }
+
+        let mut reservations = Reservations::default();
+        for node in std::iter::once(&mut self.server).chain(&mut self.peers) {
+            if node.address.is_some() {
+                continue;
+            }
+            let node_id = NodeId::new(node.node_id.as_deref().unwrap_or_default());
+            node.address = Some(match self.transport {
+                TransportKind::Tcp => {
+                    let probe = TcpListener::bind("127.0.0.1:0")?;
+                    let address = probe.local_addr()?.to_string();
+                    reservations.listeners.insert(node_id, probe);
+                    address
+                }
+                TransportKind::Unix => {
+                    let path = std::env::temp_dir()
+                        .join(format!("token-ring-{}-{node_id}.sock", std::process::id()));
+                    let address = path.display().to_string();
+                    reservations.sockets.push(path);
+                    address
+                }
+            });
+        }
+
+        Ok(reservations)
+    }
+
+    /// The commands starting the server and the peers, which must have been allocated, with
+    /// the `server` and `peer` binaries found in `binaries`. Events are recorded to
+    /// `<node id>.jsonl` files in `event_logs` when given.
+    pub fn commands(&self, binaries: &Path, event_logs: Option<&str>) -> Vec<NodeCommand> {
+        let address = |node: &NodeSpec| node.address.clone().unwrap_or_default();
+        let server_address = address(&self.server);
+
+        let mut commands = vec![self.command(
+            &self.server,
+            binaries.join("server"),
+            vec![server_address.clone(), self.peers.len().to_string()],
+            event_logs,
+        )];
+        for (i, peer) in self.peers.iter().enumerate() {
+            let next_peer = &self.peers[(i + 1) % self.peers.len()];
+            commands.push(self.command(
+                peer,
+                binaries.join("peer"),
+                vec![address(peer), server_address.clone(), address(next_peer)],
+                event_logs,
+            ));
+        }
+
+        commands
+    }
+
+    fn command(
+        &self,
+        node: &NodeSpec,
+        program: PathBuf,
+        mut args: Vec<String>,
+        event_logs: Option<&str>,
+    ) -> NodeCommand {
+        let node_id = NodeId::new(node.node_id.as_deref().unwrap_or_default());
+
+        args.extend(["--node-id".to_string(), node_id.to_string()]);
+        args.extend(["--transport".to_string(), self.transport_name()]);
+        if let Some(config) = node.config.as_ref().or(self.config.as_ref()) {
+            args.extend(["--config".to_string(), config.clone()]);
+        }
+        if let Some(address) = &self.dashboard {
+            args.extend(["--dashboard".to_string(), address.clone()]);
+        }
+        if let Some(directory) = event_logs {
+            let path = Path::new(directory).join(format!("{node_id}.jsonl"));
+            args.extend(["--event-log".to_string(), path.display().to_string()]);
+        }
+        args.extend(node.args.iter().cloned());
+
+        NodeCommand {
+            node_id,
+            program,
+            args,
+        }
+    }
+
+    fn transport_name(&self) -> String {
+        match self.transport {
+            TransportKind::Tcp => "tcp",
+            TransportKind::Unix => "unix",
+        }
+        .to_string()
+    }
+}
+
+impl Drop for Reservations {
+    fn drop(&mut self) {
+        for path in &self.sockets {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Starts every node as a child process and prints their output prefixed with their node
+/// ids, until `shutdown` is cancelled or a node exits. A node's reserved port is only released
+/// right before it's started. All the nodes are stopped on return, with SIGTERM then SIGKILL
+/// once [`STOP_GRACE_PERIOD`] has passed.
+pub async fn launch(
+    commands: &[NodeCommand],
+    mut reservations: Reservations,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let width = commands
+        .iter()
+        .map(|command| command.node_id.as_str().len())
+        .max()
+        .unwrap_or_default();
+    let (line_tx, mut line_rx) = mpsc::unbounded_channel();
+    let mut outputs = JoinSet::new();
+    let mut children = Vec::with_capacity(commands.len());
+
+    for command in commands {
+        drop(reservations.listeners.remove(&command.node_id));
+        let mut child = Command::new(&command.program)
+            .args(&command.args)
+            // the nodes share the launcher's terminal
+            .env(format!("{}LOG__CLEAR", config::ENV_PREFIX), "false")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Couldn't start {}: {e}", command.program.display()))?;
+
+        let prefix = cformat!("<bold>{:>width$}</bold> |", command.node_id.as_str());
+        if let Some(stdout) = child.stdout.take() {
+            outputs.spawn(forward(stdout, prefix.clone(), line_tx.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            outputs.spawn(forward(stderr, prefix, line_tx.clone()));
+        }
+        log::info(&cformat!(
+            "Started <bold>{}</bold> (pid {})",
+            command.node_id,
+            child.id().unwrap_or_default()
+        ));
+        children.push((command.node_id.clone(), child));
+    }
+    drop(line_tx);
+
+    let exited = loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break None,
+            Some(line) = line_rx.recv() => println!("{line}"),
+            (node_id, status) = wait_any(&mut children) => break Some((node_id, status)),
+        }
+    };
+
+    for (_, child) in &mut children {
+        terminate(child);
+    }
+    let _ = timeout(STOP_GRACE_PERIOD, async {
+        for (_, child) in &mut children {
+            let _ = child.wait().await;
+        }
+    })
+    .await;
+    for (_, child) in &mut children {
+        let _ = child.start_kill();
+        let _ = child.wait().await;
+    }
+    outputs.abort_all();
+    while let Ok(line) = line_rx.try_recv() {
+        println!("{line}");
+    }
+
+    match exited {
+        Some((node_id, Ok(status))) => Err(format!("{node_id} exited ({status}).").into()),
+        Some((node_id, Err(e))) => Err(format!("Lost track of {node_id}: {e}").into()),
+        None => {
+            log::info("Stopped every node.");
+            Ok(())
+        }
+    }
+}
+
+/// Asks `child` to exit, unless it already has.
+fn terminate(child: &Child) {
+    if let Some(pid) = child.id() {
+        // SAFETY: kill has no memory effects, and the pid is still our unreaped child
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+}
+
+async fn forward<R: AsyncRead + Unpin>(
+    output: R,
+    prefix: String,
+    lines: mpsc::UnboundedSender<String>,
+) {
+    let mut reader = BufReader::new(output).lines();
+    while let Ok(Some(line)) = reader.next_line().await {
+        if lines.send(format!("{prefix} {line}")).is_err() {
+            break;
+        }
+    }
+}
+
+async fn wait_any(children: &mut [(NodeId, Child)]) -> (NodeId, io::Result<ExitStatus>) {
+    let waits = children
+        .iter_mut()
+        .map(|(node_id, child)| Box::pin(async move { (node_id.clone(), child.wait().await) }))
+        .collect::<Vec<_>>();
+
+    futures::future::select_all(waits).await.0
+}
@@ -1,10 +1,35 @@
+use crate::arrival::*;
+use crate::auth::*;
+use crate::checker::*;
+use crate::identity::*;
 use crate::message::*;
 use crate::poisson::*;
+use crate::policy::*;
+use crate::transport::*;
+use crate::workload::*;
 
+pub mod admin;
+pub mod arrival;
+pub mod auth;
+pub mod checker;
+pub mod config;
+pub mod dashboard;
+pub mod fault;
+pub mod harness;
+pub mod identity;
+pub mod launcher;
+pub mod lock;
 pub mod log;
 pub mod message;
 pub mod peer;
 pub mod poisson;
+pub mod policy;
 pub mod server;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod submission;
+pub mod tls;
+pub mod transport;
+pub mod workload;
 
 pub const RATE: f64 = 1.;
@@ -0,0 +1,217 @@
+use crate::submission::{SubmissionRx, SubmissionTx, Submitted};
+use crate::*;
+use std::{error::Error, sync::Arc, time::Duration};
+use tokio::sync::{mpsc, oneshot, Mutex, OwnedMutexGuard};
+
+pub type LockTx = mpsc::UnboundedSender<LockRequest>;
+pub type LockRx = mpsc::UnboundedReceiver<LockRequest>;
+
+/// A waiting [`RingLock::lock`], granted with the channel its guard submits through.
+#[derive(Debug)]
+pub struct LockRequest {
+    pub resource: String,
+    pub access: Access,
+    pub priority: Priority,
+    /// The peer takes the hot potato back this long after granting the lock.
+    pub hold_deadline: Option<Duration>,
+    pub granted: oneshot::Sender<SubmissionTx>,
+}
+
+/// The requests an application makes through a peer, kept across the peer's reconnections.
+#[derive(Clone, Debug)]
+pub struct LocalRequests {
+    pub submission_tx: SubmissionTx,
+    pub lock_tx: LockTx,
+    receivers: Arc<Mutex<(SubmissionRx, LockRx)>>,
+}
+
+/// Uses the ring as a distributed lock through the peer it was taken from, one lock per
+/// resource.
+#[derive(Clone, Debug)]
+pub struct RingLock {
+    pub resource: String,
+    /// Priority of the locks taken and requests submitted through the handle.
+    pub priority: Priority,
+    /// How long the locks taken through the handle may keep the hot potato, unbounded when
+    /// unset.
+    pub hold_deadline: Option<Duration>,
+    submission_tx: SubmissionTx,
+    lock_tx: LockTx,
+}
+
+/// The peer keeps the resource's token while the guard lives, dropping it throws the token on.
+/// A shared guard holds a read lease instead, the token keeps going around.
+#[derive(Debug)]
+pub struct RingLockGuard {
+    resource: String,
+    access: Access,
+    priority: Priority,
+    submission_tx: SubmissionTx,
+}
+
+impl Default for LocalRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalRequests {
+    pub fn new() -> Self {
+        let (submission_tx, submission_rx) = mpsc::unbounded_channel();
+        let (lock_tx, lock_rx) = mpsc::unbounded_channel();
+
+        Self {
+            submission_tx,
+            lock_tx,
+            receivers: Arc::new(Mutex::new((submission_rx, lock_rx))),
+        }
+    }
+
+    /// A lock on the named `resource`, the default one when empty.
+    pub fn handle(&self, resource: &str) -> RingLock {
+        RingLock {
+            resource: resource.to_string(),
+            priority: Priority::Low,
+            hold_deadline: None,
+            submission_tx: self.submission_tx.clone(),
+            lock_tx: self.lock_tx.clone(),
+        }
+    }
+
+    /// The receiving ends, held by the running peer until it leaves the ring.
+    pub async fn receivers(&self) -> OwnedMutexGuard<(SubmissionRx, LockRx)> {
+        self.receivers.clone().lock_owned().await
+    }
+}
+
+impl RingLock {
+    /// The same handle with `priority`.
+    pub fn with_priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// The same handle whose locks are reclaimed by the peer `hold_deadline` after they're
+    /// granted, the hot potato is thrown on even if the guard is still alive.
+    pub fn with_hold_deadline(self, hold_deadline: Duration) -> Self {
+        Self {
+            hold_deadline: Some(hold_deadline),
+            ..self
+        }
+    }
+
+    /// Waits until the peer holds the resource's token and keeps it there until the guard is
+    /// dropped, whatever the hold policy, or until the handle's hold deadline. The peer grants
+    /// one lock per visit, after sending its queued requests.
+    pub async fn lock(&self) -> Result<RingLockGuard, Box<dyn Error + Send + Sync>> {
+        self.lock_with(Access::Exclusive).await
+    }
+
+    /// Waits for a read lease on the resource, held until the guard is dropped. Readers share
+    /// the resource with each other but never with a writer.
+    pub async fn lock_shared(&self) -> Result<RingLockGuard, Box<dyn Error + Send + Sync>> {
+        self.lock_with(Access::Shared).await
+    }
+
+    async fn lock_with(
+        &self,
+        access: Access,
+    ) -> Result<RingLockGuard, Box<dyn Error + Send + Sync>> {
+        let (granted_tx, granted_rx) = oneshot::channel();
+        self.lock_tx
+            .send(LockRequest {
+                resource: self.resource.clone(),
+                access,
+                priority: self.priority,
+                hold_deadline: self.hold_deadline,
+                granted: granted_tx,
+            })
+            .map_err(|_| "The peer isn't running anymore.")?;
+        let submission_tx = granted_rx
+            .await
+            .map_err(|_| "The peer left the ring before getting the hot potato.")?;
+
+        Ok(RingLockGuard {
+            resource: self.resource.clone(),
+            access,
+            priority: self.priority,
+            submission_tx,
+        })
+    }
+
+    /// Queues `request` on the peer and waits for its response, the request is sent at the
+    /// peer's next visit.
+    pub async fn submit(
+        &self,
+        request: ServerRequest,
+    ) -> Result<ServerResponse, Box<dyn Error + Send + Sync>> {
+        submit_to(
+            &self.submission_tx,
+            &self.resource,
+            Access::Exclusive,
+            self.priority,
+            request,
+        )
+        .await
+    }
+
+    /// Same as [`RingLock::submit`] for a read, sent under a read lease.
+    pub async fn submit_shared(
+        &self,
+        request: ServerRequest,
+    ) -> Result<ServerResponse, Box<dyn Error + Send + Sync>> {
+        submit_to(
+            &self.submission_tx,
+            &self.resource,
+            Access::Shared,
+            self.priority,
+            request,
+        )
+        .await
+    }
+}
+
+impl RingLockGuard {
+    /// Sends `request` to the server right away and waits for its response.
+    pub async fn submit(
+        &self,
+        request: ServerRequest,
+    ) -> Result<ServerResponse, Box<dyn Error + Send + Sync>> {
+        if !self.is_held() {
+            return Err("The lock isn't held anymore.".into());
+        }
+
+        submit_to(
+            &self.submission_tx,
+            &self.resource,
+            self.access,
+            self.priority,
+            request,
+        )
+        .await
+    }
+
+    /// False once the peer reclaimed the hot potato at the hold deadline or left the ring.
+    pub fn is_held(&self) -> bool {
+        !self.submission_tx.is_closed()
+    }
+
+    /// Throws the hot potato on or gives the read lease back, same as dropping the guard.
+    pub fn release(self) {}
+}
+
+async fn submit_to(
+    submission_tx: &SubmissionTx,
+    resource: &str,
+    access: Access,
+    priority: Priority,
+    request: ServerRequest,
+) -> Result<ServerResponse, Box<dyn Error + Send + Sync>> {
+    let (submitted, response) = Submitted::new(resource, access, priority, request);
+    submission_tx
+        .send(submitted)
+        .map_err(|_| "The peer isn't running anymore.")?;
+
+    Ok(response
+        .await
+        .map_err(|_| "The peer left the ring before getting the response.")?)
+}
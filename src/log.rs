@@ -1,19 +1,56 @@
 use color_print::cprintln;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
 use terminal_size::{terminal_size, Height};
 
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Debug as u8);
+
+/// Messages below the level are not printed, everything is by default.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    #[default]
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+fn enabled(level: Level) -> bool {
+    level as u8 >= LEVEL.load(Ordering::Relaxed)
+}
+
 pub fn warning(message: &str) {
+    if !enabled(Level::Warning) {
+        return;
+    }
     cprintln!("<yellow, bold>WARNING:</yellow, bold>  {}", message);
 }
 
 pub fn info(message: &str) {
+    if !enabled(Level::Info) {
+        return;
+    }
     cprintln!("<green, bold>INFO:</green, bold>     {}", message);
 }
 
 pub fn debug(message: &str) {
+    if !enabled(Level::Debug) {
+        return;
+    }
     cprintln!("<bold>DEBUG:</bold>    {}", message);
 }
 
 pub fn error(message: &str) {
+    if !enabled(Level::Error) {
+        return;
+    }
     cprintln!("<red, bold>ERROR:</red, bold>    {}", message);
 }
 
@@ -1,36 +1,85 @@
 use color_print::cformat;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{error::Error, net::SocketAddr};
+use std::{error::Error, fmt, net::SocketAddr, str::FromStr};
 use tokio::sync::mpsc;
 
-use crate::log;
+use crate::{identity::NodeId, log};
 
 pub type FindHotPotatoStateTx = mpsc::UnboundedSender<FindHotPotato>;
 pub type FindHotPotatoStateRx = mpsc::UnboundedReceiver<FindHotPotato>;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StartFlag(pub bool);
+/// Starts the ring, `epoch` is the one of the hot potatoes the server creates first. The
+/// peers accept no token of another epoch until the server announces a new one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StartFlag {
+    pub epoch: u64,
+}
+
+/// First line sent each way on every connection, so both ends know who they talk to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub node_id: NodeId,
+    pub address: String,
+}
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct HotPotato(pub bool);
+/// The token, `epoch` changes whenever the server creates a new one and `sequence` counts the
+/// times it was thrown within the epoch. `holder` is the node it was thrown to and `mac` signs
+/// the token when the ring shares a token key. Each named `resource` has its own token, the
+/// unnamed one guards the default resource.
+///
+/// `readers` lists the peers holding a read lease on the resource, no peer gets exclusive
+/// access until they all gave theirs back. A new epoch's token lists none, so the leases of an
+/// evicted peer don't outlive it. `waiting_writer` is the peer waiting for them, no
+/// new lease is taken meanwhile.
+///
+/// `demand` lists the peers with high-priority work waiting for the token, the peers on the
+/// way with only low-priority work pass it on to them. `wanted_by` lists the peers that pulled
+/// a resting token, see [`Demand`], it doesn't rest again before reaching them.
+///
+/// `idle_since` is the first peer the token reached without work since it last carried some,
+/// `idle_rotations` counts the times it came back there still without work.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HotPotato {
+    pub epoch: u64,
+    pub sequence: u64,
+    #[serde(default)]
+    pub holder: NodeId,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub resource: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub readers: Vec<NodeId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waiting_writer: Option<NodeId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub demand: Vec<NodeId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wanted_by: Vec<NodeId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_since: Option<NodeId>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub idle_rotations: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum HotPotatoState {
-    Holding(HotPotato),
+    Holding(Box<HotPotato>),
     NotHolding,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum FindHotPotato {
     Response {
-        hot_potato_state: HotPotatoState,
+        hot_potato_state: Box<HotPotatoState>,
         previous_peer_address: SocketAddr,
     },
     Request,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ServerRequest {
     Add(i32, i32),
     Sub(i32, i32),
@@ -38,7 +87,63 @@ pub enum ServerRequest {
     Div(i32, i32),
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+/// The kinds of [`ServerRequest`], the server only executes the ones it allows under shared
+/// access for readers.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Peers serve their high-priority requests first and have the token come to them sooner, see
+/// [`HotPotato::demand`].
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    Low,
+    High,
+}
+
+/// Sent along the ring by a peer with work for `resource`, until it reaches the peer holding
+/// that resource's token. Only high-priority work asks for the token of a circulating ring, any
+/// work pulls the resting token of a demand-driven one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Demand {
+    pub node_id: NodeId,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub resource: String,
+    #[serde(default, skip_serializing_if = "Priority::is_low")]
+    pub priority: Priority,
+}
+
+/// How a request uses its resource: readers share it, a writer gets it for itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Shared,
+    #[default]
+    Exclusive,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ServerResponse {
     Add(i32, i32, i32),
     Sub(i32, i32, i32),
@@ -47,7 +152,113 @@ pub enum ServerResponse {
     Err(i32, i32, String),
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub requests: Vec<ServerRequest>,
+    pub atomic: bool,
+    /// Named resource the batch is executed under, the default one when empty.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub resource: String,
+    /// Echoed in the [`Reply`], 0 when the sender doesn't tell its requests apart.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub id: u64,
+}
+
+/// A request executed under a named resource, sent while holding that resource's token or a
+/// read lease on it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResourceRequest {
+    pub resource: String,
+    pub request: ServerRequest,
+    #[serde(default, skip_serializing_if = "Access::is_exclusive")]
+    pub access: Access,
+    /// Echoed in the [`Reply`], 0 when the sender doesn't tell its requests apart.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub id: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchResponse(pub Vec<ServerResponse>);
+
+/// What the server answers the request or batch `id` of the peer `node_id` with, one response
+/// per operation.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    pub node_id: NodeId,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub id: u64,
+    pub responses: Vec<ServerResponse>,
+}
+
+/// Requests a client submits to a peer, answered with a [`BatchResponse`] once the peer held
+/// the token of `resource` and the server executed them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Submission {
+    pub requests: Vec<ServerRequest>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub resource: String,
+    #[serde(default, skip_serializing_if = "Access::is_exclusive")]
+    pub access: Access,
+    #[serde(default, skip_serializing_if = "Priority::is_low")]
+    pub priority: Priority,
+}
+
+/// Sent by the server to a peer to steer the ring.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Control {
+    /// Answer with a [`PeerStatus`].
+    Report,
+    /// Keep the hot potato when it arrives until resumed.
+    Pause,
+    Resume,
+    /// Drop the hot potatoes of other epochs, a new one is on its way.
+    NewEpoch(u64),
+    /// Throw the hot potato to the peer listening on this address from now on.
+    Rewire {
+        next_peer_address: String,
+    },
+    /// Leave the ring.
+    Evict,
+}
+
+/// A peer's answer to [`Control::Report`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PeerStatus {
+    pub node_id: NodeId,
+    pub previous_peer_id: Option<NodeId>,
+    pub next_peer_id: Option<NodeId>,
+    /// Epoch and sequence of the hot potato held.
+    pub holding: Option<(u64, u64)>,
+    pub paused: bool,
+    pub queue_length: usize,
+    pub visits: u64,
+    /// Named resources whose token is held.
+    #[serde(default)]
+    pub resources: Vec<String>,
+    /// Rotations of the default hot potato per second.
+    #[serde(default)]
+    pub rotation_rate: f64,
+}
+
 impl StartFlag {
+    pub fn new(epoch: u64) -> Self {
+        Self { epoch }
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(token: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(token)?)
+    }
+}
+
+impl Hello {
+    pub fn new(node_id: NodeId, address: String) -> Self {
+        Self { node_id, address }
+    }
+
     pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
         Ok(serde_json::to_string(self)?)
     }
@@ -57,9 +268,91 @@ impl StartFlag {
     }
 }
 
+impl Submission {
+    pub fn new(requests: Vec<ServerRequest>, resource: &str) -> Self {
+        Self {
+            requests,
+            resource: resource.to_string(),
+            access: Access::Exclusive,
+            priority: Priority::Low,
+        }
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(submission: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(submission)?)
+    }
+}
+
+impl Control {
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(control: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(control)?)
+    }
+}
+
+impl PeerStatus {
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(status: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(status)?)
+    }
+}
+
+impl Default for HotPotato {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HotPotato {
     pub fn new() -> Self {
-        Self(true)
+        Self {
+            epoch: 0,
+            sequence: 0,
+            holder: NodeId::default(),
+            resource: String::new(),
+            readers: Vec::new(),
+            waiting_writer: None,
+            demand: Vec::new(),
+            wanted_by: Vec::new(),
+            idle_since: None,
+            idle_rotations: 0,
+            mac: None,
+        }
+    }
+
+    /// The token of the named `resource` the server starts with.
+    pub fn for_resource(resource: &str) -> Self {
+        Self {
+            resource: resource.to_string(),
+            ..Self::new()
+        }
+    }
+
+    /// The same token, unsigned, as thrown to `holder`.
+    pub fn thrown_to(&self, holder: NodeId) -> Self {
+        Self {
+            epoch: self.epoch,
+            sequence: self.sequence + 1,
+            holder,
+            resource: self.resource.clone(),
+            readers: self.readers.clone(),
+            waiting_writer: self.waiting_writer.clone(),
+            demand: self.demand.clone(),
+            wanted_by: self.wanted_by.clone(),
+            idle_since: self.idle_since.clone(),
+            idle_rotations: self.idle_rotations,
+            mac: None,
+        }
     }
 
     pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
@@ -130,6 +423,21 @@ impl ServerRequest {
         }
     }
 
+    pub fn operands(&self) -> (i32, i32) {
+        match self {
+            Self::Add(a, b) | Self::Sub(a, b) | Self::Mul(a, b) | Self::Div(a, b) => (*a, *b),
+        }
+    }
+
+    pub fn operation(&self) -> Operation {
+        match self {
+            Self::Add(..) => Operation::Add,
+            Self::Sub(..) => Operation::Sub,
+            Self::Mul(..) => Operation::Mul,
+            Self::Div(..) => Operation::Div,
+        }
+    }
+
     pub fn generate<R: Rng + ?Sized>(rng: &mut R) -> Self {
         let a = rng.random::<i32>();
         let b = rng.random::<i32>();
@@ -145,7 +453,106 @@ impl ServerRequest {
     }
 }
 
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Add => "addition",
+            Self::Sub => "subtraction",
+            Self::Mul => "multiplication",
+            Self::Div => "division",
+        })
+    }
+}
+
+impl Operation {
+    pub const ALL: [Self; 4] = [Self::Add, Self::Sub, Self::Mul, Self::Div];
+
+    /// The operations that change nothing on the server, which is all of them so far.
+    pub const READ_ONLY: [Self; 4] = Self::ALL;
+}
+
+impl Access {
+    pub fn is_exclusive(&self) -> bool {
+        *self == Self::Exclusive
+    }
+}
+
+impl Priority {
+    pub fn is_low(&self) -> bool {
+        *self == Self::Low
+    }
+}
+
+impl Demand {
+    pub fn new(node_id: NodeId, resource: &str, priority: Priority) -> Self {
+        Self {
+            node_id,
+            resource: resource.to_string(),
+            priority,
+        }
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(demand: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(demand)?)
+    }
+}
+
+/// Parses an operation written as `a + b`, `a - b`, `a * b` or `a / b`.
+impl FromStr for ServerRequest {
+    type Err = String;
+
+    fn from_str(operation: &str) -> Result<Self, Self::Err> {
+        let operation = operation.trim();
+        // the first operator following a digit, the operands can be negative
+        let (position, operator) = operation
+            .char_indices()
+            .find(|(position, c)| {
+                "+-*/".contains(*c)
+                    && operation[..*position]
+                        .trim_end()
+                        .ends_with(|c: char| c.is_ascii_digit())
+            })
+            .ok_or_else(|| format!("`{operation}` isn't an operation like `1 + 2`."))?;
+
+        let operand = |operand: &str| {
+            operand.trim().parse::<i32>().map_err(|e| {
+                format!(
+                    "Invalid operand `{}` in `{operation}`: {e}.",
+                    operand.trim()
+                )
+            })
+        };
+        let a = operand(&operation[..position])?;
+        let b = operand(&operation[position + 1..])?;
+
+        Ok(match operator {
+            '+' => Self::Add(a, b),
+            '-' => Self::Sub(a, b),
+            '*' => Self::Mul(a, b),
+            _ => Self::Div(a, b),
+        })
+    }
+}
+
 impl ServerResponse {
+    pub fn is_err(&self) -> bool {
+        matches!(self, Self::Err(..))
+    }
+
+    pub fn operands(&self) -> (i32, i32) {
+        match self {
+            Self::Add(a, b, _)
+            | Self::Sub(a, b, _)
+            | Self::Mul(a, b, _)
+            | Self::Div(a, b, _)
+            | Self::Err(a, b, _) => (*a, *b),
+        }
+    }
+
     pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
         Ok(serde_json::to_string(self)?)
     }
@@ -164,3 +571,116 @@ impl ServerResponse {
         }
     }
 }
+
+impl BatchRequest {
+    pub fn new(requests: Vec<ServerRequest>, atomic: bool) -> Self {
+        Self {
+            requests,
+            atomic,
+            resource: String::new(),
+            id: 0,
+        }
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(token: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(token)?)
+    }
+
+    /// Executes every request in order. When the batch is atomic and any request fails, the
+    /// whole batch is rolled back and every item is answered with an error.
+    pub fn to_response(&self) -> BatchResponse {
+        let responses = self
+            .requests
+            .iter()
+            .map(ServerRequest::to_response)
+            .collect::<Vec<_>>();
+
+        if !self.atomic || !responses.iter().any(ServerResponse::is_err) {
+            return BatchResponse(responses);
+        }
+
+        BatchResponse(
+            responses
+                .into_iter()
+                .map(|response| match response {
+                    ServerResponse::Err(..) => response,
+                    _ => {
+                        let (a, b) = response.operands();
+                        ServerResponse::Err(a, b, cformat!("The operation on <bold>{a}</bold> and <bold>{b}</bold> was <bold>rolled back</bold> because the batch failed."))
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn print(&self) {
+        log::info(&cformat!(
+            "Asking the server to perform a <bold>batch</bold> of <bold>{}</bold> operations.",
+            self.requests.len()
+        ));
+        for request in &self.requests {
+            request.print();
+        }
+    }
+}
+
+impl ResourceRequest {
+    pub fn new(resource: &str, request: ServerRequest) -> Self {
+        Self {
+            resource: resource.to_string(),
+            request,
+            access: Access::Exclusive,
+            id: 0,
+        }
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(request: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(request)?)
+    }
+}
+
+impl BatchResponse {
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(token: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(token)?)
+    }
+
+    pub fn print(&self) {
+        for response in &self.0 {
+            response.print();
+        }
+    }
+}
+
+impl Reply {
+    pub fn new(node_id: NodeId, id: u64, responses: Vec<ServerResponse>) -> Self {
+        Self {
+            node_id,
+            id,
+            responses,
+        }
+    }
+
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(token: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(token)?)
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
@@ -1,23 +1,112 @@
+use crate::config::Algorithm;
+use crate::lock::{LocalRequests, LockRequest, RingLock};
+use crate::submission::{self, SubmissionRx, Submitted};
 use crate::*;
-use futures::{SinkExt, StreamExt};
+use color_print::cformat;
+use futures::{stream::SplitSink, SinkExt, StreamExt};
 use rand::{rng, RngCore};
-use std::{collections::VecDeque, error::Error, sync::Arc, time::Duration};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    error::Error,
+    io,
+    sync::{
+        self,
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::{Mutex, Notify},
-    time::sleep,
+    sync::{mpsc, oneshot, watch, Mutex, Notify},
+    task::{JoinHandle, JoinSet},
+    time::{sleep, sleep_until, timeout, timeout_at, Instant},
 };
-use tokio_util::codec::{Framed, LinesCodec};
+use tokio_util::sync::CancellationToken;
 
 pub type RequestQueue = VecDeque<ServerRequest>;
 
+/// Time a token of a new epoch waits for the server to announce the epoch.
+const EPOCH_ANNOUNCEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Window the rotation rate of [`Peer::rotation_rate`] is measured over.
+const ROTATION_WINDOW: Duration = Duration::from_secs(5);
+
+type ResponseTx = oneshot::Sender<ServerResponse>;
+
+/// Where the responses to the requests sent go, by the id of the request or batch they were
+/// sent in. The peer's own requests have no one waiting for them.
+#[derive(Clone, Default)]
+struct PendingResponses {
+    last_id: Arc<AtomicU64>,
+    waiting: Arc<sync::Mutex<HashMap<u64, Vec<Option<ResponseTx>>>>>,
+}
+
+type ServerWriter = SplitSink<Connection, String>;
+
+#[derive(Clone, Debug, Default)]
+pub enum RequestMode {
+    /// Throw the hot potato right after the requests are sent.
+    #[default]
+    FireAndForget,
+    /// Wait for every response (or the timeout) before throwing the hot potato.
+    Pipelined { timeout: Duration },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Batching {
+    /// Send one line per request.
+    #[default]
+    Disabled,
+    /// Send a single batch per visit, failures are reported per item.
+    PartialFailure,
+    /// Send a single batch per visit that the server rolls back if any item fails.
+    AllOrNothing,
+}
+
+/// Token and request queue of a named resource, the default resource uses the peer's own.
+#[derive(Clone, Debug)]
+pub struct NamedResource {
+    pub hot_potato_state: HotPotatoState,
+    pub request_queue: RequestQueue,
+}
+
 #[derive(Clone)]
 pub struct Peer {
+    pub node_id: NodeId,
     pub address: String,
     pub server_address: String,
     pub next_peer_address: String,
+    /// Accepts requests submitted by clients on this address when set.
+    pub submission_address: Option<String>,
     pub hot_potato_state: HotPotatoState,
     pub request_queue: RequestQueue,
+    /// Named resources, added when their token first arrives or requests are queued for them.
+    pub resources: BTreeMap<String, NamedResource>,
+    pub hold_policy: HoldPolicy,
+    pub priority_policy: PriorityPolicy,
+    pub pacing_policy: PacingPolicy,
+    pub algorithm: Algorithm,
+    pub request_mode: RequestMode,
+    pub batching: Batching,
+    pub workload: Workload,
+    /// Seeds the workload, a random seed is drawn when unset.
+    pub seed: Option<[u8; 32]>,
+    pub recorder: EventRecorder,
+    pub token_guard: TokenGuard,
+    /// Ids learned during the handshakes, `None` until connected.
+    pub server_id: Option<NodeId>,
+    pub previous_peer_id: Option<NodeId>,
+    pub next_peer_id: Option<NodeId>,
+    /// Critical sections entered so far.
+    pub visits: u64,
+    /// When the default hot potato arrived over the last rotation window.
+    pub arrivals: VecDeque<Instant>,
+    /// Set when the server evicted the peer from the ring.
+    pub evicted: bool,
+    /// Requests and locks of the application embedding the peer, see [`Peer::ring_lock`].
+    pub local_requests: LocalRequests,
 }
 
 impl Peer {
@@ -25,214 +114,1422 @@ impl Peer {
         let mut rng = rand::rng();
 
         Self {
+            node_id: NodeId::generate(),
             address,
             server_address,
             next_peer_address,
+            submission_address: None,
             hot_potato_state: HotPotatoState::NotHolding,
             request_queue: RequestQueue::from([ServerRequest::generate(&mut rng)]),
+            resources: BTreeMap::new(),
+            hold_policy: HoldPolicy::default(),
+            priority_policy: PriorityPolicy::default(),
+            pacing_policy: PacingPolicy::default(),
+            algorithm: Algorithm::default(),
+            request_mode: RequestMode::default(),
+            batching: Batching::default(),
+            workload: Workload::default(),
+            seed: None,
+            recorder: EventRecorder::default(),
+            token_guard: TokenGuard::default(),
+            server_id: None,
+            previous_peer_id: None,
+            next_peer_id: None,
+            visits: 0,
+            arrivals: VecDeque::new(),
+            evicted: false,
+            local_requests: LocalRequests::new(),
+        }
+    }
+
+    /// A handle to use the ring as a lock from this peer, valid across its reconnections.
+    pub fn ring_lock(&self) -> RingLock {
+        self.local_requests.handle("")
+    }
+
+    /// Same as [`Peer::ring_lock`] for the token of a named resource.
+    pub fn resource_lock(&self, resource: &str) -> RingLock {
+        self.local_requests.handle(resource)
+    }
+
+    /// Requests waiting for the token of `resource`, the default one when empty.
+    pub fn request_queue_mut(&mut self, resource: &str) -> &mut RequestQueue {
+        if resource.is_empty() {
+            return &mut self.request_queue;
+        }
+        &mut self.named_resource(resource).request_queue
+    }
+
+    pub fn hot_potato_state_mut(&mut self, resource: &str) -> &mut HotPotatoState {
+        if resource.is_empty() {
+            return &mut self.hot_potato_state;
+        }
+        &mut self.named_resource(resource).hot_potato_state
+    }
+
+    /// The resources whose token the peer holds, the default one first.
+    pub fn held_resources(&self) -> Vec<String> {
+        let default = matches!(self.hot_potato_state, HotPotatoState::Holding(_)).then(String::new);
+        let named = self
+            .resources
+            .iter()
+            .filter(|(_, named)| matches!(named.hot_potato_state, HotPotatoState::Holding(_)))
+            .map(|(resource, _)| resource.clone());
+
+        default.into_iter().chain(named).collect()
+    }
+
+    fn named_resource(&mut self, resource: &str) -> &mut NamedResource {
+        self.resources
+            .entry(resource.to_string())
+            .or_insert_with(|| NamedResource {
+                hot_potato_state: HotPotatoState::NotHolding,
+                request_queue: RequestQueue::new(),
+            })
+    }
+
+    /// What the peer reports to the server.
+    pub fn status(&self, paused: bool) -> PeerStatus {
+        let holding = match &self.hot_potato_state {
+            HotPotatoState::Holding(hot_potato) => Some((hot_potato.epoch, hot_potato.sequence)),
+            HotPotatoState::NotHolding => None,
+        };
+
+        PeerStatus {
+            node_id: self.node_id.clone(),
+            previous_peer_id: self.previous_peer_id.clone(),
+            next_peer_id: self.next_peer_id.clone(),
+            holding,
+            paused,
+            queue_length: self.request_queue.len(),
+            visits: self.visits,
+            rotation_rate: self.rotation_rate(),
+            resources: self
+                .held_resources()
+                .into_iter()
+                .filter(|resource| !resource.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Rotations of the default hot potato per second, each one brings it here once.
+    pub fn rotation_rate(&self) -> f64 {
+        let rotations = self
+            .arrivals
+            .iter()
+            .filter(|arrival| arrival.elapsed() < ROTATION_WINDOW)
+            .count();
+        rotations as f64 / ROTATION_WINDOW.as_secs_f64()
+    }
+
+    /// Takes hold of the hot potato, unless the token guard rejects it.
+    pub fn receive_hot_potato(&mut self, hot_potato: HotPotato) -> Result<(), TokenRejection> {
+        if let Err(rejection) = self.token_guard.check(&hot_potato, &self.node_id) {
+            log::error(&cformat!(
+                "Rejected a <yellow, bold>hot potato</yellow, bold>{} of epoch <bold>{}</bold> and sequence <bold>{}</bold>: {rejection}.",
+                of_resource(&hot_potato.resource),
+                hot_potato.epoch,
+                hot_potato.sequence
+            ));
+            self.recorder
+                .record(EventKind::TokenRejected(rejection.to_string()));
+            return Err(rejection);
+        }
+
+        self.recorder.record(EventKind::token_received(
+            &hot_potato.resource,
+            hot_potato.epoch,
+            hot_potato.sequence,
+        ));
+        let resource = hot_potato.resource.clone();
+        *self.hot_potato_state_mut(&resource) = HotPotatoState::Holding(Box::new(hot_potato));
+
+        if resource.is_empty() {
+            let now = Instant::now();
+            while self
+                .arrivals
+                .front()
+                .is_some_and(|arrival| now.duration_since(*arrival) >= ROTATION_WINDOW)
+            {
+                self.arrivals.pop_front();
+            }
+            self.arrivals.push_back(now);
+        }
+
+        Ok(())
+    }
+
+    /// Takes hold of `hot_potato` once the server announced its epoch: the first peers a new
+    /// token reaches may get it before the announcement.
+    async fn receive_announced_hot_potato(
+        current_peer: &Mutex<Self>,
+        hot_potato: HotPotato,
+        mut epoch_rx: watch::Receiver<u64>,
+        holding_hot_potato_notify: &Notify,
+    ) {
+        let epoch = hot_potato.epoch;
+        let _ = timeout(
+            EPOCH_ANNOUNCEMENT_TIMEOUT,
+            epoch_rx.wait_for(|announced| *announced >= epoch),
+        )
+        .await;
+
+        if current_peer
+            .lock()
+            .await
+            .receive_hot_potato(hot_potato)
+            .is_ok()
+        {
+            holding_hot_potato_notify.notify_one();
         }
     }
 
     pub async fn handle_previous_peer(
-        previous_peer_stream: TcpStream,
+        mut previous_peer_lines: Connection,
         current_peer_server: Arc<Mutex<Self>>,
         holding_hot_potato_notify: Arc<Notify>,
+        demand_tx: mpsc::UnboundedSender<Demand>,
+        epoch_rx: watch::Receiver<u64>,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut previous_peer_lines = Framed::new(previous_peer_stream, LinesCodec::new());
+        while let Some(line) = previous_peer_lines.next().await {
+            if let Ok(hot_potato_string) = line {
+                if let Ok(demand) = Demand::from_json_string(&hot_potato_string) {
+                    let _ = demand_tx.send(demand);
+                    continue;
+                }
 
-        loop {
-            if let Some(Ok(hot_potato_string)) = previous_peer_lines.next().await {
                 if let Ok(hot_potato) = HotPotato::from_json_string(&hot_potato_string) {
-                    // get hold of hot potato
-                    {
-                        let mut current_peer_server = current_peer_server.lock().await;
-                        current_peer_server.hot_potato_state = HotPotatoState::Holding(hot_potato);
-                        holding_hot_potato_notify.notify_one();
-                    }
-
-                    /*log::debug(&cformat!(
-                        "Currently holding <yellow, bold>hot potato</yellow, bold>"
-                    )); */
+                    Self::receive_announced_hot_potato(
+                        &current_peer_server,
+                        hot_potato,
+                        epoch_rx.clone(),
+                        &holding_hot_potato_notify,
+                    )
+                    .await;
                 }
             }
         }
+
+        Err("The previous peer closed the connection.".into())
+    }
+
+    async fn accept_previous_peer<L: Listener>(
+        listener: &mut L,
+        hello: &Hello,
+    ) -> Result<(Connection, Hello), Box<dyn Error + Send + Sync>> {
+        let (mut lines, _address) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Failed to accept the previous peer's connection: {e}"))?;
+        let previous_peer = answer(&mut lines, hello, listener.authenticates())
+            .await
+            .map_err(|e| format!("Handshake with the previous peer failed: {e}"))?;
+
+        Ok((lines, previous_peer))
+    }
+
+    async fn connect_next_peer<T: Transport>(
+        transport: &T,
+        address: &str,
+        hello: &Hello,
+    ) -> Result<(Connection, Hello), Box<dyn Error + Send + Sync>> {
+        let mut lines = transport
+            .connect(address)
+            .await
+            .map_err(|_| "Couldn't connect to the next peer.")?;
+        let next_peer = greet(&mut lines, hello, transport.authenticates())
+            .await
+            .map_err(|e| format!("Handshake with the next peer failed: {e}"))?;
+
+        Ok((lines, next_peer))
     }
 
     pub async fn run(&mut self) {
-        let mut rng = rng();
+        self.run_with(&Tcp).await
+    }
 
-        // client connections
-        let server_stream = match TcpStream::connect(&self.server_address).await {
-            Ok(stream) => stream,
+    pub async fn run_with<T: Transport>(&mut self, transport: &T) {
+        // open a server for previous Peer to connect
+        let previous_peer_listener = match self.bind(transport).await {
+            Ok(listener) => listener,
             Err(_) => {
-                log::error("Failed to connect to the server.");
+                log::error("Couldn't open connection for the previous peer.");
                 return;
             }
         };
-        let mut server_lines = Framed::new(server_stream, LinesCodec::new());
 
-        // open a server for previous Peer to connect
-        let previous_peer_listener = match TcpListener::bind(&self.address).await {
-            Ok(listener) => listener,
+        let submission_listener = match &self.submission_address {
+            Some(address) => match transport.bind(address).await {
+                Ok(listener) => Some(listener),
+                Err(e) => {
+                    log::error(&format!("Couldn't accept submissions on {address}: {e}"));
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        self.serve_with_submissions(
+            transport,
+            previous_peer_listener,
+            submission_listener,
+            CancellationToken::new(),
+        )
+        .await
+    }
+
+    /// Binds the listener for the previous peer and updates `address` to the bound address.
+    pub async fn bind<T: Transport>(&mut self, transport: &T) -> io::Result<T::Listener> {
+        let listener = transport.bind(&self.address).await?;
+        self.address = listener.local_address()?;
+
+        Ok(listener)
+    }
+
+    /// Runs the peer with an already bound listener until `shutdown` is cancelled or a thread
+    /// fails.
+    pub async fn serve<T: Transport>(
+        &mut self,
+        transport: &T,
+        previous_peer_listener: T::Listener,
+        shutdown: CancellationToken,
+    ) {
+        self.serve_with_submissions(transport, previous_peer_listener, None, shutdown)
+            .await
+    }
+
+    /// Same as [`Peer::serve`], also queuing the requests clients submit on
+    /// `submission_listener`. They are sent before the peer's own requests.
+    pub async fn serve_with_submissions<T: Transport>(
+        &mut self,
+        transport: &T,
+        mut previous_peer_listener: T::Listener,
+        submission_listener: Option<T::Listener>,
+        shutdown: CancellationToken,
+    ) {
+        let seed = self.seed.unwrap_or_else(|| {
+            let mut seed = [0u8; 32];
+            rng().fill_bytes(&mut seed);
+            seed
+        });
+
+        let mut workload_generator = match self.workload.generator(&self.address, &seed) {
+            Ok(generator) => generator,
+            Err(e) => {
+                log::error(&format!("Couldn't load the workload: {e}"));
+                return;
+            }
+        };
+
+        let hello = Hello::new(self.node_id.clone(), self.address.clone());
+
+        // client connections
+        let mut server_lines = match transport.connect(&self.server_address).await {
+            Ok(lines) => lines,
             Err(_) => {
-                log::error("Couldn't open connection for the previous peer.");
+                log::error("Failed to connect to the server.");
                 return;
             }
         };
+        match greet(&mut server_lines, &hello, transport.authenticates()).await {
+            Ok(server) => {
+                log::info(&cformat!(
+                    "Joined server <bold>{}</bold> as <bold>{}</bold>.",
+                    server.node_id,
+                    self.node_id
+                ));
+                self.server_id = Some(server.node_id);
+            }
+            Err(e) => {
+                log::error(&format!("Handshake with the server failed: {e}"));
+                return;
+            }
+        }
 
         // receive starting flag
-        let _ = match server_lines.next().await {
-            Some(Ok(line))
-                if matches!(
-                    StartFlag::from_json_string(&line).expect("(StartFlag) Shouldn't fail."),
-                    StartFlag(_)
-                ) => {}
-            _ => {
-                log::error("Couldn't receive the starting flag from the server.");
-            }
+        let starting_flag = tokio::select! {
+            line = server_lines.next() => line,
+            _ = shutdown.cancelled() => return,
+        };
+        let Some(Ok(start_flag)) =
+            starting_flag.map(|line| StartFlag::from_json_string(&line.unwrap_or_default()))
+        else {
+            log::error("Couldn't receive the starting flag from the server.");
+            return;
         };
+        self.token_guard.epoch = start_flag.epoch;
 
-        // create a thread-safe state instance
-        let current_peer = Arc::new(Mutex::new(self.clone()));
+        // both neighbours handshake at the same time, waiting for one before the other would
+        // deadlock the ring
+        let (previous_peer, next_peer) = tokio::select! {
+            handshakes = async {
+                tokio::join!(
+                    Self::accept_previous_peer(&mut previous_peer_listener, &hello),
+                    Self::connect_next_peer(transport, &self.next_peer_address, &hello),
+                )
+            } => handshakes,
+            _ = shutdown.cancelled() => return,
+        };
+        let (previous_peer_lines, mut next_peer_lines) = match (previous_peer, next_peer) {
+            (Ok((previous_peer_lines, previous_peer)), Ok((next_peer_lines, next_peer))) => {
+                log::info(&cformat!(
+                    "Ring position: <bold>{}</bold> -> <bold>{}</bold> -> <bold>{}</bold>.",
+                    previous_peer.node_id,
+                    self.node_id,
+                    next_peer.node_id
+                ));
+                self.previous_peer_id = Some(previous_peer.node_id);
+                self.next_peer_id = Some(next_peer.node_id);
 
-        // connect to the next Peer's server
-        let next_peer_stream = match TcpStream::connect(&self.next_peer_address).await {
-            Ok(stream) => stream,
-            Err(_) => {
-                log::error("Couldn't connect to the next peer.");
+                (previous_peer_lines, next_peer_lines)
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                log::error(&format!("{e}"));
                 return;
             }
         };
 
+        // create a thread-safe state instance
+        let current_peer = Arc::new(Mutex::new(self.clone()));
+
         let holding_hot_potato_notify = Arc::new(Notify::new());
-        let (mut server_writer, mut server_reader) = server_lines.split::<String>();
+        let (server_writer, mut server_reader) = server_lines.split::<String>();
+        let server_writer = Arc::new(Mutex::new(server_writer));
+        let (response_tx, mut response_rx) = mpsc::unbounded_channel::<(u64, usize)>();
+        let (paused_tx, mut paused_rx) = watch::channel(false);
+        let (rewire_tx, mut rewire_rx) = mpsc::unbounded_channel::<String>();
+        let (epoch_tx, epoch_rx) = watch::channel(self.token_guard.epoch);
+        let pending_responses = PendingResponses::default();
+        let pipelined = matches!(self.request_mode, RequestMode::Pipelined { .. });
+        let recorder = self.recorder.clone();
 
         // thread that handles the server connection
-        let operation_server_thread = {
+        let mut operation_server_thread = {
             let current_peer = Arc::clone(&current_peer);
             let holding_hot_potato_notify = holding_hot_potato_notify.clone();
+            let server_writer = server_writer.clone();
+            let pending_responses = pending_responses.clone();
+            let epoch_rx = epoch_rx.clone();
+            let own_id = self.node_id.clone();
 
             tokio::spawn(async move {
-                loop {
-                    if let Some(Ok(msg)) = server_reader.next().await {
-                        if let Ok(hot_potato) = HotPotato::from_json_string(&msg) {
-                            let mut current_peer = current_peer.lock().await;
+                while let Some(line) = server_reader.next().await {
+                    if let Ok(msg) = line {
+                        if let Ok(control) = Control::from_json_string(&msg) {
+                            match control {
+                                // answered aside, the peer is locked during critical sections
+                                Control::Report => {
+                                    let current_peer = current_peer.clone();
+                                    let server_writer = server_writer.clone();
+                                    let paused = *paused_tx.borrow();
 
-                            current_peer.hot_potato_state = HotPotatoState::Holding(hot_potato);
-                            holding_hot_potato_notify.notify_one();
+                                    tokio::spawn(async move {
+                                        let status = current_peer.lock().await.status(paused);
+                                        if let Ok(status) = status.to_json_string() {
+                                            let _ = server_writer.lock().await.send(status).await;
+                                        }
+                                    });
+                                }
+                                Control::Pause => {
+                                    log::warning("The server paused the ring.");
+                                    paused_tx.send_replace(true);
+                                }
+                                Control::Resume => {
+                                    log::info("The server resumed the ring.");
+                                    paused_tx.send_replace(false);
+                                }
+                                Control::NewEpoch(epoch) => {
+                                    log::warning(&cformat!(
+                                        "The server replaced the <yellow, bold>hot potato</yellow, bold>, new epoch <bold>{epoch}</bold>."
+                                    ));
+                                    let current_peer = current_peer.clone();
+                                    let epoch_tx = epoch_tx.clone();
 
-                            /*log::debug(&cformat!(
-                                "Currently holding <yellow, bold>hot potato</yellow, bold>"
-                            ));*/
+                                    tokio::spawn(async move {
+                                        let mut current_peer = current_peer.lock().await;
+                                        current_peer.token_guard.epoch = epoch;
+                                        for resource in current_peer.held_resources() {
+                                            let state =
+                                                current_peer.hot_potato_state_mut(&resource);
+                                            if matches!(state, HotPotatoState::Holding(hot_potato) if hot_potato.epoch != epoch)
+                                            {
+                                                *state = HotPotatoState::NotHolding;
+                                            }
+                                        }
+                                        epoch_tx.send_replace(epoch);
+                                    });
+                                }
+                                Control::Rewire { next_peer_address } => {
+                                    let _ = rewire_tx.send(next_peer_address);
+                                }
+                                Control::Evict => {
+                                    log::warning("Evicted from the ring by the server.");
+                                    return true;
+                                }
+                            }
+                            continue;
                         }
 
-                        if let Ok(operation_response) = ServerResponse::from_json_string(&msg) {
-                            operation_response.print();
+                        // the server sends a new epoch's tokens right after announcing it
+                        if let Ok(hot_potato) = HotPotato::from_json_string(&msg) {
+                            let current_peer = current_peer.clone();
+                            let epoch_rx = epoch_rx.clone();
+                            let holding_hot_potato_notify = holding_hot_potato_notify.clone();
+
+                            tokio::spawn(async move {
+                                Self::receive_announced_hot_potato(
+                                    &current_peer,
+                                    hot_potato,
+                                    epoch_rx,
+                                    &holding_hot_potato_notify,
+                                )
+                                .await;
+                            });
+                        }
+
+                        if let Ok(reply) = Reply::from_json_string(&msg) {
+                            if reply.node_id != own_id {
+                                log::warning(&cformat!(
+                                    "Dropped the responses the server meant for <bold>{}</bold>.",
+                                    reply.node_id
+                                ));
+                                continue;
+                            }
+
+                            for operation_response in &reply.responses {
+                                operation_response.print();
+                                recorder.record(EventKind::ResponseReceived(
+                                    operation_response.clone(),
+                                ));
+                            }
+                            if pipelined {
+                                let _ = response_tx.send((reply.id, reply.responses.len()));
+                            }
+                            // pass the responses to the requests clients submitted back to them
+                            pending_responses.respond(reply.id, reply.responses);
                         }
                     }
                 }
+
+                false
             })
         };
 
-        // open server connection for previous peer to join
-        let previous_peer_thread = {
+        // demands for the tokens coming from the previous peers
+        let (demand_tx, mut demand_rx) = mpsc::unbounded_channel();
+
+        // keep accepting previous peers, the server rewires the ring when it evicts one
+        let mut previous_peer_thread = {
             let current_peer = Arc::clone(&current_peer);
             let holding_hot_potato_notify = holding_hot_potato_notify.clone();
+            let hello = hello.clone();
+            let epoch_rx = epoch_rx.clone();
 
             tokio::spawn(async move {
-                let (previous_peer_stream, _previous_peer_address) = previous_peer_listener
-                    .accept()
-                    .await
-                    .expect("Failed to accept the previous peer's connection.");
-
-                if let Err(e) = Self::handle_previous_peer(
-                    previous_peer_stream,
-                    current_peer,
-                    holding_hot_potato_notify,
-                )
-                .await
-                {
-                    log::error(&format!("{e}"));
-                };
+                let mut handlers = JoinSet::new();
+                handlers.spawn(Self::handle_previous_peer(
+                    previous_peer_lines,
+                    current_peer.clone(),
+                    holding_hot_potato_notify.clone(),
+                    demand_tx.clone(),
+                    epoch_rx.clone(),
+                ));
+
+                loop {
+                    tokio::select! {
+                        accepted = previous_peer_listener.accept() => {
+                            let Ok((mut lines, _address)) = accepted else {
+                                log::error("Couldn't accept previous peers anymore.");
+                                return;
+                            };
+                            let authenticated = previous_peer_listener.authenticates();
+                            match answer(&mut lines, &hello, authenticated).await {
+                                Ok(previous_peer) => {
+                                    log::info(&cformat!(
+                                        "<bold>{}</bold> is the previous peer now.",
+                                        previous_peer.node_id
+                                    ));
+                                    current_peer.lock().await.previous_peer_id =
+                                        Some(previous_peer.node_id);
+                                    handlers.spawn(Self::handle_previous_peer(
+                                        lines,
+                                        current_peer.clone(),
+                                        holding_hot_potato_notify.clone(),
+                                        demand_tx.clone(),
+                                        epoch_rx.clone(),
+                                    ));
+                                }
+                                Err(e) => {
+                                    log::error(&format!("Handshake with a previous peer failed: {e}"))
+                                }
+                            }
+                        }
+                        Some(result) = handlers.join_next() => {
+                            if let Ok(Err(e)) = result {
+                                log::warning(&format!("{e}"));
+                            }
+                        }
+                    }
+                }
             })
         };
 
-        let calc_then_throw_hot_potato_thread = {
+        let mut calc_then_throw_hot_potato_thread = {
             let current_peer = Arc::clone(&current_peer);
             let holding_hot_potato_notify = holding_hot_potato_notify.clone();
-
-            let mut next_peer_lines = Framed::new(next_peer_stream, LinesCodec::new());
+            let transport = transport.clone();
+            let hello = hello.clone();
+            let local_requests = self.local_requests.clone();
 
             tokio::spawn(async move {
+                let mut waiting = Waiting::default();
+                let mut local_requests = local_requests.receivers().await;
+                let (submission_rx, lock_rx) = &mut *local_requests;
+
+                // demands of other peers to add to the tokens held, and the resources this
+                // peer sent a demand for since its last visit
+                let mut demands = BTreeMap::<String, Vec<Demand>>::new();
+                let mut demanded = BTreeMap::<String, Priority>::new();
+                // visits in a row each resource's low-priority work was deferred
+                let mut deferrals = BTreeMap::<String, usize>::new();
+                // when the tokens kept by the pacing policy are thrown on, and the ones whose
+                // delay is over
+                let mut paced = BTreeMap::<String, Instant>::new();
+                let mut due = BTreeSet::new();
+
+                // read leases, given back at the first visit after their reads are done
+                let mut leases = BTreeMap::<String, JoinHandle<()>>::new();
+                let forwarder = Forwarder {
+                    server_writer: server_writer.clone(),
+                    pending_responses: pending_responses.clone(),
+                    recorder: current_peer.lock().await.recorder.clone(),
+                };
+
+                // tokens lent to a local lock, thrown once their guard is dropped
+                let (released_tx, mut released_rx) = mpsc::unbounded_channel::<HotPotato>();
+                let mut lent = BTreeSet::new();
+
                 loop {
-                    holding_hot_potato_notify.notified().await;
-                    {
-                        let mut current_peer = current_peer.lock().await;
-                        if let HotPotatoState::Holding(hot_potato) = &current_peer.hot_potato_state
+                    let mut thrown = Vec::new();
+                    let next_hop = paced.values().min().copied();
+                    tokio::select! {
+                        _ = holding_hot_potato_notify.notified() => {}
+                        _ = sleep_until(next_hop.unwrap_or_else(Instant::now)), if next_hop.is_some() => {}
+                        Some(hot_potato) = released_rx.recv() => thrown.push((hot_potato, true)),
+                        Some(submission) = submission_rx.recv() => {
+                            waiting.submit(submission, &forwarder.recorder);
+                        }
+                        Some(lock) = lock_rx.recv() => waiting.lock(lock),
+                        Some(demand) = demand_rx.recv() => {
+                            let current_peer = current_peer.lock().await;
+                            // the demand went around without finding the token, it's sent
+                            // again below if still needed
+                            if demand.node_id == current_peer.node_id {
+                                demanded.remove(&demand.resource);
+                            } else if lent.contains(&demand.resource)
+                                || current_peer.held_resources().contains(&demand.resource)
+                            {
+                                demands.entry(demand.resource.clone()).or_default().push(demand);
+                            } else if let Ok(line) = demand.to_json_string() {
+                                next_peer_lines
+                                    .send(line)
+                                    .await
+                                    .expect("Couldn't send demand to next peer.");
+                            }
+                        }
+                    }
+
+                    // hold the hot potato while the server paused the ring
+                    let _ = paused_rx.wait_for(|paused| !*paused).await;
+
+                    while let Ok(address) = rewire_rx.try_recv() {
+                        match Self::connect_next_peer(&transport, &address, &hello).await {
+                            Ok((lines, next_peer)) => {
+                                log::info(&cformat!(
+                                    "Throwing to <bold>{}</bold> listening on <bold>{}</bold> from now on.",
+                                    next_peer.node_id,
+                                    address
+                                ));
+                                next_peer_lines = lines;
+
+                                let mut current_peer = current_peer.lock().await;
+                                current_peer.next_peer_address = address;
+                                current_peer.next_peer_id = Some(next_peer.node_id);
+                            }
+                            Err(e) => log::error(&format!("{e}")),
+                        }
+                    }
+
+                    let mut current_peer = current_peer.lock().await;
+
+                    while let Ok(submission) = submission_rx.try_recv() {
+                        waiting.submit(submission, &current_peer.recorder);
+                    }
+                    while let Ok(lock) = lock_rx.try_recv() {
+                        waiting.lock(lock);
+                    }
+                    let on_demand = current_peer.algorithm == Algorithm::OnDemand;
+                    let pulls = on_demand || current_peer.pacing_policy.is_enabled();
+
+                    // ask the holders for the tokens of the work waiting here, high-priority
+                    // work only unless the ring is demand-driven or paced
+                    let mut wanted = waiting.wanted();
+                    if !current_peer.request_queue.is_empty() {
+                        wanted.entry(String::new()).or_default();
+                    }
+                    for (resource, named) in &current_peer.resources {
+                        if !named.request_queue.is_empty() {
+                            wanted.entry(resource.clone()).or_default();
+                        }
+                    }
+                    let held = current_peer.held_resources();
+                    // a new epoch may have replaced a paced token
+                    paced.retain(|resource, _| held.contains(resource));
+                    for (resource, priority) in wanted {
+                        if !(pulls || priority == Priority::High)
+                            || lent.contains(&resource)
+                            || held.contains(&resource)
+                            || demanded
+                                .get(&resource)
+                                .is_some_and(|sent| *sent >= priority)
                         {
-                            if let Ok(hot_potato_string) = hot_potato.to_json_string() {
-                                // send all operations request to server
-                                while let Some(operation_request) =
-                                    current_peer.request_queue.pop_front()
+                            continue;
+                        }
+                        let demand = Demand::new(current_peer.node_id.clone(), &resource, priority);
+                        if let Ok(line) = demand.to_json_string() {
+                            next_peer_lines
+                                .send(line)
+                                .await
+                                .expect("Couldn't send demand to next peer.");
+                        }
+                        demanded.insert(resource, priority);
+                    }
+
+                    // the tokens held are visited one after the other, each guarding its own
+                    // resource
+                    for resource in current_peer.held_resources() {
+                        if lent.contains(&resource) {
+                            continue;
+                        }
+                        let HotPotatoState::Holding(hot_potato) =
+                            current_peer.hot_potato_state_mut(&resource).clone()
+                        else {
+                            continue;
+                        };
+                        let mut hot_potato = *hot_potato;
+                        let node_id = current_peer.node_id.clone();
+
+                        // a paced token waits for its delay, unless it's wanted meanwhile
+                        if let Some(next_hop) = paced.get(&resource) {
+                            let wanted = demands.contains_key(&resource)
+                                || waiting.writing(&resource)
+                                || waiting.reading(&resource)
+                                || !current_peer.request_queue_mut(&resource).is_empty();
+                            if !wanted && *next_hop > Instant::now() {
+                                continue;
+                            }
+                            paced.remove(&resource);
+                            if !wanted {
+                                due.insert(resource);
+                                thrown.push((hot_potato, false));
+                                continue;
+                            }
+                        }
+
+                        // a token of a new epoch doesn't list the lease anymore
+                        if leases.get(&resource).is_some_and(JoinHandle::is_finished) {
+                            hot_potato.readers.retain(|reader| *reader != node_id);
+                            leases.remove(&resource);
+                        }
+                        demanded.remove(&resource);
+                        add_demands(&mut hot_potato, demands.remove(&resource));
+                        hot_potato.demand.retain(|demanding| *demanding != node_id);
+                        hot_potato.wanted_by.retain(|wanting| *wanting != node_id);
+
+                        let writing = waiting.writing(&resource)
+                            || !current_peer.request_queue_mut(&resource).is_empty();
+                        if !writing && hot_potato.waiting_writer.as_ref() == Some(&node_id) {
+                            hot_potato.waiting_writer = None;
+                        }
+
+                        // count the rotations the token made without work
+                        if writing || waiting.reading(&resource) {
+                            hot_potato.idle_since = None;
+                            hot_potato.idle_rotations = 0;
+                        } else if hot_potato.idle_since.is_none() {
+                            hot_potato.idle_since = Some(node_id.clone());
+                        } else if hot_potato.idle_since.as_ref() == Some(&node_id) {
+                            hot_potato.idle_rotations += 1;
+                        }
+
+                        // an idle peer passes the token on to the peers that want it, or keeps
+                        // it when the ring is demand-driven
+                        let pulled =
+                            !hot_potato.demand.is_empty() || !hot_potato.wanted_by.is_empty();
+                        if !writing && !waiting.reading(&resource) && (pulled || on_demand) {
+                            thrown.push((hot_potato, false));
+                            continue;
+                        }
+
+                        // pass the token on to the peers with high-priority work, a bounded
+                        // number of times
+                        if !hot_potato.demand.is_empty() && !waiting.urgent(&resource) {
+                            let deferred = deferrals.entry(resource.clone()).or_default();
+                            if current_peer.priority_policy.defers(*deferred) {
+                                *deferred += 1;
+                                thrown.push((hot_potato, false));
+                                continue;
+                            }
+                        }
+                        deferrals.remove(&resource);
+
+                        // readers share the resource until a writer waits for it
+                        if waiting.reading(&resource)
+                            && !leases.contains_key(&resource)
+                            && hot_potato.waiting_writer.is_none()
+                        {
+                            let (lease_tx, lease_rx) = mpsc::unbounded_channel();
+                            for submission in waiting.shared.remove(&resource).unwrap_or_default() {
+                                let _ = lease_tx.send(submission);
+                            }
+                            for lock in waiting.shared_locks.remove(&resource).unwrap_or_default() {
+                                // the caller may have stopped waiting
+                                let _ = lock.granted.send(lease_tx.clone());
+                            }
+                            drop(lease_tx);
+
+                            if !hot_potato.readers.contains(&node_id) {
+                                hot_potato.readers.push(node_id.clone());
+                            }
+                            current_peer
+                                .recorder
+                                .record(EventKind::SharedSectionEnter(resource.clone()));
+                            let reads =
+                                tokio::spawn(forwarder.clone().read(resource.clone(), lease_rx));
+                            leases.insert(resource.clone(), reads);
+                        }
+
+                        // a writer waits for the readers to give their leases back, and the
+                        // other writers for it
+                        if !hot_potato.readers.is_empty()
+                            || hot_potato
+                                .waiting_writer
+                                .as_ref()
+                                .is_some_and(|waiting_writer| *waiting_writer != node_id)
+                        {
+                            if writing && hot_potato.waiting_writer.is_none() {
+                                hot_potato.waiting_writer = Some(node_id);
+                            }
+                            thrown.push((hot_potato, false));
+                            continue;
+                        }
+                        hot_potato.waiting_writer = None;
+
+                        current_peer
+                            .recorder
+                            .record(EventKind::critical_section_enter(&resource));
+                        current_peer.visits += 1;
+
+                        // drop late responses from a previous visit
+                        while response_rx.try_recv().is_ok() {}
+
+                        // send operations request to server while the hold policy allows it
+                        let holding_since = Instant::now();
+                        let batching = current_peer.batching;
+                        let mut writer = server_writer.lock().await;
+                        let mut batch = Vec::new();
+                        let mut batch_responses = Vec::new();
+                        let mut sent = HashSet::new();
+                        let mut operations = 0;
+
+                        while current_peer.hold_policy.allows(
+                            &current_peer.node_id,
+                            operations,
+                            holding_since.elapsed(),
+                        ) {
+                            // submitted requests go first, high-priority ones before the others
+                            let submission = waiting
+                                .submitted
+                                .get_mut(&resource)
+                                .and_then(VecDeque::pop_front);
+                            let (operation_request, response) = match submission {
+                                Some(Submitted {
+                                    request, response, ..
+                                }) => (request, Some(response)),
+                                None => match current_peer.request_queue_mut(&resource).pop_front()
                                 {
-                                    operation_request.print();
-                                    server_writer
-                                        .send(
-                                            operation_request
-                                                .to_json_string()
-                                                .expect("Couldn't parse operation request."),
-                                        )
-                                        .await
-                                        .expect("Couldn't send operation request to server.");
+                                    Some(request) => (request, None),
+                                    None => break,
+                                },
+                            };
+                            operations += 1;
+
+                            if !matches!(batching, Batching::Disabled) {
+                                batch.push(operation_request);
+                                batch_responses.push(response);
+                                continue;
+                            }
+
+                            let id = pending_responses.register(vec![response]);
+                            sent.insert(id);
+                            operation_request.print();
+                            writer
+                                .feed(
+                                    request_line(
+                                        &resource,
+                                        Access::Exclusive,
+                                        operation_request,
+                                        id,
+                                    )
+                                    .expect("Couldn't parse operation request."),
+                                )
+                                .await
+                                .expect("Couldn't send operation request to server.");
+                        }
+
+                        if !batch.is_empty() {
+                            let id = pending_responses.register(batch_responses);
+                            sent.insert(id);
+                            let batch_request = BatchRequest {
+                                resource: resource.clone(),
+                                id,
+                                ..BatchRequest::new(
+                                    batch,
+                                    matches!(batching, Batching::AllOrNothing),
+                                )
+                            };
+
+                            batch_request.print();
+                            writer
+                                .feed(
+                                    batch_request
+                                        .to_json_string()
+                                        .expect("Couldn't parse batch request."),
+                                )
+                                .await
+                                .expect("Couldn't send batch request to server.");
+                        }
+                        writer
+                            .flush()
+                            .await
+                            .expect("Couldn't send operation request to server.");
+                        drop(writer);
+                        if operations > 0 && resource.is_empty() {
+                            current_peer
+                                .recorder
+                                .record(EventKind::RequestsSent(operations));
+                        }
+
+                        // keep the hot potato until the server answered every request
+                        if let RequestMode::Pipelined { timeout } = current_peer.request_mode {
+                            // the wait counts against the hold time as well
+                            let mut deadline = Instant::now() + timeout;
+                            if let Some(max_hold_time) = current_peer.hold_policy.max_hold_time {
+                                deadline = deadline.min(holding_since + max_hold_time);
+                            }
+                            let mut responses = 0;
+
+                            while !sent.is_empty() {
+                                match timeout_at(deadline, response_rx.recv()).await {
+                                    // replies to the locks and leases forwarded meanwhile are
+                                    // left out
+                                    Ok(Some((id, count))) => {
+                                        if sent.remove(&id) {
+                                            responses += count;
+                                        }
+                                    }
+                                    Ok(None) => break,
+                                    Err(_) => {
+                                        log::warning(&format!(
+                                            "Only received {responses} of {operations} responses before the timeout."
+                                        ));
+                                        break;
+                                    }
                                 }
+                            }
+                        }
 
-                                // sleep(Duration::from_secs(2)).await;
-                                next_peer_lines
-                                    .send(hot_potato_string)
-                                    .await
-                                    .expect("Couldn't send hot potato to next peer.");
+                        // lend the hot potato to a local lock until its guard is dropped, the
+                        // other tokens keep going around meanwhile
+                        let mut guarded = None;
+                        while let Some(lock) = waiting
+                            .locks
+                            .get_mut(&resource)
+                            .and_then(VecDeque::pop_front)
+                        {
+                            let (guarded_tx, rx) = mpsc::unbounded_channel();
+                            // the caller may have stopped waiting
+                            if lock.granted.send(guarded_tx).is_ok() {
+                                guarded = Some((rx, lock.hold_deadline));
+                                break;
                             }
                         }
-                        current_peer.hot_potato_state = HotPotatoState::NotHolding;
+                        let Some((guarded_rx, hold_deadline)) = guarded else {
+                            thrown.push((hot_potato, true));
+                            continue;
+                        };
+
+                        lent.insert(resource);
+                        tokio::spawn(forwarder.clone().lend(
+                            hot_potato,
+                            guarded_rx,
+                            hold_deadline,
+                            released_tx.clone(),
+                        ));
+                    }
+
+                    // demands that came during the visits are added to the tokens thrown
+                    while let Ok(demand) = demand_rx.try_recv() {
+                        if demand.node_id == current_peer.node_id {
+                            // sent again at the next wake up, the token is on its way here
+                            demanded.remove(&demand.resource);
+                        } else if lent.contains(&demand.resource)
+                            || thrown
+                                .iter()
+                                .any(|(hot_potato, _)| hot_potato.resource == demand.resource)
+                        {
+                            demands
+                                .entry(demand.resource.clone())
+                                .or_default()
+                                .push(demand);
+                        } else if let Ok(line) = demand.to_json_string() {
+                            next_peer_lines
+                                .send(line)
+                                .await
+                                .expect("Couldn't send demand to next peer.");
+                        }
+                    }
+
+                    for (mut hot_potato, entered) in thrown {
+                        let resource = hot_potato.resource.clone();
+                        lent.remove(&resource);
+
+                        add_demands(&mut hot_potato, demands.remove(&resource));
+                        let node_id = current_peer.node_id.clone();
+                        if waiting.urgent(&resource) && !hot_potato.demand.contains(&node_id) {
+                            hot_potato.demand.push(node_id.clone());
+                        }
+                        let working = waiting.writing(&resource)
+                            || waiting.reading(&resource)
+                            || !current_peer.request_queue_mut(&resource).is_empty();
+                        if on_demand && working && !hot_potato.wanted_by.contains(&node_id) {
+                            hot_potato.wanted_by.push(node_id);
+                        }
+
+                        if entered {
+                            current_peer
+                                .recorder
+                                .record(EventKind::critical_section_exit(&resource));
+                        }
+
+                        let pacing = &current_peer.pacing_policy;
+                        let delay = pacing.hop_delay(hot_potato.idle_rotations);
+                        let paces = !due.remove(&resource)
+                            && pacing.is_enabled()
+                            && !delay.is_zero()
+                            && !working;
+
+                        // the token rests here until a demand pulls it
+                        let state = current_peer.hot_potato_state_mut(&resource);
+                        let same = |held: &HotPotato| {
+                            held.epoch == hot_potato.epoch && held.sequence == hot_potato.sequence
+                        };
+                        if on_demand
+                            && hot_potato.demand.is_empty()
+                            && hot_potato.wanted_by.is_empty()
+                        {
+                            if matches!(state, HotPotatoState::Holding(held) if same(held)) {
+                                *state = HotPotatoState::Holding(Box::new(hot_potato));
+                            }
+                            continue;
+                        }
+
+                        // and the one nobody asked for waits before its next hop
+                        if paces && hot_potato.demand.is_empty() && hot_potato.wanted_by.is_empty()
+                        {
+                            if matches!(state, HotPotatoState::Holding(held) if same(held)) {
+                                *state = HotPotatoState::Holding(Box::new(hot_potato));
+                            }
+                            paced.insert(resource, Instant::now() + delay);
+                            continue;
+                        }
+
+                        let mut thrown_hot_potato = hot_potato
+                            .clone()
+                            .thrown_to(current_peer.next_peer_id.clone().unwrap_or_default());
+                        current_peer.token_guard.sign(&mut thrown_hot_potato);
+
+                        if let Ok(hot_potato_string) = thrown_hot_potato.to_json_string() {
+                            // record before throwing so the next peer can't receive it first
+                            current_peer.recorder.record(EventKind::token_sent(
+                                &resource,
+                                thrown_hot_potato.epoch,
+                                thrown_hot_potato.sequence,
+                            ));
+
+                            next_peer_lines
+                                .send(hot_potato_string)
+                                .await
+                                .expect("Couldn't send hot potato to next peer.");
+                        }
+
+                        // a new epoch may have replaced a token lent meanwhile
+                        let state = current_peer.hot_potato_state_mut(&resource);
+                        if matches!(state, HotPotatoState::Holding(held) if same(held)) {
+                            *state = HotPotatoState::NotHolding;
+                        }
                     }
                 }
             })
         };
 
-        let generate_potato_work_thread = {
+        let mut generate_potato_work_thread = {
             let current_peer = current_peer.clone();
-
-            let mut seed: [u8; 32] = [0u8; 32];
-            rng.fill_bytes(&mut seed);
-
-            let mut poisson_process = Poisson::new(RATE, &mut seed);
+            let holding_hot_potato_notify = holding_hot_potato_notify.clone();
 
             tokio::spawn(async move {
-                loop {
-                    sleep(Duration::from_secs_f64(
-                        poisson_process.time_for_next_event(),
-                    ))
-                    .await;
-                    current_peer
-                        .lock()
-                        .await
-                        .request_queue
-                        .push_back(ServerRequest::generate(&mut poisson_process.rng));
+                while let Some((delay, operation_requests)) = workload_generator.next_requests() {
+                    sleep(delay).await;
+
+                    let mut current_peer = current_peer.lock().await;
+                    for _ in &operation_requests {
+                        current_peer.recorder.record(EventKind::RequestQueued);
+                    }
+                    current_peer.request_queue.extend(operation_requests);
+                    // the new work pulls a resting or paced hot potato
+                    if current_peer.algorithm == Algorithm::OnDemand
+                        || current_peer.pacing_policy.is_enabled()
+                    {
+                        holding_hot_potato_notify.notify_one();
+                    }
                 }
             })
         };
 
-        if let Err(_) = operation_server_thread.await {
-            log::error("Operation Server Thread failded.");
+        let mut submission_thread = match submission_listener {
+            Some(listener) => {
+                match listener.local_address() {
+                    Ok(address) => log::info(&cformat!(
+                        "Accepting submitted requests on <bold>{}</bold>.",
+                        address
+                    )),
+                    Err(e) => log::warning(&format!("Couldn't read the submission address: {e}")),
+                }
+                tokio::spawn(submission::serve(
+                    listener,
+                    self.local_requests.submission_tx.clone(),
+                ))
+            }
+            None => tokio::spawn(async {}),
+        };
+        let mut submitting = true;
+
+        // stop as soon as a connection is lost or the peer is shut down
+        let mut generating = true;
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                result = &mut operation_server_thread => {
+                    match result {
+                        Ok(evicted) => self.evicted = evicted,
+                        Err(_) => log::error("Operation Server Thread failded."),
+                    }
+                    break;
+                }
+                result = &mut previous_peer_thread => {
+                    if result.is_err() {
+                        log::error("Previous Peer Thread failded.");
+                    }
+                    break;
+                }
+                result = &mut calc_then_throw_hot_potato_thread => {
+                    if result.is_err() {
+                        log::error("Calculate then Throw Hot Potato Thread failded.");
+                    }
+                    break;
+                }
+                result = &mut generate_potato_work_thread, if generating => {
+                    // a replayed trace can run out of requests, the peer keeps going without it
+                    if result.is_err() {
+                        log::error("Generate Potato Work Thread failded.");
+                        break;
+                    }
+                    generating = false;
+                }
+                _ = &mut submission_thread, if submitting => {
+                    // only stops accepting submissions, the peer keeps its place in the ring
+                    submitting = false;
+                }
+            }
+        }
+
+        operation_server_thread.abort();
+        previous_peer_thread.abort();
+        calc_then_throw_hot_potato_thread.abort();
+        generate_potato_work_thread.abort();
+        submission_thread.abort();
+
+        // a rejoining peer still spots the tokens replayed from before
+        self.token_guard = current_peer.lock().await.token_guard.clone();
+    }
+}
+
+/// Local requests and locks waiting for a token or a read lease, by resource, high-priority
+/// ones first.
+#[derive(Default)]
+struct Waiting {
+    submitted: BTreeMap<String, VecDeque<Submitted>>,
+    shared: BTreeMap<String, VecDeque<Submitted>>,
+    locks: BTreeMap<String, VecDeque<LockRequest>>,
+    shared_locks: BTreeMap<String, VecDeque<LockRequest>>,
+}
+
+impl Waiting {
+    /// Queues `submission` behind the ones of the same or a higher priority.
+    fn submit(&mut self, submission: Submitted, recorder: &EventRecorder) {
+        if submission.resource.is_empty() && submission.access.is_exclusive() {
+            recorder.record(EventKind::RequestQueued);
+        }
+        let queues = match submission.access {
+            Access::Shared => &mut self.shared,
+            Access::Exclusive => &mut self.submitted,
+        };
+        let queue = queues.entry(submission.resource.clone()).or_default();
+        insert(queue, submission, |submission| submission.priority);
+    }
+
+    /// Same as [`Waiting::submit`] for a lock.
+    fn lock(&mut self, lock: LockRequest) {
+        let queues = match lock.access {
+            Access::Shared => &mut self.shared_locks,
+            Access::Exclusive => &mut self.locks,
+        };
+        let queue = queues.entry(lock.resource.clone()).or_default();
+        insert(queue, lock, |lock| lock.priority);
+    }
+
+    /// The resources work waits for, high priority when any of it is.
+    fn wanted(&self) -> BTreeMap<String, Priority> {
+        self.submitted
+            .keys()
+            .chain(self.shared.keys())
+            .chain(self.locks.keys())
+            .chain(self.shared_locks.keys())
+            .filter(|resource| self.writing(resource) || self.reading(resource))
+            .map(|resource| {
+                let priority = if self.urgent(resource) {
+                    Priority::High
+                } else {
+                    Priority::Low
+                };
+                (resource.clone(), priority)
+            })
+            .collect()
+    }
+
+    /// Whether high-priority work waits for `resource`.
+    fn urgent(&self, resource: &str) -> bool {
+        let high = Priority::High;
+        first_priority(&self.submitted, resource, |submission| submission.priority) == high
+            || first_priority(&self.shared, resource, |submission| submission.priority) == high
+            || first_priority(&self.locks, resource, |lock| lock.priority) == high
+            || first_priority(&self.shared_locks, resource, |lock| lock.priority) == high
+    }
+
+    /// Whether reads wait for a lease on `resource`.
+    fn reading(&self, resource: &str) -> bool {
+        self.shared
+            .get(resource)
+            .is_some_and(|queue| !queue.is_empty())
+            || self
+                .shared_locks
+                .get(resource)
+                .is_some_and(|queue| !queue.is_empty())
+    }
+
+    /// Whether local writes wait for the token of `resource`.
+    fn writing(&self, resource: &str) -> bool {
+        self.submitted
+            .get(resource)
+            .is_some_and(|queue| !queue.is_empty())
+            || self
+                .locks
+                .get(resource)
+                .is_some_and(|queue| !queue.is_empty())
+    }
+}
+
+/// Adds the peers of `demands` to the ones `hot_potato` is wanted by, the high-priority ones
+/// to its demand as well.
+fn add_demands(hot_potato: &mut HotPotato, demands: Option<Vec<Demand>>) {
+    for demand in demands.unwrap_or_default() {
+        if demand.priority == Priority::High && !hot_potato.demand.contains(&demand.node_id) {
+            hot_potato.demand.push(demand.node_id.clone());
         }
-        if let Err(_) = previous_peer_thread.await {
-            log::error("Previous Peer Thread failded.");
+        if !hot_potato.wanted_by.contains(&demand.node_id) {
+            hot_potato.wanted_by.push(demand.node_id);
         }
-        if let Err(_) = calc_then_throw_hot_potato_thread.await {
-            log::error("Calculate then Throw Hot Potato Thread failded.");
+    }
+}
+
+/// Priority of the first item waiting for `resource`, low when there's none.
+fn first_priority<T>(
+    queues: &BTreeMap<String, VecDeque<T>>,
+    resource: &str,
+    priority: impl Fn(&T) -> Priority,
+) -> Priority {
+    queues
+        .get(resource)
+        .and_then(VecDeque::front)
+        .map(priority)
+        .unwrap_or_default()
+}
+
+/// Inserts `item` behind the items of the same or a higher priority.
+fn insert<T>(queue: &mut VecDeque<T>, item: T, priority: impl Fn(&T) -> Priority) {
+    let position = queue.partition_point(|queued| priority(queued) >= priority(&item));
+    queue.insert(position, item);
+}
+
+impl PendingResponses {
+    /// A new request id, the responses to the request or batch sent with it go to `responses`.
+    fn register(&self, responses: Vec<Option<ResponseTx>>) -> u64 {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        if responses.iter().any(Option::is_some) {
+            if let Ok(mut waiting) = self.waiting.lock() {
+                waiting.insert(id, responses);
+            }
         }
-        if let Err(_) = generate_potato_work_thread.await {
-            log::error("Generate Potato Work Thread failded.");
+        id
+    }
+
+    /// Passes the responses of the request or batch `id` to whoever waits for them.
+    fn respond(&self, id: u64, operation_responses: Vec<ServerResponse>) {
+        let waiting = self
+            .waiting
+            .lock()
+            .ok()
+            .and_then(|mut waiting| waiting.remove(&id));
+        for (response, operation_response) in waiting.into_iter().flatten().zip(operation_responses)
+        {
+            if let Some(response) = response {
+                let _ = response.send(operation_response);
+            }
         }
     }
 }
+
+/// Sends requests to the server outside of the visits, for local locks and read leases.
+#[derive(Clone)]
+struct Forwarder {
+    server_writer: Arc<Mutex<ServerWriter>>,
+    pending_responses: PendingResponses,
+    recorder: EventRecorder,
+}
+
+impl Forwarder {
+    /// Sends the requests of `requests` to the server as they come until all its senders are
+    /// dropped, then waits for their responses. They never wait for the token, so they aren't
+    /// recorded as queued.
+    async fn forward(
+        &self,
+        resource: &str,
+        access: Access,
+        mut requests: SubmissionRx,
+        deadline: Option<Instant>,
+    ) {
+        let mut responses = JoinSet::new();
+
+        loop {
+            let submitted = match deadline {
+                Some(deadline) => tokio::select! {
+                    submitted = requests.recv() => submitted,
+                    _ = sleep_until(deadline) => {
+                        log::warning(&cformat!(
+                            "Took the <yellow, bold>hot potato</yellow, bold> of <bold>{resource}</bold> back from a lock held past its deadline."
+                        ));
+                        break;
+                    }
+                },
+                None => requests.recv().await,
+            };
+            let Some(Submitted {
+                request, response, ..
+            }) = submitted
+            else {
+                break;
+            };
+
+            let (response_tx, response_rx) = oneshot::channel();
+            let id = self.pending_responses.register(vec![Some(response_tx)]);
+            let mut writer = self.server_writer.lock().await;
+            request.print();
+            writer
+                .send(
+                    request_line(resource, access, request, id)
+                        .expect("Couldn't parse operation request."),
+                )
+                .await
+                .expect("Couldn't send operation request to server.");
+            drop(writer);
+
+            responses.spawn(async move {
+                if let Ok(operation_response) = response_rx.await {
+                    let _ = response.send(operation_response);
+                }
+            });
+        }
+
+        while responses.join_next().await.is_some() {}
+    }
+
+    /// Lends `hot_potato` to a local lock and gives it back to be thrown once the guard is
+    /// dropped or `hold_deadline` after it's lent, whichever comes first.
+    async fn lend(
+        self,
+        hot_potato: HotPotato,
+        guarded_rx: SubmissionRx,
+        hold_deadline: Option<Duration>,
+        released_tx: mpsc::UnboundedSender<HotPotato>,
+    ) {
+        let deadline = hold_deadline.map(|hold_deadline| Instant::now() + hold_deadline);
+        self.forward(
+            &hot_potato.resource,
+            Access::Exclusive,
+            guarded_rx,
+            deadline,
+        )
+        .await;
+        let _ = released_tx.send(hot_potato);
+    }
+
+    /// Sends the reads of a lease while the hot potato keeps going around.
+    async fn read(self, resource: String, lease_rx: SubmissionRx) {
+        self.forward(&resource, Access::Shared, lease_rx, None)
+            .await;
+        self.recorder.record(EventKind::SharedSectionExit(resource));
+    }
+}
+
+/// The request `id` under `resource`, which the server's reply echoes.
+fn request_line(
+    resource: &str,
+    access: Access,
+    request: ServerRequest,
+    id: u64,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    ResourceRequest {
+        access,
+        id,
+        ..ResourceRequest::new(resource, request)
+    }
+    .to_json_string()
+}
+
+fn of_resource(resource: &str) -> String {
+    if resource.is_empty() {
+        String::new()
+    } else {
+        cformat!(" of <bold>{}</bold>", resource)
+    }
+}
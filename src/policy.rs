@@ -0,0 +1,115 @@
+use crate::*;
+use std::{collections::HashMap, time::Duration};
+
+/// Limits how long a peer may keep the hot potato before throwing it to the next peer.
+/// Work that doesn't fit in a visit stays in the request queue for the next one.
+#[derive(Clone, Debug, Default)]
+pub struct HoldPolicy {
+    pub max_operations: Option<usize>,
+    pub max_hold_time: Option<Duration>,
+    /// Multipliers of `max_operations` per peer.
+    pub weights: HashMap<NodeId, usize>,
+}
+
+impl HoldPolicy {
+    pub fn new(
+        max_operations: Option<usize>,
+        max_hold_time: Option<Duration>,
+        weights: HashMap<NodeId, usize>,
+    ) -> Self {
+        Self {
+            max_operations,
+            max_hold_time,
+            weights,
+        }
+    }
+
+    /// Number of operations the peer `node_id` may send per visit (`None` means unbounded).
+    pub fn quota_for(&self, node_id: &NodeId) -> Option<usize> {
+        self.max_operations
+            .map(|max| max.saturating_mul(self.weights.get(node_id).copied().unwrap_or(1)))
+    }
+
+    pub fn allows(&self, node_id: &NodeId, operations: usize, held_for: Duration) -> bool {
+        self.quota_for(node_id)
+            .is_none_or(|quota| operations < quota)
+            && self.max_hold_time.is_none_or(|max| held_for < max)
+    }
+}
+
+/// Bounds how many visits in a row a peer with only low-priority work lets the hot potato go
+/// on to peers with high-priority work, so low-priority requests still wait a bounded time.
+#[derive(Clone, Debug)]
+pub struct PriorityPolicy {
+    pub max_deferrals: usize,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
+        Self { max_deferrals: 1 }
+    }
+}
+
+impl PriorityPolicy {
+    pub fn new(max_deferrals: usize) -> Self {
+        Self { max_deferrals }
+    }
+
+    /// Whether a peer that already let the hot potato go `deferrals` times may do it again.
+    pub fn defers(&self, deferrals: usize) -> bool {
+        deferrals < self.max_deferrals
+    }
+}
+
+/// Parses a `NODE_ID=WEIGHT` flag, a weight of 0 would starve the peer so it's rejected.
+pub fn parse_weight(weight: &str) -> Result<(NodeId, usize), String> {
+    let (node_id, value) = weight
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected NODE_ID=WEIGHT, got `{weight}`"))?;
+    let value = value
+        .parse::<usize>()
+        .map_err(|e| format!("invalid weight `{value}`: {e}"))?;
+    if value == 0 {
+        return Err(format!("weight of `{node_id}` must be at least 1"));
+    }
+
+    Ok((NodeId::new(node_id), value))
+}
+
+/// Slows the hot potato down when no peer asked for it. Each hop waits at least
+/// `min_hop_delay`, doubling with every rotation that carried no work up to `max_idle_delay`.
+/// Peers with work pull the token with a demand, which cuts the wait short.
+#[derive(Clone, Debug, Default)]
+pub struct PacingPolicy {
+    pub min_hop_delay: Duration,
+    pub max_idle_delay: Duration,
+}
+
+impl PacingPolicy {
+    /// Delay the idle rotations double when there's no minimum hop delay.
+    const BACKOFF_BASE: Duration = Duration::from_millis(1);
+
+    pub fn new(min_hop_delay: Duration, max_idle_delay: Duration) -> Self {
+        Self {
+            min_hop_delay,
+            max_idle_delay,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.min_hop_delay.is_zero() || !self.max_idle_delay.is_zero()
+    }
+
+    /// How long to keep a token nobody asked for after `idle_rotations` rotations without work.
+    pub fn hop_delay(&self, idle_rotations: u64) -> Duration {
+        if idle_rotations == 0 || self.max_idle_delay <= self.min_hop_delay {
+            return self.min_hop_delay;
+        }
+
+        let factor = 2u32.saturating_pow(u32::try_from(idle_rotations).unwrap_or(u32::MAX));
+        self.min_hop_delay
+            .max(Self::BACKOFF_BASE)
+            .saturating_mul(factor)
+            .min(self.max_idle_delay)
+    }
+}
@@ -1,96 +1,466 @@
 use crate::*;
 use color_print::cformat;
 use futures::{SinkExt, StreamExt};
-use std::{error::Error, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    io,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::{Barrier, Mutex},
+    sync::{mpsc, Barrier, Mutex},
+    task::JoinSet,
 };
-use tokio_util::codec::{Framed, LinesCodec};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone)]
 pub struct Server {
+    pub node_id: NodeId,
     pub own_address: String,
     pub number_of_peers: usize,
+    pub recorder: EventRecorder,
+    /// Signs the hot potato the ring starts with.
+    pub token_key: Option<TokenKey>,
+    /// Named resources, each with its own token circulating besides the default one.
+    pub resources: Vec<String>,
+    /// Operations readers may request under shared access, the read-only ones by default.
+    pub shared_operations: BTreeSet<Operation>,
+    /// Addresses the peers that joined listen on, by node id.
+    pub peers: BTreeMap<NodeId, String>,
+    /// Serves the admin protocol on this address when set.
+    pub admin_address: Option<String>,
+    /// Lines sent to the connected peers outside of their requests' responses, by node id.
+    pub controls: BTreeMap<NodeId, mpsc::UnboundedSender<String>>,
+    /// Admin requests waiting for the peers' statuses, each report is sent to all of them.
+    pub status_waiters: Vec<mpsc::UnboundedSender<(NodeId, PeerStatus)>>,
+    /// Peers that may not join again.
+    pub evicted: BTreeSet<NodeId>,
+    /// Epoch of the latest hot potato the server created, every run starts a new one taken from
+    /// the clock unless set beforehand.
+    pub epoch: u64,
+    pub paused: bool,
+    pub stats: ServerStats,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerStats {
+    pub requests: u64,
+    pub batches: u64,
+    pub failed_requests: u64,
+    pub regenerations: u64,
+    pub evictions: u64,
+    /// Requests executed under each named resource.
+    #[serde(default)]
+    pub resource_requests: BTreeMap<String, u64>,
+    /// Requests executed under shared access.
+    #[serde(default)]
+    pub shared_requests: u64,
 }
 
 impl Server {
     pub fn new(own_address: String, number_of_peers: usize) -> Self {
         Self {
+            node_id: NodeId::generate(),
             own_address,
             number_of_peers,
+            recorder: EventRecorder::default(),
+            token_key: None,
+            resources: Vec::new(),
+            shared_operations: Operation::READ_ONLY.into(),
+            peers: BTreeMap::new(),
+            admin_address: None,
+            controls: BTreeMap::new(),
+            status_waiters: Vec::new(),
+            evicted: BTreeSet::new(),
+            epoch: 0,
+            paused: false,
+            stats: ServerStats::default(),
+        }
+    }
+
+    /// Sends `control` to every connected peer.
+    pub fn broadcast(&self, control: &Control) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let line = control.to_json_string()?;
+        for control_tx in self.controls.values() {
+            let _ = control_tx.send(line.clone());
+        }
+        Ok(())
+    }
+
+    /// Sends `line` to the peer `node_id`.
+    pub fn send_to(
+        &self,
+        node_id: &NodeId,
+        line: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.controls
+            .get(node_id)
+            .ok_or_else(|| format!("{node_id} isn't connected."))?
+            .send(line)
+            .map_err(|_| format!("{node_id} disconnected.").into())
+    }
+
+    /// Replaces the hot potatoes: the peers drop the older ones and `holder` gets a new one
+    /// for every resource.
+    pub fn regenerate(&mut self, holder: &NodeId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.epoch += 1;
+        self.stats.regenerations += 1;
+        self.broadcast(&Control::NewEpoch(self.epoch))?;
+
+        log::warning(&cformat!(
+            "Sending new <yellow, bold>hot potatoes</yellow, bold> of epoch <bold>{}</bold> to <bold>{}</bold>.",
+            self.epoch,
+            holder
+        ));
+        for resource in self.all_resources() {
+            let hot_potato = self.token(&resource, holder);
+            self.send_to(holder, hot_potato.to_json_string()?)?;
+        }
+        Ok(())
+    }
+
+    /// The default resource followed by the named ones.
+    pub fn all_resources(&self) -> Vec<String> {
+        std::iter::once(String::new())
+            .chain(self.resources.iter().cloned())
+            .collect()
+    }
+
+    /// A signed token of the current epoch for `resource`, thrown to `holder`.
+    fn token(&self, resource: &str, holder: &NodeId) -> HotPotato {
+        let mut hot_potato = HotPotato {
+            epoch: self.epoch,
+            holder: holder.clone(),
+            ..HotPotato::for_resource(resource)
+        };
+        if let Some(token_key) = &self.token_key {
+            token_key.sign(&mut hot_potato);
+        }
+        hot_potato
+    }
+
+    /// Executes `request` under `resource`, which must be one of the ring's. Readers may only
+    /// request the shared operations.
+    fn execute(
+        &mut self,
+        resource: &str,
+        access: Access,
+        request: &ServerRequest,
+    ) -> ServerResponse {
+        let shared = access == Access::Shared;
+        let response = if !self.has_resource(resource) {
+            unknown_resource(resource, request)
+        } else if shared && !self.shared_operations.contains(&request.operation()) {
+            let (a, b) = request.operands();
+            ServerResponse::Err(
+                a,
+                b,
+                cformat!(
+                    "The <bold>{}</bold> isn't allowed under shared access.",
+                    request.operation()
+                ),
+            )
+        } else {
+            if shared {
+                self.stats.shared_requests += 1;
+            }
+            request.to_response()
+        };
+        self.count(resource, &response);
+
+        response
+    }
+
+    /// Executes `batch_request` under its resource, which must be one of the ring's.
+    fn execute_batch(&mut self, batch_request: &BatchRequest) -> BatchResponse {
+        let batch_response = if self.has_resource(&batch_request.resource) {
+            batch_request.to_response()
+        } else {
+            BatchResponse(
+                batch_request
+                    .requests
+                    .iter()
+                    .map(|request| unknown_resource(&batch_request.resource, request))
+                    .collect(),
+            )
+        };
+
+        self.stats.batches += 1;
+        for response in &batch_response.0 {
+            self.count(&batch_request.resource, response);
+        }
+        batch_response
+    }
+
+    fn has_resource(&self, resource: &str) -> bool {
+        resource.is_empty() || self.resources.iter().any(|named| named == resource)
+    }
+
+    fn count(&mut self, resource: &str, response: &ServerResponse) {
+        self.stats.requests += 1;
+        if response.is_err() {
+            self.stats.failed_requests += 1;
+        }
+        if !resource.is_empty() {
+            *self
+                .stats
+                .resource_requests
+                .entry(resource.to_string())
+                .or_default() += 1;
         }
     }
 
     async fn handle(
-        stream: TcpStream,
-        _server: Arc<Mutex<Self>>,
+        mut lines: Connection,
+        server: Arc<Mutex<Self>>,
         barrier: Arc<Barrier>,
-        starts_with_hot_potato: Arc<Mutex<bool>>,
+        started: Arc<Mutex<usize>>,
+        authenticated: bool,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let lines = Framed::new(stream, LinesCodec::new());
+        let (recorder, hello) = {
+            let server = server.lock().await;
+            let hello = Hello::new(server.node_id.clone(), server.own_address.clone());
+            (server.recorder.clone(), hello)
+        };
+
+        // learn which peer this connection belongs to
+        let peer = answer(&mut lines, &hello, authenticated).await?;
+        let (control_tx, mut control_rx) = mpsc::unbounded_channel();
+        {
+            let mut server = server.lock().await;
+            if server.evicted.contains(&peer.node_id) {
+                return Err(
+                    format!("The evicted peer {} tried to join again.", peer.node_id).into(),
+                );
+            }
+            if server.peers.contains_key(&peer.node_id) {
+                return Err(format!(
+                    "{} tried to join as {}, which is already in the ring.",
+                    peer.address, peer.node_id
+                )
+                .into());
+            }
+            server
+                .peers
+                .insert(peer.node_id.clone(), peer.address.clone());
+
+            log::info(&cformat!(
+                "Peer <bold>{}</bold> listening on <bold>{}</bold> joined (<bold>{}</bold> of <bold>{}</bold>).",
+                peer.node_id,
+                peer.address,
+                server.peers.len(),
+                server.number_of_peers
+            ));
+        }
+
         let (mut writer, mut reader) = lines.split::<String>();
 
         // wait for all participants to join
         barrier.wait().await;
 
-        log::info(&cformat!("Send <bold>starting flag</bold> to peer."));
-        writer.send(StartFlag(true).to_json_string()?).await?;
+        // the peers only read control lines once started
+        server
+            .lock()
+            .await
+            .controls
+            .insert(peer.node_id.clone(), control_tx);
+
+        log::info(&cformat!(
+            "Send <bold>starting flag</bold> to <bold>{}</bold>.",
+            peer.node_id
+        ));
+        let epoch = server.lock().await.epoch;
+        writer.send(StartFlag::new(epoch).to_json_string()?).await?;
         writer.flush().await?;
 
         {
-            let mut starts_with_hot_potato = starts_with_hot_potato.lock().await;
+            // the first peer started gets the default hot potato, the tokens of the named
+            // resources are spread over the peers
+            let mut started = started.lock().await;
+            let hot_potatoes = {
+                let server = server.lock().await;
+                server
+                    .all_resources()
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| i % server.number_of_peers.max(1) == *started)
+                    .map(|(_, resource)| server.token(resource, &peer.node_id))
+                    .collect::<Vec<_>>()
+            };
+            *started += 1;
 
-            if *starts_with_hot_potato {
+            for hot_potato in hot_potatoes {
                 log::info(&cformat!(
-                    "Sending <yellow, bold>hot potato</yellow, bold> to a peer."
+                    "Sending <yellow, bold>hot potato</yellow, bold>{} to <bold>{}</bold>.",
+                    if hot_potato.resource.is_empty() {
+                        String::new()
+                    } else {
+                        cformat!(" of <bold>{}</bold>", hot_potato.resource)
+                    },
+                    peer.node_id
                 ));
-                *starts_with_hot_potato = false;
-                writer.send(StartFlag(true).to_json_string()?).await?;
-                writer.flush().await?;
+                writer.send(hot_potato.to_json_string()?).await?;
             }
+            writer.flush().await?;
         }
 
         loop {
             tokio::select! {
-                Some(Ok(line)) = reader.next() => {
-                    match serde_json::from_str::<ServerRequest>(&line) {
-                        Ok(request) => {
-                            let response = request.to_response();
+                line = reader.next() => {
+                    let Some(line) = line else {
+                        let mut server = server.lock().await;
+                        server.peers.remove(&peer.node_id);
+                        server.controls.remove(&peer.node_id);
+                        return Ok(());
+                    };
+                    let Ok(line) = line else {
+                        continue;
+                    };
 
-                            request.print();
-                            response.print();
+                    if let Ok(request) = ServerRequest::from_json_string(&line) {
+                        let response =
+                            server.lock().await.execute("", Access::Exclusive, &request);
+                        recorder.record(EventKind::RequestExecuted);
 
-                            writer.send(response.to_json_string()?).await?;
-                        }
-                        Err(_) => {
-                            writer.send(ServerResponse::Err(0, 0, cformat!("The request had <bold>incorrect formatting</bold>.")).to_json_string()?).await?;
+                        log::info(&cformat!("Request from <bold>{}</bold>.", peer.node_id));
+                        request.print();
+                        response.print();
+
+                        writer.send(Reply::new(peer.node_id.clone(), 0, vec![response]).to_json_string()?).await?;
+                    } else if let Ok(ResourceRequest { resource, request, access, id }) =
+                        ResourceRequest::from_json_string(&line)
+                    {
+                        let response = server.lock().await.execute(&resource, access, &request);
+                        recorder.record(EventKind::RequestExecuted);
+
+                        log::info(&cformat!(
+                            "{} request{} from <bold>{}</bold>.",
+                            if access == Access::Shared { "Shared" } else { "Exclusive" },
+                            if resource.is_empty() {
+                                String::new()
+                            } else {
+                                cformat!(" on <bold>{}</bold>", resource)
+                            },
+                            peer.node_id
+                        ));
+                        request.print();
+                        response.print();
+
+                        writer.send(Reply::new(peer.node_id.clone(), id, vec![response]).to_json_string()?).await?;
+                    } else if let Ok(batch_request) = BatchRequest::from_json_string(&line) {
+                        // hold the server while the batch runs so it executes as a unit
+                        let batch_response = server.lock().await.execute_batch(&batch_request);
+                        for _ in &batch_response.0 {
+                            recorder.record(EventKind::RequestExecuted);
                         }
+
+                        log::info(&cformat!("Batch from <bold>{}</bold>.", peer.node_id));
+                        batch_request.print();
+                        batch_response.print();
+
+                        writer.send(Reply::new(peer.node_id.clone(), batch_request.id, batch_response.0).to_json_string()?).await?;
+                    } else if let Ok(status) = PeerStatus::from_json_string(&line) {
+                        server.lock().await.status_waiters.retain(|waiter| waiter.send((peer.node_id.clone(), status.clone())).is_ok());
+                    } else {
+                        let response = ServerResponse::Err(0, 0, cformat!("The request had <bold>incorrect formatting</bold>."));
+                        writer.send(Reply::new(peer.node_id.clone(), 0, vec![response]).to_json_string()?).await?;
                     }
                 }
+                control = control_rx.recv() => {
+                    // the sender is dropped when the peer is evicted
+                    let Some(control) = control else {
+                        return Ok(());
+                    };
+                    writer.send(control).await?;
+                }
             }
         }
     }
 
     pub async fn run(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let listener = TcpListener::bind(&self.own_address).await?;
-        let server = Arc::new(Mutex::new(self.clone()));
+        self.run_with(&Tcp).await
+    }
+
+    pub async fn run_with<T: Transport>(
+        &self,
+        transport: &T,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut server = self.clone();
+        let listener = server.bind(transport).await?;
+        let admin_listener = match &self.admin_address {
+            Some(address) => Some(transport.bind(address).await?),
+            None => None,
+        };
+
+        server
+            .serve_with_admin(listener, admin_listener, CancellationToken::new())
+            .await
+    }
+
+    /// Binds the server's listener and updates `own_address` to the bound address.
+    pub async fn bind<T: Transport>(&mut self, transport: &T) -> io::Result<T::Listener> {
+        let listener = transport.bind(&self.own_address).await?;
+        self.own_address = listener.local_address()?;
+
+        Ok(listener)
+    }
+
+    /// Accepts peers on an already bound listener until `shutdown` is cancelled.
+    pub async fn serve<L: Listener>(
+        &self,
+        listener: L,
+        shutdown: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.serve_with_admin(listener, None, shutdown).await
+    }
+
+    /// Same as [`Server::serve`], also serving the admin protocol on `admin_listener`.
+    pub async fn serve_with_admin<L: Listener>(
+        &self,
+        mut listener: L,
+        admin_listener: Option<L>,
+        shutdown: CancellationToken,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let server = Arc::new(Mutex::new(Self {
+            epoch: match self.epoch {
+                0 => first_epoch(),
+                epoch => epoch,
+            },
+            ..self.clone()
+        }));
 
         let barrier = Arc::new(Barrier::new(self.number_of_peers));
-        let starts_with_hot_potato = Arc::new(Mutex::new(true));
+        let started = Arc::new(Mutex::new(0));
+
+        // dropping the set on shutdown aborts the connections' handlers
+        let mut handlers = JoinSet::new();
+
+        if let Some(admin_listener) = admin_listener {
+            log::info(&cformat!(
+                "Serving the admin protocol on <bold>{}</bold>.",
+                admin_listener.local_address()?
+            ));
+            handlers.spawn(admin::serve(server.clone(), admin_listener));
+        }
 
         loop {
-            let (peer_stream, _peer_address) = listener.accept().await?;
+            let (peer_lines, _peer_address) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = shutdown.cancelled() => return Ok(()),
+            };
 
             log::info(&cformat!("Accepted a <bold>connection</bold>."));
 
             let server = server.clone();
             let barrier = barrier.clone();
-            let starts_with_hot_potato = starts_with_hot_potato.clone();
+            let started = started.clone();
+            let authenticated = listener.authenticates();
 
-            let _handle = tokio::spawn(async move {
+            handlers.spawn(async move {
                 if let Err(e) =
-                    Self::handle(peer_stream, server, barrier, starts_with_hot_potato).await
+                    Self::handle(peer_lines, server, barrier, started, authenticated).await
                 {
                     log::error(&format!("{e}"));
                 };
@@ -98,3 +468,20 @@ impl Server {
         }
     }
 }
+
+/// First epoch of a server run, taken from the clock so a restarted server doesn't accept the
+/// tokens of an earlier run.
+fn first_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_micros() as u64)
+}
+
+fn unknown_resource(resource: &str, request: &ServerRequest) -> ServerResponse {
+    let (a, b) = request.operands();
+    ServerResponse::Err(
+        a,
+        b,
+        cformat!("The resource <bold>{}</bold> doesn't exist.", resource),
+    )
+}
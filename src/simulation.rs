@@ -0,0 +1,188 @@
+use crate::fault::{FaultConfig, Faulty};
+use crate::peer::{Batching, Peer, RequestMode, RequestQueue};
+use crate::server::Server;
+use crate::*;
+use rand::{rngs::SmallRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, time::Duration};
+use tokio::{
+    runtime,
+    sync::mpsc,
+    task::JoinSet,
+    time::{timeout_at, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+const SERVER_ADDRESS: &str = "server";
+
+/// Everything needed to reproduce a simulated run, the `seed` drives every random choice.
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    pub number_of_peers: usize,
+    pub seed: u64,
+    pub duration: Duration,
+    pub hop_latency: Duration,
+    pub server_latency: Duration,
+    pub workload: Workload,
+    pub hold_policy: HoldPolicy,
+    pub request_mode: RequestMode,
+    pub batching: Batching,
+}
+
+/// The events the nodes recorded, `at` counts the simulated microseconds since the start.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub seed: u64,
+    pub rotations: usize,
+    pub events: Vec<Event>,
+}
+
+/// Runs the real server and peers of a ring over in-memory links on a single thread with a
+/// paused clock, so that the same configuration always produces the same sequence of events.
+pub struct Simulation {
+    pub config: SimulationConfig,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            number_of_peers: 3,
+            seed: 0,
+            duration: Duration::from_secs(60),
+            hop_latency: Duration::from_millis(1),
+            server_latency: Duration::from_millis(1),
+            workload: Workload::default(),
+            hold_policy: HoldPolicy::default(),
+            request_mode: RequestMode::default(),
+            batching: Batching::default(),
+        }
+    }
+}
+
+impl SimulationReport {
+    pub fn to_json_string(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json_string(report: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(report)?)
+    }
+
+    pub fn responses(&self, peer: usize) -> impl Iterator<Item = &ServerResponse> {
+        let node = Simulation::address(peer);
+        self.events
+            .iter()
+            .filter_map(move |event| match &event.kind {
+                EventKind::ResponseReceived(response) if event.node == node => Some(response),
+                _ => None,
+            })
+    }
+}
+
+impl Simulation {
+    pub fn new(config: SimulationConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if config.number_of_peers == 0 {
+            return Err("A ring needs at least one peer.".into());
+        }
+        config.workload.validate()?;
+
+        Ok(Self { config })
+    }
+
+    /// Name of the peer, also its address and its node id.
+    pub fn address(peer: usize) -> String {
+        format!("peer-{peer}")
+    }
+
+    pub fn run(self) -> Result<SimulationReport, Box<dyn Error + Send + Sync>> {
+        runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()?
+            .block_on(self.simulate())
+    }
+
+    async fn simulate(self) -> Result<SimulationReport, Box<dyn Error + Send + Sync>> {
+        let config = self.config;
+        let mut rng = SmallRng::seed_from_u64(config.seed);
+
+        // every link delays its messages by the latency of a hop, links to the server by the
+        // server's
+        let latency = |latency: Duration| FaultConfig {
+            delay: Some((latency, latency)),
+            ..Default::default()
+        };
+        let transport = Faulty::new(
+            Memory::default(),
+            latency(config.hop_latency),
+            rng.next_u64(),
+        )
+        .with_link(SERVER_ADDRESS, latency(config.server_latency));
+
+        let started = Instant::now();
+        let shutdown = CancellationToken::new();
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let mut nodes = JoinSet::new();
+
+        let mut server = Server::new(SERVER_ADDRESS.to_string(), config.number_of_peers);
+        server.node_id = NodeId::new(SERVER_ADDRESS);
+        server.epoch = rng.random_range(1..u64::MAX);
+        server.recorder = EventRecorder::to_channel(SERVER_ADDRESS, event_tx.clone());
+        let server_listener = server.bind(&transport).await?;
+
+        let mut peers = Vec::with_capacity(config.number_of_peers);
+        for i in 0..config.number_of_peers {
+            let next_peer = Self::address((i + 1) % config.number_of_peers);
+            let mut peer = Peer::new(Self::address(i), SERVER_ADDRESS.to_string(), next_peer);
+            peer.node_id = NodeId::new(&Self::address(i));
+            peer.request_queue = RequestQueue::from([ServerRequest::generate(&mut rng)]);
+            peer.hold_policy = config.hold_policy.clone();
+            peer.request_mode = config.request_mode.clone();
+            peer.batching = config.batching;
+            peer.workload = config.workload.clone();
+            let mut seed = [0u8; 32];
+            rng.fill_bytes(&mut seed);
+            peer.seed = Some(seed);
+            peer.recorder = EventRecorder::to_channel(peer.node_id.as_str(), event_tx.clone());
+            let listener = peer.bind(&transport).await?;
+            peers.push((peer, listener));
+        }
+        drop(event_tx);
+
+        {
+            let shutdown = shutdown.clone();
+            nodes.spawn(async move {
+                if let Err(e) = server.serve(server_listener, shutdown).await {
+                    log::error(&format!("{e}"));
+                }
+            });
+        }
+        for (mut peer, listener) in peers {
+            let transport = transport.clone();
+            let shutdown = shutdown.clone();
+            nodes.spawn(async move { peer.serve(&transport, listener, shutdown).await });
+        }
+
+        // the clock only moves on once every node waits, so events are timed when recorded
+        let deadline = started + config.duration;
+        let mut events = Vec::new();
+        while let Ok(Some(mut event)) = timeout_at(deadline, event_rx.recv()).await {
+            event.at = started.elapsed().as_micros() as u64;
+            events.push(event);
+        }
+
+        shutdown.cancel();
+        while nodes.join_next().await.is_some() {}
+
+        let throws = events
+            .iter()
+            .filter(|event| matches!(event.kind, EventKind::TokenSent { .. }))
+            .count();
+
+        Ok(SimulationReport {
+            seed: config.seed,
+            rotations: throws / config.number_of_peers,
+            events,
+        })
+    }
+}
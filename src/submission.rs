@@ -0,0 +1,128 @@
+use crate::*;
+use color_print::cformat;
+use futures::{future, SinkExt, StreamExt};
+use std::error::Error;
+use tokio::sync::{mpsc, oneshot};
+
+pub type SubmissionTx = mpsc::UnboundedSender<Submitted>;
+pub type SubmissionRx = mpsc::UnboundedReceiver<Submitted>;
+
+/// A request queued on a peer from outside, its response is sent back through `response`.
+#[derive(Debug)]
+pub struct Submitted {
+    /// Named resource whose token the request waits for, the default one when empty.
+    pub resource: String,
+    /// Shared requests are sent under a read lease instead of the token.
+    pub access: Access,
+    pub priority: Priority,
+    pub request: ServerRequest,
+    pub response: oneshot::Sender<ServerResponse>,
+}
+
+impl Submitted {
+    pub fn new(
+        resource: &str,
+        access: Access,
+        priority: Priority,
+        request: ServerRequest,
+    ) -> (Self, oneshot::Receiver<ServerResponse>) {
+        let (response, response_rx) = oneshot::channel();
+        let submitted = Self {
+            resource: resource.to_string(),
+            access,
+            priority,
+            request,
+            response,
+        };
+
+        (submitted, response_rx)
+    }
+}
+
+/// Accepts clients on `listener` and queues the requests they submit on the peer, the same
+/// clients as [`admin::admits`] are accepted.
+pub async fn serve<L: Listener>(mut listener: L, submissions: SubmissionTx) {
+    let authenticated = listener.authenticates();
+    loop {
+        let (mut lines, address) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error(&format!("Couldn't accept submissions anymore: {e}"));
+                return;
+            }
+        };
+        if !admin::admits(&address, authenticated) {
+            log::warning(&cformat!(
+                "Refused submissions from <bold>{}</bold>, only local clients are allowed without TLS.",
+                address
+            ));
+            continue;
+        }
+        let submissions = submissions.clone();
+
+        tokio::spawn(async move {
+            while let Some(Ok(line)) = lines.next().await {
+                let Ok(Submission {
+                    requests,
+                    resource,
+                    access,
+                    priority,
+                }) = Submission::from_json_string(&line)
+                else {
+                    log::warning(&format!("Ignored an invalid submission from {address}."));
+                    continue;
+                };
+                log::info(&cformat!(
+                    "Queued <bold>{}</bold> submitted requests.",
+                    requests.len()
+                ));
+
+                let mut responses = Vec::new();
+                for request in requests {
+                    let (submitted, response) =
+                        Submitted::new(&resource, access, priority, request);
+                    if submissions.send(submitted).is_err() {
+                        return;
+                    }
+                    responses.push(response);
+                }
+
+                // the responses are dropped when the peer leaves the ring before sending them
+                let Ok(responses) = future::try_join_all(responses).await else {
+                    return;
+                };
+                let Ok(response) = BatchResponse(responses).to_json_string() else {
+                    return;
+                };
+                if lines.send(response).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Submits the requests of `submission` to the peer accepting submissions on `address` and
+/// waits for their responses, in the same order.
+pub async fn submit<T: Transport>(
+    transport: &T,
+    address: &str,
+    submission: &Submission,
+) -> Result<Vec<ServerResponse>, Box<dyn Error + Send + Sync>> {
+    let mut lines = transport.connect(address).await?;
+    lines.send(submission.to_json_string()?).await?;
+
+    match lines.next().await {
+        Some(line) => Ok(BatchResponse::from_json_string(&line?)?.0),
+        None => Err("The peer closed the connection before answering.".into()),
+    }
+}
+
+/// Parses one operation per line, skipping blank lines and `#` comments.
+pub fn parse_operations(text: &str) -> Result<Vec<ServerRequest>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| line.parse().map_err(|e| format!("Line {}: {e}", i + 1)))
+        .collect()
+}
@@ -0,0 +1,366 @@
+use crate::*;
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    client::WebPkiServerVerifier,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    server::WebPkiClientVerifier,
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig,
+    SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufReader, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle, time::timeout};
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Name sent to servers, their certificates are checked against the node id instead.
+const SERVER_NAME: &str = "token-ring";
+
+/// PEM files a node authenticates with: the CA every node trusts, and its own certificate
+/// and key. The certificate is issued for the node id as a DNS name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub ca: String,
+    pub certificate: String,
+    pub key: String,
+}
+
+/// Wraps a transport and runs mutual TLS over its connections, both ends must present a
+/// certificate signed by the CA.
+#[derive(Clone)]
+pub struct Tls<T: Transport> {
+    pub inner: T,
+    acceptor: TlsAcceptor,
+    connector: TlsConnector,
+}
+
+/// Accepts connections in the background and runs every handshake in its own task, so a
+/// client that never finishes its handshake doesn't hold up the others.
+pub struct TlsListener {
+    address: String,
+    connections: mpsc::UnboundedReceiver<io::Result<(Connection, String)>>,
+    accepting: JoinHandle<()>,
+}
+
+/// Checks the server's certificate chain but not its name, the node id it is issued for is
+/// only known after the handshake (see [`certifies`]).
+#[derive(Debug)]
+struct NodeCertificateVerifier(Arc<WebPkiServerVerifier>);
+
+impl TlsConfig {
+    pub fn new(ca: String, certificate: String, key: String) -> Self {
+        Self {
+            ca,
+            certificate,
+            key,
+        }
+    }
+
+    /// The files [`generate_certificates`] writes for `node_id` into `directory`.
+    pub fn in_directory(directory: &str, node_id: &NodeId) -> Self {
+        let path = |file: String| Path::new(directory).join(file).display().to_string();
+
+        Self::new(
+            path("ca.pem".to_string()),
+            path(format!("{node_id}.pem")),
+            path(format!("{node_id}.key")),
+        )
+    }
+}
+
+impl<T: Transport> Tls<T> {
+    pub fn new(inner: T, config: &TlsConfig) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut roots = RootCertStore::empty();
+        for certificate in load_certificates(&config.ca)? {
+            roots.add(certificate)?;
+        }
+        let roots = Arc::new(roots);
+        let certificates = load_certificates(&config.certificate)?;
+        let key = load_key(&config.key)?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let client_verifier =
+            WebPkiClientVerifier::builder_with_provider(roots.clone(), provider.clone()).build()?;
+        let server_config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(certificates.clone(), key.clone_key())?;
+
+        let server_verifier =
+            WebPkiServerVerifier::builder_with_provider(roots, provider.clone()).build()?;
+        let client_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NodeCertificateVerifier(server_verifier)))
+            .with_client_auth_cert(certificates, key)?;
+
+        Ok(Self {
+            inner,
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            connector: TlsConnector::from(Arc::new(client_config)),
+        })
+    }
+}
+
+impl<T: Transport> Transport for Tls<T> {
+    type Listener = TlsListener;
+
+    fn authenticates(&self) -> bool {
+        true
+    }
+
+    async fn bind(&self, address: &str) -> io::Result<Self::Listener> {
+        let mut inner = self.inner.bind(address).await?;
+        let address = inner.local_address()?;
+        let acceptor = self.acceptor.clone();
+        let (connection_tx, connections) = mpsc::unbounded_channel();
+
+        let accepting = tokio::spawn(async move {
+            loop {
+                let (lines, address) = match inner.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let _ = connection_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let connection_tx = connection_tx.clone();
+
+                // a client failing the handshake must not stop the listener
+                tokio::spawn(async move {
+                    match timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(lines.into_inner())).await
+                    {
+                        Ok(Ok(stream)) => {
+                            let _ = connection_tx.send(Ok((connection(stream), address)));
+                        }
+                        Ok(Err(e)) => {
+                            log::warning(&format!("Rejected a connection from {address}: {e}"))
+                        }
+                        Err(_) => {
+                            log::warning(&format!("The TLS handshake with {address} timed out."))
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(TlsListener {
+            address,
+            connections,
+            accepting,
+        })
+    }
+
+    async fn connect(&self, address: &str) -> io::Result<Connection> {
+        let stream = self.inner.connect(address).await?.into_inner();
+        let server_name = ServerName::try_from(SERVER_NAME).expect("Invalid server name.");
+
+        let stream = timeout(
+            TLS_HANDSHAKE_TIMEOUT,
+            self.connector.connect(server_name, stream),
+        )
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+        Ok(connection(stream))
+    }
+}
+
+impl Listener for TlsListener {
+    async fn accept(&mut self) -> io::Result<(Connection, String)> {
+        self.connections
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(io::Error::from(io::ErrorKind::BrokenPipe)))
+    }
+
+    fn local_address(&self) -> io::Result<String> {
+        Ok(self.address.clone())
+    }
+
+    fn authenticates(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.accepting.abort();
+    }
+}
+
+impl Stream for server::TlsStream<Box<dyn Stream>> {
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        let (_, connection) = self.get_ref();
+        connection
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .map(|certificate| certificate.clone().into_owned())
+    }
+}
+
+impl Stream for client::TlsStream<Box<dyn Stream>> {
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        let (_, connection) = self.get_ref();
+        connection
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .map(|certificate| certificate.clone().into_owned())
+    }
+}
+
+impl ServerCertVerifier for NodeCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self
+            .0
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0
+            .verify_tls12_signature(message, certificate, signature)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0
+            .verify_tls13_signature(message, certificate, signature)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
+
+/// Whether `certificate` was issued for `node_id`.
+pub fn certifies(certificate: &CertificateDer<'_>, node_id: &NodeId) -> bool {
+    let (Ok(certificate), Ok(name)) = (
+        webpki::EndEntityCert::try_from(certificate),
+        ServerName::try_from(node_id.as_str()),
+    ) else {
+        return false;
+    };
+
+    certificate.verify_is_valid_for_subject_name(&name).is_ok()
+}
+
+fn load_certificates(
+    path: &str,
+) -> Result<Vec<CertificateDer<'static>>, Box<dyn Error + Send + Sync>> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if certificates.is_empty() {
+        return Err(format!("No certificate found in {path}.").into());
+    }
+    Ok(certificates)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, Box<dyn Error + Send + Sync>> {
+    rustls_pemfile::private_key(&mut BufReader::new(File::open(path)?))?
+        .ok_or_else(|| format!("No private key found in {path}.").into())
+}
+
+/// Writes a private key only its owner may read, also when the file already existed.
+fn write_key(path: &Path, pem: &str) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+
+    file.write_all(pem.as_bytes())
+}
+
+/// Creates a local CA and a certificate for every node in `directory`, as `ca.pem`, `ca.key`,
+/// `<node id>.pem` and `<node id>.key`.
+pub fn generate_certificates(
+    directory: &str,
+    node_ids: &[NodeId],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let directory = Path::new(directory);
+    fs::create_dir_all(directory)?;
+
+    let mut ca_params = CertificateParams::new(Vec::new())?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "token ring CA");
+    ca_params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    let ca_key = KeyPair::generate()?;
+    let ca = ca_params.self_signed(&ca_key)?;
+
+    fs::write(directory.join("ca.pem"), ca.pem())?;
+    write_key(&directory.join("ca.key"), &ca_key.serialize_pem())?;
+
+    for node_id in node_ids {
+        if !matches!(
+            ServerName::try_from(node_id.as_str()),
+            Ok(ServerName::DnsName(_))
+        ) {
+            return Err(format!("The node id {node_id} isn't a valid certificate name.").into());
+        }
+
+        let mut params = CertificateParams::new(vec![node_id.to_string()])?;
+        params
+            .distinguished_name
+            .push(DnType::CommonName, node_id.as_str());
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        let key = KeyPair::generate()?;
+        let certificate = params.signed_by(&key, &ca, &ca_key)?;
+
+        fs::write(directory.join(format!("{node_id}.pem")), certificate.pem())?;
+        write_key(
+            &directory.join(format!("{node_id}.key")),
+            &key.serialize_pem(),
+        )?;
+    }
+
+    Ok(())
+}
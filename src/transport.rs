@@ -0,0 +1,295 @@
+use rustls::pki_types::CertificateDer;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    io,
+    os::unix::fs::FileTypeExt,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{duplex, AsyncRead, AsyncWrite, DuplexStream},
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    sync::mpsc,
+};
+use tokio_util::codec::{Framed, LinesCodec};
+
+const MEMORY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Longest line a connection accepts unless its transport is [`Limited`].
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 1024 * 1024;
+
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    /// The certificate the remote end authenticated with, if the stream is authenticated.
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        None
+    }
+}
+
+impl Stream for TcpStream {}
+
+impl Stream for UnixStream {}
+
+impl Stream for DuplexStream {}
+
+/// A line-delimited connection, independent of what carries the bytes.
+pub type Connection = Framed<Box<dyn Stream>, LinesCodec>;
+
+pub type MemoryConnectionTx = mpsc::UnboundedSender<(Connection, String)>;
+pub type MemoryConnectionRx = mpsc::UnboundedReceiver<(Connection, String)>;
+pub type MemoryListeners = Arc<Mutex<HashMap<String, MemoryConnectionTx>>>;
+
+pub trait Transport: Clone + Send + Sync + 'static {
+    type Listener: Listener;
+
+    /// Whether the remote end of every connection authenticated with a certificate.
+    fn authenticates(&self) -> bool {
+        false
+    }
+
+    fn bind(&self, address: &str) -> impl Future<Output = io::Result<Self::Listener>> + Send;
+
+    fn connect(&self, address: &str) -> impl Future<Output = io::Result<Connection>> + Send;
+}
+
+pub trait Listener: Send + 'static {
+    /// Waits for a connection and returns it with the address of the remote end.
+    fn accept(&mut self) -> impl Future<Output = io::Result<(Connection, String)>> + Send;
+
+    fn local_address(&self) -> io::Result<String>;
+
+    /// Same as [`Transport::authenticates`] for the accepted connections.
+    fn authenticates(&self) -> bool {
+        false
+    }
+}
+
+pub fn connection<S: Stream>(stream: S) -> Connection {
+    Framed::new(
+        Box::new(stream),
+        LinesCodec::new_with_max_length(DEFAULT_MAX_LINE_LENGTH),
+    )
+}
+
+/// Transports selectable from the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Unix,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tcp;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unix;
+
+/// Connections between tasks of the same process, addresses are plain names.
+#[derive(Clone, Default)]
+pub struct Memory {
+    listeners: MemoryListeners,
+}
+
+pub struct MemoryListener {
+    address: String,
+    connections: MemoryConnectionRx,
+    listeners: MemoryListeners,
+}
+
+/// Wraps a transport whose connections discard lines longer than `max_line_length`.
+#[derive(Clone, Debug)]
+pub struct Limited<T: Transport> {
+    pub inner: T,
+    pub max_line_length: usize,
+}
+
+pub struct LimitedListener<L: Listener> {
+    inner: L,
+    max_line_length: usize,
+}
+
+impl Transport for Tcp {
+    type Listener = TcpListener;
+
+    async fn bind(&self, address: &str) -> io::Result<TcpListener> {
+        TcpListener::bind(address).await
+    }
+
+    async fn connect(&self, address: &str) -> io::Result<Connection> {
+        Ok(connection(TcpStream::connect(address).await?))
+    }
+}
+
+impl Listener for TcpListener {
+    async fn accept(&mut self) -> io::Result<(Connection, String)> {
+        let (stream, address) = TcpListener::accept(self).await?;
+        Ok((connection(stream), address.to_string()))
+    }
+
+    fn local_address(&self) -> io::Result<String> {
+        Ok(self.local_addr()?.to_string())
+    }
+}
+
+impl Transport for Unix {
+    type Listener = UnixListener;
+
+    async fn bind(&self, address: &str) -> io::Result<UnixListener> {
+        // a socket file left behind by a previous run would make the bind fail, other files
+        // and sockets still listened on are left alone
+        let is_socket =
+            fs::symlink_metadata(address).is_ok_and(|metadata| metadata.file_type().is_socket());
+        if is_socket && UnixStream::connect(address).await.is_err() {
+            fs::remove_file(address)?;
+        }
+        UnixListener::bind(address)
+    }
+
+    async fn connect(&self, address: &str) -> io::Result<Connection> {
+        Ok(connection(UnixStream::connect(address).await?))
+    }
+}
+
+impl Listener for UnixListener {
+    async fn accept(&mut self) -> io::Result<(Connection, String)> {
+        let (stream, address) = UnixListener::accept(self).await?;
+        let address = address
+            .as_pathname()
+            .map_or_else(String::new, |path| path.display().to_string());
+
+        Ok((connection(stream), address))
+    }
+
+    fn local_address(&self) -> io::Result<String> {
+        Ok(self
+            .local_addr()?
+            .as_pathname()
+            .map_or_else(String::new, |path| path.display().to_string()))
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Transport for Memory {
+    type Listener = MemoryListener;
+
+    async fn bind(&self, address: &str) -> io::Result<MemoryListener> {
+        let mut listeners = self.listeners.lock().expect("Memory transport poisoned.");
+
+        if listeners.contains_key(address) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{address} is already bound"),
+            ));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        listeners.insert(address.to_string(), tx);
+
+        Ok(MemoryListener {
+            address: address.to_string(),
+            connections: rx,
+            listeners: self.listeners.clone(),
+        })
+    }
+
+    async fn connect(&self, address: &str) -> io::Result<Connection> {
+        let listener = self
+            .listeners
+            .lock()
+            .expect("Memory transport poisoned.")
+            .get(address)
+            .cloned()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("nothing is bound to {address}"),
+                )
+            })?;
+
+        let (local, remote) = duplex(MEMORY_BUFFER_SIZE);
+        listener
+            .send((connection(remote), String::new()))
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+
+        Ok(connection(local))
+    }
+}
+
+impl Listener for MemoryListener {
+    async fn accept(&mut self) -> io::Result<(Connection, String)> {
+        self.connections
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    fn local_address(&self) -> io::Result<String> {
+        Ok(self.address.clone())
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.remove(&self.address);
+        }
+    }
+}
+
+impl<T: Transport> Limited<T> {
+    pub fn new(inner: T, max_line_length: usize) -> Self {
+        Self {
+            inner,
+            max_line_length,
+        }
+    }
+}
+
+/// Frames `lines` with a new codec, before anything was read from it.
+fn limit(mut lines: Connection, max_line_length: usize) -> Connection {
+    *lines.codec_mut() = LinesCodec::new_with_max_length(max_line_length);
+    lines
+}
+
+impl<T: Transport> Transport for Limited<T> {
+    type Listener = LimitedListener<T::Listener>;
+
+    fn authenticates(&self) -> bool {
+        self.inner.authenticates()
+    }
+
+    async fn bind(&self, address: &str) -> io::Result<Self::Listener> {
+        Ok(LimitedListener {
+            inner: self.inner.bind(address).await?,
+            max_line_length: self.max_line_length,
+        })
+    }
+
+    async fn connect(&self, address: &str) -> io::Result<Connection> {
+        let lines = self.inner.connect(address).await?;
+        Ok(limit(lines, self.max_line_length))
+    }
+}
+
+impl<L: Listener> Listener for LimitedListener<L> {
+    async fn accept(&mut self) -> io::Result<(Connection, String)> {
+        let (lines, address) = self.inner.accept().await?;
+        Ok((limit(lines, self.max_line_length), address))
+    }
+
+    fn local_address(&self) -> io::Result<String> {
+        self.inner.local_address()
+    }
+
+    fn authenticates(&self) -> bool {
+        self.inner.authenticates()
+    }
+}
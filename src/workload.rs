@@ -0,0 +1,325 @@
+use crate::*;
+use rand::{rngs::SmallRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    f64::consts::PI,
+    fs,
+    time::Duration,
+};
+
+/// Relative weights used to pick the operation of each generated request.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperationMix {
+    pub add: f64,
+    pub sub: f64,
+    pub mul: f64,
+    pub div: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum OperandDistribution {
+    /// Any `i32`.
+    #[default]
+    Full,
+    Uniform {
+        min: i32,
+        max: i32,
+    },
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+}
+
+/// Arrival process of a peer's requests. `Uniform` and `MarkovModulated` bring their own
+/// rates, the others follow the peer's.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Arrival {
+    #[default]
+    Poisson,
+    Deterministic,
+    /// Inter-arrival times uniform between `min` and `max` seconds.
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    Pareto {
+        shape: f64,
+    },
+    MarkovModulated {
+        rates: Vec<f64>,
+        switch_rates: Vec<f64>,
+    },
+    BatchPoisson {
+        mean_batch: f64,
+    },
+    OnOff {
+        on: f64,
+        off: f64,
+    },
+    Diurnal {
+        period: f64,
+        amplitude: f64,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub delay: f64,
+    pub request: ServerRequest,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Workload {
+    pub rate: f64,
+    pub rates: HashMap<String, f64>,
+    pub operations: OperationMix,
+    pub operands: OperandDistribution,
+    pub arrival: Arrival,
+    pub trace: Option<String>,
+}
+
+pub struct WorkloadGenerator {
+    pub workload: Workload,
+    pub arrival: Box<dyn ArrivalProcess>,
+    pub rng: SmallRng,
+    pub trace: Option<VecDeque<TraceEntry>>,
+}
+
+impl Default for OperationMix {
+    fn default() -> Self {
+        Self {
+            add: 1.,
+            sub: 1.,
+            mul: 1.,
+            div: 1.,
+        }
+    }
+}
+
+impl OperationMix {
+    /// The weights may not be negative and at least one must be positive.
+    pub fn validate(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let weights = [self.add, self.sub, self.mul, self.div];
+        if weights
+            .iter()
+            .any(|weight| !weight.is_finite() || *weight < 0.)
+        {
+            return Err("The operation weights must be non-negative numbers.".into());
+        }
+        if weights.iter().sum::<f64>() <= 0. {
+            return Err("At least one operation needs a positive weight.".into());
+        }
+        Ok(())
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R, a: i32, b: i32) -> ServerRequest {
+        let total = self.add + self.sub + self.mul + self.div;
+        let mut pick = rng.random::<f64>() * total;
+
+        for (weight, request) in [
+            (
+                self.add,
+                ServerRequest::Add as fn(i32, i32) -> ServerRequest,
+            ),
+            (self.sub, ServerRequest::Sub),
+            (self.mul, ServerRequest::Mul),
+        ] {
+            if pick < weight {
+                return request(a, b);
+            }
+            pick -= weight;
+        }
+
+        ServerRequest::Div(a, b)
+    }
+}
+
+impl OperandDistribution {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> i32 {
+        match self {
+            Self::Full => rng.random::<i32>(),
+            Self::Uniform { min, max } => rng.random_range(*min..=*max),
+            Self::Normal { mean, std_dev } => {
+                // Box-Muller transform
+                let u1 = 1. - rng.random::<f64>();
+                let u2 = rng.random::<f64>();
+                let z = (-2. * u1.ln()).sqrt() * (2. * PI * u2).cos();
+
+                (mean + std_dev * z).round() as i32
+            }
+        }
+    }
+}
+
+impl Arrival {
+    /// The process running at `rate`, fails when its parameters are out of range.
+    pub fn process(
+        &self,
+        rate: f64,
+        seed: &[u8; 32],
+    ) -> Result<Box<dyn ArrivalProcess>, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            Self::Poisson => Box::new(Poisson::new(rate, seed)),
+            Self::Deterministic => Box::new(Deterministic::new(rate)?),
+            Self::Uniform { min, max } => Box::new(UniformArrival::new(*min, *max, seed)?),
+            Self::Pareto { shape } => Box::new(Pareto::with_rate(rate, *shape, seed)?),
+            Self::MarkovModulated {
+                rates,
+                switch_rates,
+            } => Box::new(MarkovModulatedPoisson::new(
+                rates.clone(),
+                switch_rates.clone(),
+                seed,
+            )?),
+            Self::BatchPoisson { mean_batch } => {
+                Box::new(BatchPoisson::new(rate, *mean_batch, seed)?)
+            }
+            Self::OnOff { on, off } => Box::new(OnOff::new(rate, *on, *off, seed)?),
+            Self::Diurnal { period, amplitude } => {
+                Box::new(Diurnal::new(rate, *period, *amplitude, seed)?)
+            }
+        })
+    }
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self {
+            rate: RATE,
+            rates: HashMap::new(),
+            operations: OperationMix::default(),
+            operands: OperandDistribution::default(),
+            arrival: Arrival::default(),
+            trace: None,
+        }
+    }
+}
+
+impl Workload {
+    pub fn from_json_file(path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(serde_json::from_str::<Self>(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn rate_for(&self, address: &str) -> f64 {
+        self.rates.get(address).copied().unwrap_or(self.rate)
+    }
+
+    /// Rejects the workloads that would generate meaningless delays or requests.
+    pub fn validate(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let positive = |name: &str, rate: f64| {
+            if rate.is_finite() && rate > 0. {
+                Ok(())
+            } else {
+                Err(format!("The {name} must be a positive number, not {rate}."))
+            }
+        };
+        positive("rate", self.rate)?;
+        for (address, rate) in &self.rates {
+            positive(&format!("rate of {address}"), *rate)?;
+        }
+        for rate in std::iter::once(&self.rate).chain(self.rates.values()) {
+            self.arrival.process(*rate, &[0; 32])?;
+        }
+
+        self.operations.validate()?;
+        match self.operands {
+            OperandDistribution::Uniform { min, max } if min > max => {
+                return Err(
+                    format!("The operands' minimum {min} is above their maximum {max}.").into(),
+                );
+            }
+            OperandDistribution::Normal { mean, .. } if !mean.is_finite() => {
+                return Err(format!("The operands' mean must be a number, not {mean}.").into());
+            }
+            OperandDistribution::Normal { std_dev, .. } if !std_dev.is_finite() || std_dev < 0. => {
+                return Err(format!(
+                    "The operands' standard deviation must be a non-negative number, not {std_dev}."
+                )
+                .into());
+            }
+            _ => {}
+        }
+        self.load_trace()?;
+        Ok(())
+    }
+
+    /// The entries of the trace to replay, if any.
+    fn load_trace(&self) -> Result<Option<VecDeque<TraceEntry>>, Box<dyn Error + Send + Sync>> {
+        let Some(path) = &self.trace else {
+            return Ok(None);
+        };
+        let trace = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str::<TraceEntry>)
+            .collect::<Result<VecDeque<_>, _>>()?;
+
+        if let Some(i) = trace
+            .iter()
+            .position(|entry| !entry.delay.is_finite() || entry.delay < 0.)
+        {
+            return Err(format!(
+                "Entry {i} of the trace {path} has an invalid delay {}.",
+                trace[i].delay
+            )
+            .into());
+        }
+        Ok(Some(trace))
+    }
+
+    pub fn generator(
+        &self,
+        address: &str,
+        seed: &[u8; 32],
+    ) -> Result<WorkloadGenerator, Box<dyn Error + Send + Sync>> {
+        let trace = self.load_trace()?;
+
+        // requests and arrivals draw from independent streams of the same seed
+        let mut rng = SmallRng::from_seed(*seed);
+        let mut arrival_seed = [0u8; 32];
+        rng.fill_bytes(&mut arrival_seed);
+
+        Ok(WorkloadGenerator {
+            workload: self.clone(),
+            arrival: self
+                .arrival
+                .process(self.rate_for(address), &arrival_seed)?,
+            rng,
+            trace,
+        })
+    }
+}
+
+impl WorkloadGenerator {
+    pub fn with_arrival(mut self, arrival: Box<dyn ArrivalProcess>) -> Self {
+        self.arrival = arrival;
+        self
+    }
+
+    /// Returns the time until the next arrival and the requests it carries, or `None` once a
+    /// replayed trace is exhausted.
+    pub fn next_requests(&mut self) -> Option<(Duration, Vec<ServerRequest>)> {
+        if let Some(trace) = &mut self.trace {
+            let entry = trace.pop_front()?;
+            return Some((Duration::from_secs_f64(entry.delay), vec![entry.request]));
+        }
+
+        let (delay, count) = self.arrival.next_arrival();
+        let requests = (0..count)
+            .map(|_| {
+                let a = self.workload.operands.sample(&mut self.rng);
+                let b = self.workload.operands.sample(&mut self.rng);
+                self.workload.operations.sample(&mut self.rng, a, b)
+            })
+            .collect();
+
+        Some((Duration::from_secs_f64(delay), requests))
+    }
+}
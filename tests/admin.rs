@@ -0,0 +1,223 @@
+use std::time::Duration;
+use token_ring::{
+    admin::{admits, request, AdminRequest, AdminResponse},
+    harness::{Ring, RingBuilder},
+    identity::NodeId,
+    transport::Tcp,
+};
+use tokio::time::{sleep, timeout};
+
+async fn admin(ring: &Ring, admin_request: AdminRequest) -> AdminResponse {
+    request(&Tcp, ring.admin_address.as_ref().unwrap(), &admin_request)
+        .await
+        .unwrap()
+}
+
+/// Waits until every peer of the ring reports its status.
+async fn ring_order(ring: &Ring, number_of_peers: usize) -> (Vec<String>, Option<NodeId>) {
+    for _ in 0..100 {
+        if let AdminResponse::Ring { order, holder, .. } = admin(ring, AdminRequest::Ring).await {
+            if order.len() == number_of_peers {
+                return (order.iter().map(|id| id.to_string()).collect(), holder);
+            }
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    panic!("The peers never reported their status.");
+}
+
+async fn visits(ring: &Ring) -> Vec<u64> {
+    match admin(ring, AdminRequest::Stats).await {
+        AdminResponse::Stats { peers, .. } => peers.iter().map(|peer| peer.visits).collect(),
+        response => panic!("Unexpected response {response:?}"),
+    }
+}
+
+async fn epoch(ring: &Ring) -> u64 {
+    match admin(ring, AdminRequest::Stats).await {
+        AdminResponse::Stats { epoch, .. } => epoch,
+        response => panic!("Unexpected response {response:?}"),
+    }
+}
+
+#[tokio::test]
+async fn admin_lists_peers_and_ring_order() {
+    let mut builder = RingBuilder::new(3);
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+
+    let (order, _) = ring_order(&ring, 3).await;
+    assert_eq!(order, ["peer-0", "peer-1", "peer-2"]);
+
+    match admin(&ring, AdminRequest::Peers).await {
+        AdminResponse::Peers(peers) => {
+            assert_eq!(
+                peers.keys().map(|id| id.as_str()).collect::<Vec<_>>(),
+                ["peer-0", "peer-1", "peer-2"]
+            );
+        }
+        response => panic!("Unexpected response {response:?}"),
+    }
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn concurrent_admins_get_every_status() {
+    let mut builder = RingBuilder::new(3);
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    ring_order(&ring, 3).await;
+
+    let (first, second, third) = tokio::join!(visits(&ring), visits(&ring), visits(&ring));
+    for visits in [first, second, third] {
+        assert_eq!(visits.len(), 3);
+    }
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn pausing_stops_the_hot_potato() {
+    let mut builder = RingBuilder::new(2);
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    ring_order(&ring, 2).await;
+
+    assert!(matches!(
+        admin(&ring, AdminRequest::Pause).await,
+        AdminResponse::Done(_)
+    ));
+    sleep(Duration::from_millis(200)).await;
+
+    let (_, holder) = ring_order(&ring, 2).await;
+    assert!(holder.is_some());
+    let paused = visits(&ring).await;
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(visits(&ring).await, paused);
+
+    admin(&ring, AdminRequest::Resume).await;
+    sleep(Duration::from_millis(200)).await;
+    assert!(visits(&ring).await.iter().sum::<u64>() > paused.iter().sum::<u64>());
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn regenerated_hot_potato_replaces_the_old_one() {
+    let mut builder = RingBuilder::new(3);
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    ring_order(&ring, 3).await;
+
+    let first_epoch = epoch(&ring).await;
+    let response = admin(
+        &ring,
+        AdminRequest::Regenerate {
+            peer: Some(NodeId::new("peer-2")),
+        },
+    )
+    .await;
+    assert!(matches!(response, AdminResponse::Done(_)), "{response:?}");
+
+    let before = visits(&ring).await;
+    sleep(Duration::from_millis(200)).await;
+    let after = visits(&ring).await;
+    assert!(after
+        .iter()
+        .zip(&before)
+        .all(|(after, before)| after > before));
+
+    match admin(&ring, AdminRequest::Stats).await {
+        AdminResponse::Stats { stats, epoch, .. } => {
+            assert_eq!(epoch, first_epoch + 1);
+            assert_eq!(stats.regenerations, 1);
+        }
+        response => panic!("Unexpected response {response:?}"),
+    }
+
+    let response = admin(
+        &ring,
+        AdminRequest::Regenerate {
+            peer: Some(NodeId::new("peer-9")),
+        },
+    )
+    .await;
+    assert!(matches!(response, AdminResponse::Error(_)));
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn evicted_peers_are_bypassed() {
+    let mut builder = RingBuilder::new(3);
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    ring_order(&ring, 3).await;
+
+    let response = admin(
+        &ring,
+        AdminRequest::Evict {
+            peer: NodeId::new("peer-1"),
+        },
+    )
+    .await;
+    assert!(matches!(response, AdminResponse::Done(_)), "{response:?}");
+
+    sleep(Duration::from_millis(200)).await;
+    let (order, _) = ring_order(&ring, 2).await;
+    assert_eq!(order, ["peer-0", "peer-2"]);
+
+    let before = visits(&ring).await;
+    sleep(Duration::from_millis(200)).await;
+    let after = visits(&ring).await;
+    assert_eq!(after.len(), 2);
+    assert!(after
+        .iter()
+        .zip(&before)
+        .all(|(after, before)| after > before));
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn read_leases_leave_with_evicted_peers() {
+    let mut builder = RingBuilder::new(3);
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    ring_order(&ring, 3).await;
+
+    let lease = timeout(Duration::from_secs(10), ring.locks()[0].lock_shared())
+        .await
+        .unwrap()
+        .unwrap();
+    let response = admin(
+        &ring,
+        AdminRequest::Evict {
+            peer: NodeId::new("peer-0"),
+        },
+    )
+    .await;
+    assert!(matches!(response, AdminResponse::Done(_)), "{response:?}");
+
+    // the new hot potato doesn't wait for the evicted reader
+    let guard = timeout(Duration::from_secs(10), ring.locks()[1].lock())
+        .await
+        .unwrap()
+        .unwrap();
+    drop(guard);
+    drop(lease);
+
+    ring.stop().await;
+}
+
+#[test]
+fn only_local_admins_are_served_without_tls() {
+    assert!(admits("127.0.0.1:4000", false));
+    assert!(admits("[::1]:4000", false));
+    assert!(admits("/tmp/admin.sock", false));
+    assert!(admits("", false));
+    assert!(!admits("192.168.1.7:4000", false));
+    assert!(!admits("[2001:db8::1]:4000", false));
+    assert!(admits("192.168.1.7:4000", true));
+}
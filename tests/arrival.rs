@@ -0,0 +1,97 @@
+use token_ring::{arrival::*, workload::Arrival};
+
+fn arrivals() -> Vec<Arrival> {
+    vec![
+        Arrival::Poisson,
+        Arrival::Deterministic,
+        Arrival::Uniform { min: 0.5, max: 2. },
+        Arrival::Pareto { shape: 2.5 },
+        Arrival::MarkovModulated {
+            rates: vec![10., 0.],
+            switch_rates: vec![1., 2.],
+        },
+        Arrival::BatchPoisson { mean_batch: 3. },
+        Arrival::OnOff { on: 1., off: 4. },
+        Arrival::Diurnal {
+            period: 10.,
+            amplitude: 0.8,
+        },
+    ]
+}
+
+fn sample(arrival: &Arrival, seed: u8) -> Vec<(f64, usize)> {
+    let mut process = arrival.process(2., &[seed; 32]).unwrap();
+    (0..100).map(|_| process.next_arrival()).collect()
+}
+
+#[test]
+fn arrivals_of_the_same_seed_agree() {
+    for arrival in arrivals() {
+        let arrivals = sample(&arrival, 1);
+        assert_eq!(arrivals, sample(&arrival, 1), "{arrival:?}");
+        assert!(
+            arrivals
+                .iter()
+                .all(|(delay, count)| delay.is_finite() && *delay >= 0. && *count >= 1),
+            "{arrival:?}"
+        );
+        if !matches!(arrival, Arrival::Deterministic) {
+            assert_ne!(arrivals, sample(&arrival, 2), "{arrival:?}");
+        }
+    }
+}
+
+#[test]
+fn out_of_range_arrivals_are_rejected() {
+    let invalid = [
+        Arrival::Deterministic.process(0., &[0; 32]),
+        Arrival::Uniform { min: -1., max: 1. }.process(1., &[0; 32]),
+        Arrival::Uniform { min: 2., max: 1. }.process(1., &[0; 32]),
+        Arrival::Pareto { shape: 1. }.process(1., &[0; 32]),
+        Arrival::MarkovModulated {
+            rates: vec![],
+            switch_rates: vec![],
+        }
+        .process(1., &[0; 32]),
+        Arrival::MarkovModulated {
+            rates: vec![1., 2.],
+            switch_rates: vec![1.],
+        }
+        .process(1., &[0; 32]),
+        Arrival::MarkovModulated {
+            rates: vec![0., 0.],
+            switch_rates: vec![1., 1.],
+        }
+        .process(1., &[0; 32]),
+        Arrival::BatchPoisson { mean_batch: 0.5 }.process(1., &[0; 32]),
+        Arrival::OnOff { on: 0., off: 0. }.process(1., &[0; 32]),
+        Arrival::Diurnal {
+            period: 0.,
+            amplitude: 0.5,
+        }
+        .process(1., &[0; 32]),
+        Arrival::Diurnal {
+            period: 1.,
+            amplitude: 2.,
+        }
+        .process(1., &[0; 32]),
+    ];
+    for (i, process) in invalid.into_iter().enumerate() {
+        assert!(process.is_err(), "arrival {i} was accepted");
+    }
+
+    assert!(Pareto::new(0., 2., &[0; 32]).is_err());
+    assert!(Pareto::with_rate(1., 0.5, &[0; 32]).is_err());
+}
+
+#[test]
+fn pareto_arrivals_keep_the_mean_of_their_rate() {
+    let mut pareto = Pareto::with_rate(4., 3., &[7; 32]).unwrap();
+    let delays = (0..100_000)
+        .map(|_| pareto.next_arrival().0)
+        .collect::<Vec<_>>();
+
+    assert!(delays.iter().all(|delay| *delay >= pareto.scale));
+    let mean = delays.iter().sum::<f64>() / delays.len() as f64;
+    assert!((0.22..0.28).contains(&mean), "mean {mean}");
+}
@@ -0,0 +1,216 @@
+use token_ring::{
+    auth::{TokenGuard, TokenKey, TokenRejection},
+    checker::{EventKind, EventRecorder},
+    harness::Harness,
+    identity::NodeId,
+    message::{HotPotato, HotPotatoState},
+    peer::Peer,
+};
+use tokio::sync::mpsc;
+
+fn signed(key: &TokenKey, epoch: u64, sequence: u64, holder: &str) -> HotPotato {
+    let mut hot_potato = HotPotato {
+        epoch,
+        sequence,
+        holder: NodeId::new(holder),
+        resource: String::new(),
+        readers: Vec::new(),
+        waiting_writer: None,
+        demand: Vec::new(),
+        wanted_by: Vec::new(),
+        idle_since: None,
+        idle_rotations: 0,
+        mac: None,
+    };
+    key.sign(&mut hot_potato);
+    hot_potato
+}
+
+#[test]
+fn signatures_cover_epoch_sequence_and_holder() {
+    let key = TokenKey::new(b"secret");
+    let hot_potato = signed(&key, 3, 7, "peer-0");
+    assert_eq!(key.verify(&hot_potato), Ok(()));
+
+    for tampered in [
+        HotPotato {
+            epoch: 4,
+            ..hot_potato.clone()
+        },
+        HotPotato {
+            sequence: 8,
+            ..hot_potato.clone()
+        },
+        HotPotato {
+            holder: NodeId::new("peer-1"),
+            ..hot_potato.clone()
+        },
+        HotPotato {
+            demand: vec![NodeId::new("peer-1")],
+            ..hot_potato.clone()
+        },
+        HotPotato {
+            wanted_by: vec![NodeId::new("peer-1")],
+            ..hot_potato.clone()
+        },
+        HotPotato {
+            idle_since: Some(NodeId::new("peer-1")),
+            idle_rotations: 5,
+            ..hot_potato.clone()
+        },
+    ] {
+        assert_eq!(key.verify(&tampered), Err(TokenRejection::Forged));
+    }
+    assert_eq!(
+        TokenKey::new(b"other secret").verify(&hot_potato),
+        Err(TokenRejection::Forged)
+    );
+}
+
+#[test]
+fn signatures_tell_fields_apart() {
+    let key = TokenKey::new(b"secret");
+    let mut demanded = signed(&key, 3, 7, "peer-0");
+    demanded.demand = vec![NodeId::new("db")];
+    key.sign(&mut demanded);
+
+    // the demand list can't pass for a resource name
+    let resource = HotPotato {
+        resource: "db".to_string(),
+        demand: Vec::new(),
+        ..demanded.clone()
+    };
+    assert_eq!(key.verify(&resource), Err(TokenRejection::Forged));
+
+    let wanted = HotPotato {
+        demand: Vec::new(),
+        wanted_by: vec![NodeId::new("db")],
+        ..demanded
+    };
+    assert_eq!(key.verify(&wanted), Err(TokenRejection::Forged));
+}
+
+#[test]
+fn guard_rejects_unsigned_misdirected_and_replayed_tokens() {
+    let key = TokenKey::new(b"secret");
+    let me = NodeId::new("peer-0");
+    let mut guard = TokenGuard::new(Some(key.clone()));
+
+    assert_eq!(
+        guard.check(&HotPotato::new(), &me),
+        Err(TokenRejection::Unsigned)
+    );
+    assert_eq!(
+        guard.check(&signed(&key, 0, 1, "peer-1"), &me),
+        Err(TokenRejection::Misdirected {
+            holder: NodeId::new("peer-1")
+        })
+    );
+    assert_eq!(guard.check(&signed(&key, 0, 1, "peer-0"), &me), Ok(()));
+    assert_eq!(
+        guard.check(&signed(&key, 0, 1, "peer-0"), &me),
+        Err(TokenRejection::Replayed { last_sequence: 1 })
+    );
+    assert_eq!(guard.check(&signed(&key, 0, 4, "peer-0"), &me), Ok(()));
+    // a new epoch starts its own sequence
+    guard.epoch = 1;
+    assert_eq!(guard.check(&signed(&key, 1, 0, "peer-0"), &me), Ok(()));
+}
+
+#[test]
+fn guard_without_key_accepts_everything() {
+    let mut guard = TokenGuard::default();
+    assert_eq!(
+        guard.check(&HotPotato::new(), &NodeId::new("peer-0")),
+        Ok(())
+    );
+}
+
+#[test]
+fn guard_drops_tokens_of_other_epochs() {
+    let me = NodeId::new("peer-0");
+    let key = TokenKey::new(b"secret");
+
+    for (mut guard, key) in [
+        (TokenGuard::default(), None),
+        (TokenGuard::new(Some(key.clone())), Some(key)),
+    ] {
+        let token = |epoch| {
+            let mut hot_potato = HotPotato {
+                epoch,
+                holder: me.clone(),
+                ..HotPotato::new()
+            };
+            if let Some(key) = &key {
+                key.sign(&mut hot_potato);
+            }
+            hot_potato
+        };
+
+        guard.epoch = 2;
+        assert_eq!(guard.check(&token(2), &me), Ok(()));
+        assert_eq!(
+            guard.check(&token(1), &me),
+            Err(TokenRejection::OtherEpoch { epoch: 2 })
+        );
+        // only the server announces new epochs, e.g. a token of an earlier run of the server
+        // doesn't move the guard on
+        assert_eq!(
+            guard.check(&token(9), &me),
+            Err(TokenRejection::OtherEpoch { epoch: 2 })
+        );
+        assert_eq!(guard.epoch, 2);
+    }
+}
+
+#[test]
+fn peers_drop_forged_tokens_and_alert() {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let mut peer = Peer::new(String::new(), String::new(), String::new());
+    peer.node_id = NodeId::new("peer-0");
+    peer.token_guard = TokenGuard::new(Some(TokenKey::new(b"secret")));
+    peer.recorder = EventRecorder::to_channel("peer-0", event_tx);
+
+    let forged = signed(&TokenKey::new(b"guessed"), 0, 1, "peer-0");
+    assert_eq!(peer.receive_hot_potato(forged), Err(TokenRejection::Forged));
+    assert_eq!(peer.hot_potato_state, HotPotatoState::NotHolding);
+    assert!(matches!(
+        event_rx.try_recv().unwrap().kind,
+        EventKind::TokenRejected(_)
+    ));
+}
+
+#[tokio::test]
+async fn ring_runs_with_signed_tokens() {
+    let harness = Harness {
+        token_key: Some(TokenKey::new(b"secret")),
+        ..Harness::new(3, 20)
+    };
+    let report = harness.run().await.unwrap();
+
+    assert_eq!(report.rotations, 20);
+    assert!(!report
+        .events
+        .iter()
+        .any(|event| matches!(event.kind, EventKind::TokenRejected(_))));
+}
+
+#[tokio::test]
+async fn every_server_run_starts_its_own_epoch() {
+    let harness = Harness {
+        token_key: Some(TokenKey::new(b"secret")),
+        ..Harness::new(2, 2)
+    };
+
+    let mut epochs = Vec::new();
+    for _ in 0..2 {
+        let report = harness.run().await.unwrap();
+        epochs.extend(report.events.iter().find_map(|event| match event.kind {
+            EventKind::TokenReceived { epoch, .. } => Some(epoch),
+            _ => None,
+        }));
+    }
+    // the tokens of the first run would be rejected by the second
+    assert_eq!(epochs.len(), 2);
+    assert_ne!(epochs[0], epochs[1]);
+}
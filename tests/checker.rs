@@ -0,0 +1,89 @@
+use token_ring::checker::{Checker, Event, EventKind, Invariant};
+
+fn received(sequence: u64) -> EventKind {
+    EventKind::TokenReceived { epoch: 1, sequence }
+}
+
+fn sent(sequence: u64) -> EventKind {
+    EventKind::TokenSent { epoch: 1, sequence }
+}
+
+/// A visit of `node` to the hot potato of `sequence`, which it throws on a tick later.
+fn visit(at: u64, node: &str, sequence: u64) -> Vec<Event> {
+    vec![
+        Event::new(at, node, received(sequence)),
+        Event::new(at, node, EventKind::CriticalSectionEnter),
+        Event::new(at + 1, node, EventKind::CriticalSectionExit),
+        Event::new(at + 1, node, sent(sequence + 1)),
+    ]
+}
+
+#[test]
+fn rotations_pass() {
+    let events = [visit(0, "a", 0), visit(2, "b", 1), visit(4, "a", 2)].concat();
+
+    let summary = Checker::default().check(&events).unwrap();
+    assert_eq!(summary.events, 12);
+    assert_eq!(summary.critical_sections, 3);
+}
+
+#[test]
+fn tied_clocks_deliver_after_the_send() {
+    // b's events come first and share their times with a's
+    let mut events = visit(1, "b", 1);
+    events.extend(visit(0, "a", 0));
+
+    Checker::default().check(&events).unwrap();
+}
+
+#[test]
+fn skewed_clocks_deliver_after_the_send() {
+    // b's clock is behind, it received the token before a sent it by the timestamps
+    let mut events = visit(0, "a", 0);
+    events.extend(visit(0, "b", 1).into_iter().map(|event| Event {
+        at: event.at.saturating_sub(1),
+        ..event
+    }));
+
+    Checker::default().check(&events).unwrap();
+}
+
+#[test]
+fn overlapping_critical_sections_are_violations() {
+    let events = [
+        Event::new(0, "a", received(0)),
+        Event::new(1, "a", EventKind::CriticalSectionEnter),
+        Event::new(2, "b", EventKind::CriticalSectionEnter),
+        Event::new(3, "a", EventKind::CriticalSectionExit),
+        Event::new(4, "b", EventKind::CriticalSectionExit),
+    ];
+
+    let violation = Checker::default().check(&events).unwrap_err();
+    assert_eq!(violation.invariant, Invariant::MutualExclusion);
+    assert_eq!(violation.event.node, "b");
+}
+
+#[test]
+fn tokens_held_twice_are_violations() {
+    // b got the token that a never sent on
+    let events = [
+        Event::new(0, "a", received(1)),
+        Event::new(1, "b", received(2)),
+        Event::new(2, "c", received(2)),
+    ];
+
+    let violation = Checker::default().check(&events).unwrap_err();
+    assert_eq!(violation.invariant, Invariant::TokenUniqueness);
+}
+
+#[test]
+fn bypassing_a_waiting_node_too_often_is_a_violation() {
+    let mut events = vec![Event::new(0, "c", EventKind::RequestQueued)];
+    for (i, node) in ["a", "b", "a"].into_iter().enumerate() {
+        events.extend(visit(1 + 2 * i as u64, node, i as u64));
+    }
+
+    assert!(Checker::new(Some(3)).check(&events).is_ok());
+    let violation = Checker::new(Some(2)).check(&events).unwrap_err();
+    assert_eq!(violation.invariant, Invariant::BoundedWaiting);
+}
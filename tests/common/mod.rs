@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+
+use std::io;
+use token_ring::{
+    transport::{Connection, Listener, MemoryListener},
+    workload::{Arrival, Workload},
+};
+
+/// In-memory connections that look like they come from another host.
+pub struct Remote(pub MemoryListener);
+
+impl Listener for Remote {
+    async fn accept(&mut self) -> io::Result<(Connection, String)> {
+        let (lines, _) = self.0.accept().await?;
+        Ok((lines, "192.168.1.7:4000".to_string()))
+    }
+
+    fn local_address(&self) -> io::Result<String> {
+        self.0.local_address()
+    }
+}
+
+/// Peers send a request on their first visit and then next to none.
+pub fn idle_workload() -> Workload {
+    Workload {
+        rate: 0.001,
+        arrival: Arrival::Deterministic,
+        ..Default::default()
+    }
+}
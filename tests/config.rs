@@ -0,0 +1,264 @@
+use std::{io::Write, time::Duration};
+use token_ring::{
+    config::{parse, Algorithm, PeerConfig, ServerConfig},
+    identity::NodeId,
+    log::Level,
+    peer::Batching,
+    transport::{TransportKind, DEFAULT_MAX_LINE_LENGTH},
+};
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(var, value)| (var.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn peer_config_is_read_from_toml() {
+    let config: PeerConfig = parse(
+        r#"
+        address = "127.0.0.1:8001"
+        server_address = "127.0.0.1:8000"
+        next_peer_address = "127.0.0.1:8002"
+        node_id = "peer-1"
+        transport = "unix"
+        algorithm = "on-demand"
+        retry_delay_ms = 250
+        max_operations = 4
+        min_hop_delay_ms = 5
+        max_idle_delay_ms = 500
+        pipeline_timeout_ms = 100
+        batching = "all-or-nothing"
+
+        [weights]
+        peer-2 = 2
+
+        [workload]
+        rate = 3.5
+
+        [log]
+        level = "warning"
+        clear = false
+
+        [codec]
+        max_line_length = 4096
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+
+    assert_eq!(config.address.as_deref(), Some("127.0.0.1:8001"));
+    assert_eq!(config.node_id.as_deref(), Some("peer-1"));
+    assert_eq!(config.transport, TransportKind::Unix);
+    assert_eq!(config.algorithm, Algorithm::OnDemand);
+    assert_eq!(config.retry_delay(), Duration::from_millis(250));
+    assert_eq!(config.max_operations, Some(4));
+    assert_eq!(config.batching, Batching::AllOrNothing);
+    assert_eq!(config.weights[&NodeId::new("peer-2")], 2);
+    assert_eq!(config.workload.as_ref().unwrap().rate, 3.5);
+    assert_eq!(config.log.level, Level::Warning);
+    assert!(!config.log.clear);
+    assert_eq!(config.codec.max_line_length, 4096);
+    config.validate().unwrap();
+
+    let peer = config.to_peer().unwrap();
+    assert_eq!(peer.node_id.as_str(), "peer-1");
+    assert_eq!(peer.batching, Batching::AllOrNothing);
+    assert_eq!(peer.algorithm, Algorithm::OnDemand);
+    assert_eq!(peer.pacing_policy.min_hop_delay, Duration::from_millis(5));
+    assert_eq!(
+        peer.pacing_policy.max_idle_delay,
+        Duration::from_millis(500)
+    );
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let config: ServerConfig = parse(
+        r#"
+        address = "127.0.0.1:8000"
+        number_of_peers = 3
+        "#,
+        vars(&[
+            ("TOKEN_RING_NUMBER_OF_PEERS", "5"),
+            ("TOKEN_RING_LOG__LEVEL", "error"),
+            ("TOKEN_RING_NODE_ID", "server-a"),
+            ("UNRELATED", "ignored"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.number_of_peers, Some(5));
+    assert_eq!(config.log.level, Level::Error);
+    assert_eq!(config.node_id.as_deref(), Some("server-a"));
+    assert_eq!(config.restart_delay(), Duration::from_secs(5));
+    assert_eq!(config.codec.max_line_length, DEFAULT_MAX_LINE_LENGTH);
+    config.validate().unwrap();
+}
+
+#[test]
+fn errors_name_the_offending_key() {
+    let error = parse::<PeerConfig>("[codec]\nmax_line_length = \"long\"", vars(&[])).unwrap_err();
+    assert_eq!(error.key, "codec.max_line_length");
+
+    let error = parse::<PeerConfig>("[log]\nverbose = true", vars(&[])).unwrap_err();
+    assert_eq!(error.key, "log.verbose");
+
+    let error = parse::<PeerConfig>("[codec]\nformat = \"json-lines\"", vars(&[])).unwrap_err();
+    assert_eq!(error.key, "codec.format");
+
+    let error = parse::<PeerConfig>("[workload]\nad = 3", vars(&[])).unwrap_err();
+    assert_eq!(error.key, "workload.ad");
+
+    let error = parse::<PeerConfig>(
+        "[workload.operands.Normal]\nmean = 0.0\nstd_dev = 1.0\ndeviation = 2.0",
+        vars(&[]),
+    )
+    .unwrap_err();
+    assert_eq!(error.key, "workload.operands.Normal.deviation");
+
+    let error =
+        parse::<ServerConfig>("", vars(&[("TOKEN_RING_RESTART_DELAY_MS", "soon")])).unwrap_err();
+    assert_eq!(error.key, "restart_delay_ms");
+    assert!(error.message.contains("TOKEN_RING_RESTART_DELAY_MS"));
+
+    let error = parse::<ServerConfig>("transport = \"carrier-pigeon\"", vars(&[])).unwrap_err();
+    assert_eq!(error.key, "transport");
+
+    // only peers choose how the hot potato moves
+    let error = parse::<ServerConfig>("algorithm = \"on-demand\"", vars(&[])).unwrap_err();
+    assert_eq!(error.key, "algorithm");
+}
+
+#[test]
+fn validation_rejects_incomplete_or_invalid_configs() {
+    let config: PeerConfig = parse(
+        r#"
+        address = "127.0.0.1:8001"
+        server_address = "127.0.0.1:8000"
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "next_peer_address");
+
+    let config: PeerConfig = parse(
+        r#"
+        address = "127.0.0.1:8001"
+        server_address = "127.0.0.1:8000"
+        next_peer_address = "127.0.0.1:8002"
+
+        [workload]
+        rate = -1.0
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    let error = config.validate().unwrap_err();
+    assert_eq!(error.key, "workload");
+    assert!(error.message.contains("rate"), "{error}");
+
+    let config: PeerConfig = parse(
+        r#"
+        address = "127.0.0.1:8001"
+        server_address = "127.0.0.1:8000"
+        next_peer_address = "127.0.0.1:8002"
+
+        [workload.operands.Uniform]
+        min = 10
+        max = 1
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "workload");
+
+    let mut workload_file = tempfile::NamedTempFile::new().unwrap();
+    writeln!(
+        workload_file,
+        r#"{{"operations": {{"add": 0, "sub": 0, "mul": 0, "div": 0}}}}"#
+    )
+    .unwrap();
+    let config: PeerConfig = parse(
+        &format!(
+            r#"
+            address = "127.0.0.1:8001"
+            server_address = "127.0.0.1:8000"
+            next_peer_address = "127.0.0.1:8002"
+            workload_file = "{}"
+            "#,
+            workload_file.path().display()
+        ),
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "workload_file");
+
+    let config: PeerConfig = parse(
+        r#"
+        address = "127.0.0.1:8001"
+        server_address = "127.0.0.1:8000"
+        next_peer_address = "127.0.0.1:8002"
+        min_hop_delay_ms = 100
+        max_idle_delay_ms = 10
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "max_idle_delay_ms");
+
+    let config: PeerConfig = parse(
+        r#"
+        address = "127.0.0.1:8001"
+        server_address = "127.0.0.1:8000"
+        next_peer_address = "127.0.0.1:8002"
+        max_hold_time_ms = 100
+
+        [weights]
+        peer-2 = 2
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "weights");
+
+    let config: ServerConfig = parse(
+        r#"
+        address = "127.0.0.1:8000"
+        number_of_peers = 2
+        shared_operations = []
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "shared_operations");
+
+    let config: ServerConfig = parse(
+        r#"
+        address = "127.0.0.1:8000"
+        number_of_peers = 2
+
+        [tls]
+        ca = "/nonexistent/ca.pem"
+        certificate = "/nonexistent/server.pem"
+        key = "/nonexistent/server.key"
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "tls.ca");
+
+    let config: ServerConfig = parse("address = \"127.0.0.1:8000\"", vars(&[])).unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "number_of_peers");
+
+    let config: ServerConfig = parse(
+        r#"
+        address = "127.0.0.1:8000"
+        number_of_peers = 2
+        resources = ["printer", "disk", "printer"]
+        "#,
+        vars(&[]),
+    )
+    .unwrap();
+    assert_eq!(config.validate().unwrap_err().key, "resources");
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use common::Remote;
+use std::time::Duration;
+use token_ring::{
+    checker::{Event, EventKind, EventRecorder},
+    dashboard::{self, Dashboard},
+    identity::NodeId,
+    message::ServerResponse,
+    transport::{Memory, Transport},
+};
+use tokio::{sync::mpsc, time::timeout};
+
+fn visit(events: &mut Vec<Event>, at: u64, node: &str, sequence: u64) {
+    events.extend([
+        Event::new(at, node, EventKind::TokenReceived { epoch: 0, sequence }),
+        Event::new(at, node, EventKind::CriticalSectionEnter),
+        Event::new(at, node, EventKind::CriticalSectionExit),
+        Event::new(
+            at,
+            node,
+            EventKind::TokenSent {
+                epoch: 0,
+                sequence: sequence + 1,
+            },
+        ),
+    ]);
+}
+
+#[test]
+fn ring_is_learned_from_the_throws() {
+    let mut events = Vec::new();
+    for (i, node) in ["peer-c", "peer-a", "peer-b", "peer-c", "peer-a"]
+        .iter()
+        .enumerate()
+    {
+        visit(&mut events, i as u64 * 100_000, node, i as u64);
+    }
+    events.push(Event::new(
+        500_000,
+        "peer-b",
+        EventKind::TokenReceived {
+            epoch: 0,
+            sequence: 5,
+        },
+    ));
+
+    let mut dashboard = Dashboard::new();
+    for event in &events {
+        dashboard.apply(event);
+    }
+
+    assert_eq!(dashboard.ring(), ["peer-a", "peer-b", "peer-c"]);
+    assert_eq!(dashboard.holder.as_deref(), Some("peer-b"));
+    assert_eq!(dashboard.nodes["peer-c"].visits, 2);
+    // 5 throws over a window of 5 seconds, in a ring of 3 peers
+    assert!((dashboard.rotation_rate() - 1. / 3.).abs() < 1e-9);
+}
+
+#[test]
+fn queues_operations_and_errors_are_tracked() {
+    let mut dashboard = Dashboard::new();
+    for kind in [
+        EventKind::RequestQueued,
+        EventKind::RequestQueued,
+        EventKind::RequestQueued,
+        EventKind::RequestsSent(2),
+        EventKind::ResponseReceived(ServerResponse::Add(1, 2, 3)),
+        EventKind::ResponseReceived(ServerResponse::Err(1, 0, "Division by zero.".to_string())),
+        EventKind::TokenRejected("forged".to_string()),
+    ] {
+        dashboard.apply(&Event::new(0, "peer-0", kind));
+    }
+    dashboard.apply(&Event::new(0, "server", EventKind::RequestExecuted));
+
+    let status = &dashboard.nodes["peer-0"];
+    assert_eq!(status.queue_length, 1);
+    assert_eq!(status.responses, 2);
+    assert_eq!(status.errors, 2);
+    assert_eq!(dashboard.nodes["server"].requests_executed, 1);
+    assert_eq!(dashboard.recent_errors.len(), 2);
+
+    let screen = dashboard.render(40);
+    assert!(screen.contains("1 + 2 = 3"));
+    assert!(screen.contains("Division by zero."));
+    assert!(screen.contains("Rejected a hot potato: forged."));
+}
+
+#[test]
+fn rendering_fits_the_height() {
+    let mut dashboard = Dashboard::new();
+    for i in 0..50 {
+        dashboard.apply(&Event::new(
+            0,
+            "peer-0",
+            EventKind::ResponseReceived(ServerResponse::Mul(i, 2, i * 2)),
+        ));
+    }
+
+    let screen = dashboard.render(20);
+    assert!(screen.lines().count() <= 20);
+    // the latest operations are shown first
+    assert!(screen.contains("49 * 2 = 98"));
+}
+
+#[tokio::test]
+async fn recorded_events_reach_the_dashboard() {
+    let transport = Memory::new();
+    let listener = transport.bind("dashboard").await.unwrap();
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    tokio::spawn(dashboard::receive(listener, event_tx));
+
+    let mut recorder = EventRecorder::default();
+    dashboard::report_to(
+        &transport,
+        "dashboard",
+        &NodeId::new("peer-7"),
+        &mut recorder,
+    );
+    recorder.record(EventKind::RequestQueued);
+
+    let event = timeout(Duration::from_secs(5), event_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.node, "peer-7");
+    assert_eq!(event.kind, EventKind::RequestQueued);
+}
+
+#[tokio::test]
+async fn remote_nodes_may_not_report_without_tls() {
+    let transport = Memory::new();
+    let listener = transport.bind("dashboard").await.unwrap();
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    tokio::spawn(dashboard::receive(Remote(listener), event_tx));
+
+    let mut recorder = EventRecorder::default();
+    dashboard::report_to(
+        &transport,
+        "dashboard",
+        &NodeId::new("peer-7"),
+        &mut recorder,
+    );
+    recorder.record(EventKind::RequestQueued);
+
+    assert!(timeout(Duration::from_millis(200), event_rx.recv())
+        .await
+        .is_err());
+}
+
+#[test]
+fn forwarded_events_are_dropped_while_the_channel_is_full() {
+    let (event_tx, mut event_rx) = mpsc::channel(2);
+    let mut recorder = EventRecorder::default();
+    recorder.forward_to(event_tx);
+
+    for _ in 0..5 {
+        recorder.record(EventKind::RequestQueued);
+    }
+
+    assert!(event_rx.try_recv().is_ok());
+    assert!(event_rx.try_recv().is_ok());
+    assert!(event_rx.try_recv().is_err());
+}
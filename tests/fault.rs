@@ -0,0 +1,180 @@
+use futures::{SinkExt, StreamExt};
+use std::{sync::atomic::Ordering, time::Duration};
+use token_ring::{
+    auth::{TokenGuard, TokenKey, TokenRejection},
+    fault::{FaultConfig, Faulty},
+    identity::NodeId,
+    message::HotPotato,
+    peer::Peer,
+    server::Server,
+    transport::{Connection, Listener, Memory, Transport},
+};
+use tokio::{
+    task::JoinSet,
+    time::{timeout, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+/// Connects through a faulty link to `address`, returns both ends of the link.
+async fn link(faulty: &Faulty<Memory>, address: &str) -> (Connection, Connection) {
+    let mut listener = faulty.bind(address).await.unwrap();
+    let local = faulty.connect(address).await.unwrap();
+    let (remote, _) = listener.accept().await.unwrap();
+    (local, remote)
+}
+
+fn token(sequence: u64) -> HotPotato {
+    HotPotato {
+        epoch: 1,
+        sequence,
+        holder: NodeId::new("peer-1"),
+        ..HotPotato::new()
+    }
+}
+
+async fn received(remote: &mut Connection) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Ok(Some(Ok(line))) = timeout(Duration::from_secs(1), remote.next()).await {
+        lines.push(line);
+    }
+    lines
+}
+
+#[tokio::test(start_paused = true)]
+async fn lost_tokens_never_arrive() {
+    let config = FaultConfig {
+        drop: 1.,
+        ..Default::default()
+    };
+    let faulty = Faulty::new(Memory::default(), config, 1);
+    let (mut local, mut remote) = link(&faulty, "peer-1").await;
+
+    for sequence in 1..=3 {
+        local
+            .send(token(sequence).to_json_string().unwrap())
+            .await
+            .unwrap();
+    }
+    assert!(received(&mut remote).await.is_empty());
+    assert_eq!(faulty.stats.dropped.load(Ordering::Relaxed), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn duplicated_tokens_are_replays() {
+    let config = FaultConfig {
+        duplicate: 1.,
+        ..Default::default()
+    };
+    let faulty = Faulty::new(Memory::default(), config, 2);
+    let (mut local, mut remote) = link(&faulty, "peer-1").await;
+
+    let key = TokenKey::new(b"secret");
+    let mut hot_potato = token(1);
+    key.sign(&mut hot_potato);
+    local
+        .send(hot_potato.to_json_string().unwrap())
+        .await
+        .unwrap();
+
+    let lines = received(&mut remote).await;
+    assert_eq!(lines.len(), 2);
+    assert_eq!(faulty.stats.duplicated.load(Ordering::Relaxed), 1);
+
+    // the guard only lets the first copy through
+    let mut guard = TokenGuard::new(Some(key));
+    guard.epoch = 1;
+    let node_id = NodeId::new("peer-1");
+    let first = HotPotato::from_json_string(&lines[0]).unwrap();
+    let second = HotPotato::from_json_string(&lines[1]).unwrap();
+    assert!(guard.check(&first, &node_id).is_ok());
+    assert!(matches!(
+        guard.check(&second, &node_id),
+        Err(TokenRejection::Replayed { last_sequence: 1 })
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn held_back_lines_are_flushed_at_the_end() {
+    let config = FaultConfig {
+        reorder: 1.,
+        ..Default::default()
+    };
+    let faulty = Faulty::new(Memory::default(), config, 3);
+    let (mut local, mut remote) = link(&faulty, "peer-1").await;
+
+    for line in ["a", "b", "c"] {
+        local.send(line.to_string()).await.unwrap();
+    }
+    drop(local);
+
+    assert_eq!(received(&mut remote).await, ["b", "a", "c"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_links_are_severed_in_time() {
+    let config = FaultConfig {
+        sever_after: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let faulty = Faulty::new(Memory::default(), config, 4);
+    let (_local, mut remote) = link(&faulty, "server").await;
+
+    let started = Instant::now();
+    assert!(timeout(Duration::from_secs(1), remote.next())
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(started.elapsed(), Duration::from_millis(100));
+    assert_eq!(faulty.stats.severed.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn peers_stop_when_their_server_disconnects() {
+    let number_of_peers = 2;
+    // the hops take time, so the paused clock moves on while the hot potato goes around
+    let hop = FaultConfig {
+        delay: Some((Duration::from_millis(1), Duration::from_millis(1))),
+        ..Default::default()
+    };
+    let faulty = Faulty::new(Memory::default(), hop.clone(), 5).with_link(
+        "server",
+        FaultConfig {
+            sever_after: Some(Duration::from_secs(1)),
+            ..hop
+        },
+    );
+    let shutdown = CancellationToken::new();
+
+    let mut server = Server::new("server".to_string(), number_of_peers);
+    let listener = server.bind(&faulty).await.unwrap();
+    let server = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { server.serve(listener, shutdown).await })
+    };
+
+    let mut peers = JoinSet::new();
+    for i in 0..number_of_peers {
+        let mut peer = Peer::new(
+            format!("peer-{i}"),
+            "server".to_string(),
+            format!("peer-{}", (i + 1) % number_of_peers),
+        );
+        let listener = peer.bind(&faulty).await.unwrap();
+        let (faulty, shutdown) = (faulty.clone(), shutdown.clone());
+        peers.spawn(async move { peer.serve(&faulty, listener, shutdown).await });
+    }
+
+    // the peers give up on their own, without being shut down
+    timeout(Duration::from_secs(10), async {
+        while peers.join_next().await.is_some() {}
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        faulty.stats.severed.load(Ordering::Relaxed),
+        number_of_peers
+    );
+
+    shutdown.cancel();
+    let _ = server.await;
+}
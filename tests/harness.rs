@@ -0,0 +1,144 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use token_ring::{
+    checker::{Checker, EventKind},
+    harness::Harness,
+    identity::NodeId,
+    message::ServerResponse,
+    peer::{Batching, RequestMode},
+    policy::HoldPolicy,
+    transport::Memory,
+    workload::Workload,
+};
+
+fn busy_workload() -> Workload {
+    Workload {
+        rate: 200.,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn ring_completes_rotations_over_tcp() {
+    let report = Harness::new(3, 20).run().await.unwrap();
+
+    assert_eq!(report.rotations, 20);
+    assert_eq!(report.peer_addresses.len(), 3);
+    assert!(report.peer_addresses.iter().all(|a| !a.ends_with(":0")));
+    assert!(!report.server_address.ends_with(":0"));
+}
+
+#[tokio::test]
+async fn events_are_recorded_under_node_ids() {
+    let harness = Harness {
+        workload: busy_workload(),
+        ..Harness::new(3, 10)
+    };
+    let report = harness.run().await.unwrap();
+
+    let mut ids = report.peer_ids.clone();
+    ids.push(report.server_id.clone());
+    assert_eq!(
+        report.peer_ids,
+        ["peer-0", "peer-1", "peer-2"].map(NodeId::new)
+    );
+    assert!(report
+        .events
+        .iter()
+        .all(|event| ids.contains(&NodeId::new(&event.node))));
+    assert!(report
+        .responses
+        .keys()
+        .all(|id| report.peer_ids.contains(id)));
+}
+
+#[tokio::test]
+async fn recorded_events_satisfy_the_invariants() {
+    let harness = Harness {
+        workload: busy_workload(),
+        ..Harness::new(4, 50)
+    };
+    let report = harness.run().await.unwrap();
+
+    let summary = Checker::default().check(&report.events).unwrap();
+    assert!(summary.critical_sections >= 4 * 50);
+}
+
+#[tokio::test]
+async fn pipelined_peers_receive_every_response_before_throwing() {
+    let harness = Harness {
+        workload: busy_workload(),
+        request_mode: RequestMode::Pipelined {
+            timeout: Duration::from_secs(1),
+        },
+        hold_policy: HoldPolicy::new(Some(2), None, Default::default()),
+        ..Harness::new(2, 30)
+    };
+    let report = harness.run().await.unwrap();
+
+    // a response is always received while its peer is still in the critical section
+    let mut in_critical_section = HashSet::new();
+    for event in &report.events {
+        match &event.kind {
+            EventKind::CriticalSectionEnter => {
+                in_critical_section.insert(event.node.clone());
+            }
+            EventKind::CriticalSectionExit => {
+                in_critical_section.remove(&event.node);
+            }
+            EventKind::ResponseReceived(_) => {
+                assert!(in_critical_section.contains(&event.node));
+            }
+            _ => {}
+        }
+    }
+    assert!(report.responses.values().map(Vec::len).sum::<usize>() > 0);
+}
+
+#[tokio::test]
+async fn atomic_batches_roll_back_together() {
+    let harness = Harness {
+        workload: busy_workload(),
+        request_mode: RequestMode::Pipelined {
+            timeout: Duration::from_secs(1),
+        },
+        batching: Batching::AllOrNothing,
+        ..Harness::new(2, 50)
+    };
+    let report = harness.run().await.unwrap();
+
+    // pipelined, so every response of a visit belongs to the batch sent during it
+    let mut batches: HashMap<String, Vec<ServerResponse>> = HashMap::new();
+    for event in &report.events {
+        match &event.kind {
+            EventKind::CriticalSectionEnter => {
+                batches.insert(event.node.clone(), Vec::new());
+            }
+            EventKind::ResponseReceived(response) => {
+                batches.get_mut(&event.node).unwrap().push(response.clone());
+            }
+            EventKind::CriticalSectionExit => {
+                let batch = batches.remove(&event.node).unwrap_or_default();
+                let failed = batch.iter().filter(|response| response.is_err()).count();
+                assert!(failed == 0 || failed == batch.len());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn ring_runs_over_the_memory_transport() {
+    let addresses = ["server", "peer-0", "peer-1", "peer-2"]
+        .map(String::from)
+        .to_vec();
+    let report = Harness::new(3, 10)
+        .run_with(&Memory::new(), addresses)
+        .await
+        .unwrap();
+
+    assert_eq!(report.rotations, 10);
+    assert_eq!(report.peer_addresses, ["peer-0", "peer-1", "peer-2"]);
+}
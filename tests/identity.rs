@@ -0,0 +1,119 @@
+use futures::StreamExt;
+use std::time::Duration;
+use token_ring::{
+    identity::{answer, greet, NodeId},
+    message::Hello,
+    server::Server,
+    transport::{Listener, Memory, Transport},
+};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn node_ids_persist_across_restarts() {
+    let path = std::env::temp_dir().join(format!("node-id-{}", NodeId::generate()));
+
+    let generated = NodeId::load_or_generate(&path).unwrap();
+    let loaded = NodeId::load_or_generate(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(generated, loaded);
+    assert_ne!(generated, NodeId::load_or_generate(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn configured_ids_take_precedence() {
+    let id = NodeId::resolve(Some("alice".to_string()), Some("/nonexistent/id")).unwrap();
+    assert_eq!(id, NodeId::new("alice"));
+}
+
+#[tokio::test]
+async fn handshake_exchanges_ids() {
+    let transport = Memory::new();
+    let mut listener = transport.bind("server").await.unwrap();
+
+    let client = tokio::spawn(async move {
+        let mut lines = transport.connect("server").await.unwrap();
+        greet(
+            &mut lines,
+            &Hello::new(NodeId::new("peer"), "peer".to_string()),
+            false,
+        )
+        .await
+        .unwrap()
+    });
+
+    let (mut lines, _) = listener.accept().await.unwrap();
+    let peer = answer(
+        &mut lines,
+        &Hello::new(NodeId::new("server"), "server".to_string()),
+        false,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(peer.node_id, NodeId::new("peer"));
+    assert_eq!(client.await.unwrap().node_id, NodeId::new("server"));
+}
+
+#[tokio::test]
+async fn handshake_rejects_other_messages() {
+    let transport = Memory::new();
+    let mut listener = transport.bind("server").await.unwrap();
+
+    tokio::spawn(async move {
+        use futures::SinkExt;
+        let mut lines = transport.connect("server").await.unwrap();
+        lines.send("{\"Add\":[1,2]}".to_string()).await.unwrap();
+    });
+
+    let (mut lines, _) = listener.accept().await.unwrap();
+    let hello = Hello::new(NodeId::new("server"), "server".to_string());
+    assert!(answer(&mut lines, &hello, false).await.is_err());
+}
+
+#[tokio::test]
+async fn authenticated_handshakes_require_a_certificate() {
+    let transport = Memory::new();
+    let mut listener = transport.bind("server").await.unwrap();
+
+    tokio::spawn(async move {
+        let mut lines = transport.connect("server").await.unwrap();
+        let hello = Hello::new(NodeId::new("peer"), "peer".to_string());
+        let _ = greet(&mut lines, &hello, false).await;
+    });
+
+    // e.g. a wrapper that lost the certificate of a TLS connection
+    let (mut lines, _) = listener.accept().await.unwrap();
+    let hello = Hello::new(NodeId::new("server"), "server".to_string());
+    assert!(answer(&mut lines, &hello, true).await.is_err());
+}
+
+#[tokio::test]
+async fn servers_turn_away_taken_node_ids() {
+    let transport = Memory::new();
+    let mut server = Server::new("server".to_string(), 2);
+    let listener = server.bind(&transport).await.unwrap();
+    let shutdown = CancellationToken::new();
+    let served = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { server.serve(listener, shutdown).await })
+    };
+
+    let mut first = transport.connect("server").await.unwrap();
+    let hello = Hello::new(NodeId::new("peer-0"), "peer-0".to_string());
+    greet(&mut first, &hello, false).await.unwrap();
+
+    // another peer claiming the same id is disconnected after the handshake
+    let mut second = transport.connect("server").await.unwrap();
+    let hello = Hello::new(NodeId::new("peer-0"), "peer-1".to_string());
+    greet(&mut second, &hello, false).await.unwrap();
+    let line = timeout(Duration::from_secs(10), second.next())
+        .await
+        .unwrap();
+    assert!(line.is_none(), "{line:?}");
+
+    shutdown.cancel();
+    let _ = served.await;
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashSet, fs, net::TcpListener, path::Path, time::Duration};
+use token_ring::{
+    checker::{Checker, Event},
+    identity::NodeId,
+    launcher::{launch, Topology},
+    transport::TransportKind,
+};
+use tokio_util::sync::CancellationToken;
+
+fn directory(name: &str) -> String {
+    let directory = std::env::temp_dir().join(format!("{name}-{}", NodeId::generate()));
+    fs::create_dir_all(&directory).unwrap();
+    directory.display().to_string()
+}
+
+#[test]
+fn peers_are_wired_into_a_ring() {
+    let mut topology = Topology::ring(3);
+    let reservations = topology.allocate().unwrap();
+
+    let addresses = std::iter::once(&topology.server)
+        .chain(&topology.peers)
+        .map(|node| node.address.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(addresses.iter().collect::<HashSet<_>>().len(), 4);
+    // nothing else gets the ports before the nodes are launched
+    for address in &addresses {
+        assert!(TcpListener::bind(address).is_err(), "{address}");
+    }
+    drop(reservations);
+
+    let commands = topology.commands(Path::new("/bin"), None);
+    let node_ids = commands
+        .iter()
+        .map(|command| command.node_id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(node_ids, ["server", "peer-0", "peer-1", "peer-2"]);
+
+    assert_eq!(commands[0].program, Path::new("/bin/server"));
+    assert_eq!(
+        commands[0].args[..2],
+        [addresses[0].clone(), "3".to_string()]
+    );
+    for i in 0..3 {
+        let next = 1 + (i + 1) % 3;
+        assert_eq!(commands[1 + i].program, Path::new("/bin/peer"));
+        assert_eq!(
+            commands[1 + i].args[..3],
+            [
+                addresses[1 + i].clone(),
+                addresses[0].clone(),
+                addresses[next].clone()
+            ]
+        );
+    }
+}
+
+#[test]
+fn topology_files_describe_the_nodes() {
+    let directory = directory("topology");
+    let path = Path::new(&directory).join("ring.toml");
+    fs::write(
+        &path,
+        r#"
+        transport = "unix"
+        config = "shared.toml"
+
+        [server]
+        node_id = "hub"
+
+        [[peers]]
+        node_id = "alpha"
+        config = "alpha.toml"
+        args = ["--max-operations", "2"]
+
+        [[peers]]
+        address = "/tmp/beta.sock"
+        "#,
+    )
+    .unwrap();
+
+    let mut topology = Topology::from_toml_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(topology.transport, TransportKind::Unix);
+    topology.allocate().unwrap();
+    let commands = topology.commands(Path::new("/bin"), Some("/logs"));
+
+    assert_eq!(commands[0].node_id.as_str(), "hub");
+    assert_eq!(commands[2].node_id.as_str(), "peer-1");
+    assert_eq!(commands[2].args[0], "/tmp/beta.sock");
+    assert_eq!(commands[1].args[2], "/tmp/beta.sock");
+
+    let alpha = commands[1].args.join(" ");
+    assert!(alpha.contains("--transport unix"));
+    assert!(alpha.contains("--config alpha.toml"));
+    assert!(alpha.contains("--event-log /logs/alpha.jsonl"));
+    assert!(alpha.ends_with("--max-operations 2"));
+    assert!(commands[2].args.join(" ").contains("--config shared.toml"));
+
+    fs::write(&path, "[[peers]]\nname = \"alpha\"").unwrap();
+    let error = Topology::from_toml_file(path.to_str().unwrap()).unwrap_err();
+    assert_eq!(error.key, "peers[0].name");
+}
+
+#[tokio::test]
+async fn launched_ring_runs_until_shutdown() {
+    let directory = directory("launcher");
+    let server_config = Path::new(&directory).join("server.toml");
+    let peer_config = Path::new(&directory).join("peer.toml");
+    fs::write(&server_config, "").unwrap();
+    fs::write(&peer_config, "retry_delay_ms = 100").unwrap();
+
+    // the server's restart delay of a second per peer doesn't hold back its first start
+    let mut topology = Topology::ring(3);
+    topology.transport = TransportKind::Unix;
+    topology.server.config = Some(server_config.display().to_string());
+    topology.config = Some(peer_config.display().to_string());
+    let reservations = topology.allocate().unwrap();
+    let sockets = std::iter::once(&topology.server)
+        .chain(&topology.peers)
+        .map(|node| node.address.clone().unwrap())
+        .collect::<Vec<_>>();
+    let binaries = Path::new(env!("CARGO_BIN_EXE_peer")).parent().unwrap();
+    let commands = topology.commands(binaries, Some(&directory));
+
+    let shutdown = CancellationToken::new();
+    let launched = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            launch(&commands, reservations, shutdown)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    });
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    shutdown.cancel();
+    launched.await.unwrap().unwrap();
+    for socket in &sockets {
+        assert!(fs::metadata(socket).is_err(), "{socket}");
+    }
+
+    let mut events = Vec::new();
+    for node_id in ["server", "peer-0", "peer-1", "peer-2"] {
+        let path = Path::new(&directory).join(format!("{node_id}.jsonl"));
+        events.extend(Event::from_json_file(path.to_str().unwrap()).unwrap());
+    }
+    let summary = Checker::new(None).check(&events).unwrap();
+    assert!(summary.critical_sections > 0);
+}
@@ -0,0 +1,130 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use token_ring::{
+    harness::RingBuilder,
+    message::{ServerRequest, ServerResponse},
+};
+use tokio::{
+    task::JoinSet,
+    time::{sleep, timeout},
+};
+
+#[tokio::test]
+async fn ring_locks_are_mutually_exclusive() {
+    let ring = RingBuilder::new(3).start().await.unwrap();
+    let locks = ring.locks();
+
+    let locked = Arc::new(AtomicBool::new(false));
+    let critical_sections = Arc::new(AtomicUsize::new(0));
+    let mut applications = JoinSet::new();
+    for (i, lock) in locks.into_iter().enumerate() {
+        let locked = locked.clone();
+        let critical_sections = critical_sections.clone();
+
+        applications.spawn(async move {
+            for j in 0..5 {
+                let guard = lock.lock().await.unwrap();
+                assert!(!locked.swap(true, Ordering::SeqCst));
+
+                let response = guard.submit(ServerRequest::Add(i as i32, j)).await;
+                assert_eq!(
+                    response.unwrap(),
+                    ServerResponse::Add(i as i32, j, i as i32 + j)
+                );
+                sleep(Duration::from_millis(5)).await;
+
+                locked.store(false, Ordering::SeqCst);
+                critical_sections.fetch_add(1, Ordering::SeqCst);
+                guard.release();
+            }
+        });
+    }
+
+    timeout(Duration::from_secs(20), async {
+        while let Some(result) = applications.join_next().await {
+            result.unwrap();
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(critical_sections.load(Ordering::SeqCst), 15);
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn requests_are_submitted_without_locking() {
+    let ring = RingBuilder::new(2).start().await.unwrap();
+    let locks = ring.locks();
+
+    let response = timeout(
+        Duration::from_secs(10),
+        locks[1].submit(ServerRequest::Div(9, 3)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response, ServerResponse::Div(9, 3, 3));
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn abandoned_locks_are_skipped() {
+    let ring = RingBuilder::new(2).start().await.unwrap();
+    let locks = ring.locks();
+
+    // gives up before the peer gets the hot potato
+    assert!(timeout(Duration::ZERO, locks[0].lock()).await.is_err());
+
+    let guard = timeout(Duration::from_secs(10), locks[0].lock())
+        .await
+        .unwrap()
+        .unwrap();
+    drop(guard);
+
+    // the hot potato goes around again once released
+    timeout(Duration::from_secs(10), locks[1].lock())
+        .await
+        .unwrap()
+        .unwrap();
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn locks_held_past_their_deadline_are_reclaimed() {
+    let ring = RingBuilder::new(2).start().await.unwrap();
+    let locks = ring.locks();
+
+    let guard = timeout(
+        Duration::from_secs(10),
+        locks[0]
+            .clone()
+            .with_hold_deadline(Duration::from_millis(100))
+            .lock(),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        guard.submit(ServerRequest::Add(1, 2)).await.unwrap(),
+        ServerResponse::Add(1, 2, 3)
+    );
+
+    // the other peer gets the hot potato while the guard is still alive
+    let other = timeout(Duration::from_secs(10), locks[1].lock())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!guard.is_held());
+    assert!(guard.submit(ServerRequest::Add(1, 2)).await.is_err());
+
+    drop((guard, other));
+    ring.stop().await;
+}
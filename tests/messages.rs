@@ -0,0 +1,441 @@
+use proptest::prelude::*;
+use std::net::{IpAddr, SocketAddr};
+use token_ring::{identity::NodeId, message::*};
+
+fn server_request() -> impl Strategy<Value = ServerRequest> {
+    (0..4, any::<i32>(), any::<i32>()).prop_map(|(op, a, b)| match op {
+        0 => ServerRequest::Add(a, b),
+        1 => ServerRequest::Sub(a, b),
+        2 => ServerRequest::Mul(a, b),
+        _ => ServerRequest::Div(a, b),
+    })
+}
+
+fn server_response() -> impl Strategy<Value = ServerResponse> {
+    (0..5, any::<i32>(), any::<i32>(), any::<i32>(), ".*").prop_map(|(op, a, b, c, e)| match op {
+        0 => ServerResponse::Add(a, b, c),
+        1 => ServerResponse::Sub(a, b, c),
+        2 => ServerResponse::Mul(a, b, c),
+        3 => ServerResponse::Div(a, b, c),
+        _ => ServerResponse::Err(a, b, e),
+    })
+}
+
+fn hot_potato() -> impl Strategy<Value = HotPotato> {
+    (
+        any::<u64>(),
+        any::<u64>(),
+        ".*",
+        "[a-z]{0,8}",
+        prop::collection::vec(".*", 0..3),
+        prop::option::of(".*"),
+        prop::collection::vec(".*", 0..3),
+        prop::collection::vec(".*", 0..3),
+        prop::option::of(".*"),
+        any::<u64>(),
+        prop::option::of("[0-9a-f]{64}"),
+    )
+        .prop_map(
+            |(
+                epoch,
+                sequence,
+                holder,
+                resource,
+                readers,
+                waiting_writer,
+                demand,
+                wanted_by,
+                idle_since,
+                idle_rotations,
+                mac,
+            )| {
+                HotPotato {
+                    epoch,
+                    sequence,
+                    holder: NodeId(holder),
+                    resource,
+                    readers: readers.into_iter().map(NodeId).collect(),
+                    waiting_writer: waiting_writer.map(NodeId),
+                    demand: demand.into_iter().map(NodeId).collect(),
+                    wanted_by: wanted_by.into_iter().map(NodeId).collect(),
+                    idle_since: idle_since.map(NodeId),
+                    idle_rotations,
+                    mac,
+                }
+            },
+        )
+}
+
+fn demand() -> impl Strategy<Value = Demand> {
+    (".*", "[a-z]{0,8}", any::<bool>()).prop_map(|(node_id, resource, high)| Demand {
+        node_id: NodeId(node_id),
+        resource,
+        priority: if high { Priority::High } else { Priority::Low },
+    })
+}
+
+fn control() -> impl Strategy<Value = Control> {
+    prop_oneof![
+        Just(Control::Report),
+        Just(Control::Pause),
+        Just(Control::Resume),
+        any::<u64>().prop_map(Control::NewEpoch),
+        ".*".prop_map(|next_peer_address| Control::Rewire { next_peer_address }),
+        Just(Control::Evict),
+    ]
+}
+
+fn peer_status() -> impl Strategy<Value = PeerStatus> {
+    (
+        ".*",
+        prop::option::of(".*"),
+        prop::option::of(".*"),
+        prop::option::of((any::<u64>(), any::<u64>())),
+        any::<bool>(),
+        any::<usize>(),
+        any::<u64>(),
+        prop::collection::vec("[a-z]{1,8}", 0..3),
+        // quarters survive the trip through JSON exactly
+        (0..10_000u32).prop_map(|quarters| f64::from(quarters) / 4.0),
+    )
+        .prop_map(
+            |(
+                node_id,
+                previous_peer_id,
+                next_peer_id,
+                holding,
+                paused,
+                queue_length,
+                visits,
+                resources,
+                rotation_rate,
+            )| {
+                PeerStatus {
+                    node_id: NodeId(node_id),
+                    previous_peer_id: previous_peer_id.map(NodeId),
+                    next_peer_id: next_peer_id.map(NodeId),
+                    holding,
+                    paused,
+                    queue_length,
+                    visits,
+                    resources,
+                    rotation_rate,
+                }
+            },
+        )
+}
+
+fn hot_potato_state() -> impl Strategy<Value = HotPotatoState> {
+    prop_oneof![
+        Just(HotPotatoState::NotHolding),
+        hot_potato().prop_map(|hot_potato| HotPotatoState::Holding(Box::new(hot_potato))),
+    ]
+}
+
+/// Addresses are sent in their textual form, which has no room for the IPv6 flow label.
+fn socket_address() -> impl Strategy<Value = SocketAddr> {
+    (any::<IpAddr>(), any::<u16>()).prop_map(|(ip, port)| SocketAddr::new(ip, port))
+}
+
+fn find_hot_potato() -> impl Strategy<Value = FindHotPotato> {
+    prop_oneof![
+        Just(FindHotPotato::Request),
+        (hot_potato_state(), socket_address()).prop_map(
+            |(hot_potato_state, previous_peer_address)| FindHotPotato::Response {
+                hot_potato_state: Box::new(hot_potato_state),
+                previous_peer_address,
+            }
+        ),
+    ]
+}
+
+/// Reference model computing the result in a wider type.
+fn expected(request: &ServerRequest) -> Option<i32> {
+    let result = match *request {
+        ServerRequest::Add(a, b) => a as i64 + b as i64,
+        ServerRequest::Sub(a, b) => a as i64 - b as i64,
+        ServerRequest::Mul(a, b) => a as i64 * b as i64,
+        ServerRequest::Div(a, b) if b != 0 => a as i64 / b as i64,
+        ServerRequest::Div(..) => return None,
+    };
+
+    i32::try_from(result).ok()
+}
+
+fn operands(request: &ServerRequest) -> (i32, i32) {
+    match *request {
+        ServerRequest::Add(a, b)
+        | ServerRequest::Sub(a, b)
+        | ServerRequest::Mul(a, b)
+        | ServerRequest::Div(a, b) => (a, b),
+    }
+}
+
+proptest! {
+    #[test]
+    fn start_flag_round_trips(epoch in any::<u64>()) {
+        let start_flag = StartFlag::new(epoch);
+        let decoded = StartFlag::from_json_string(&start_flag.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, start_flag);
+    }
+
+    #[test]
+    fn hello_round_trips(node_id in ".*", address in ".*") {
+        let hello = Hello::new(NodeId(node_id), address);
+        let decoded = Hello::from_json_string(&hello.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, hello);
+    }
+
+    #[test]
+    fn hot_potato_round_trips(hot_potato in hot_potato()) {
+        let decoded = HotPotato::from_json_string(&hot_potato.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, hot_potato);
+    }
+
+    #[test]
+    fn hot_potato_state_round_trips(state in hot_potato_state()) {
+        let decoded = HotPotatoState::from_json_string(&state.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, state);
+    }
+
+    #[test]
+    fn find_hot_potato_round_trips(find in find_hot_potato()) {
+        let decoded = FindHotPotato::from_json_string(&find.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, find);
+    }
+
+    #[test]
+    fn control_round_trips(control in control()) {
+        let decoded = Control::from_json_string(&control.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, control);
+    }
+
+    #[test]
+    fn peer_status_round_trips(status in peer_status()) {
+        let decoded = PeerStatus::from_json_string(&status.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, status);
+    }
+
+    #[test]
+    fn server_request_round_trips(request in server_request()) {
+        let decoded = ServerRequest::from_json_string(&request.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn server_response_round_trips(response in server_response()) {
+        let decoded = ServerResponse::from_json_string(&response.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, response);
+    }
+
+    #[test]
+    fn demand_round_trips(demand in demand()) {
+        let decoded = Demand::from_json_string(&demand.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, demand);
+    }
+
+    #[test]
+    fn resource_request_round_trips(resource in ".*", request in server_request(), shared in any::<bool>(), id in any::<u64>()) {
+        let resource_request = ResourceRequest {
+            access: if shared { Access::Shared } else { Access::Exclusive },
+            id,
+            ..ResourceRequest::new(&resource, request)
+        };
+        let decoded = ResourceRequest::from_json_string(&resource_request.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, resource_request);
+    }
+
+    #[test]
+    fn batch_request_round_trips(requests in prop::collection::vec(server_request(), 0..16), atomic in any::<bool>(), resource in "[a-z]{0,8}", id in any::<u64>()) {
+        let batch_request = BatchRequest {
+            resource,
+            id,
+            ..BatchRequest::new(requests, atomic)
+        };
+        let decoded = BatchRequest::from_json_string(&batch_request.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, batch_request);
+    }
+
+    #[test]
+    fn batch_response_round_trips(responses in prop::collection::vec(server_response(), 0..16)) {
+        let batch_response = BatchResponse(responses);
+        let decoded = BatchResponse::from_json_string(&batch_response.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, batch_response);
+    }
+
+    #[test]
+    fn reply_round_trips(node_id in ".*", id in any::<u64>(), responses in prop::collection::vec(server_response(), 0..16)) {
+        let reply = Reply::new(NodeId(node_id), id, responses);
+        let decoded = Reply::from_json_string(&reply.to_json_string().unwrap()).unwrap();
+        prop_assert_eq!(decoded, reply);
+    }
+
+    /// The peer tells the lines of the server apart by trying each decoder in turn.
+    #[test]
+    fn server_lines_decode_as_a_single_type(hot_potato in hot_potato(), node_id in ".*", id in any::<u64>(), responses in prop::collection::vec(server_response(), 0..4)) {
+        let hot_potato = hot_potato.to_json_string().unwrap();
+        let reply = Reply::new(NodeId(node_id), id, responses).to_json_string().unwrap();
+
+        prop_assert!(Reply::from_json_string(&hot_potato).is_err());
+        prop_assert!(HotPotato::from_json_string(&reply).is_err());
+        prop_assert!(StartFlag::from_json_string(&reply).is_err());
+
+        for line in [&hot_potato, &reply] {
+            prop_assert!(Control::from_json_string(line).is_err());
+        }
+    }
+
+    /// Demands share the ring links with the hot potato.
+    #[test]
+    fn previous_peer_lines_decode_as_a_single_type(hot_potato in hot_potato(), demand in demand()) {
+        let hot_potato = hot_potato.to_json_string().unwrap();
+        let demand = demand.to_json_string().unwrap();
+
+        prop_assert!(Demand::from_json_string(&hot_potato).is_err());
+        prop_assert!(HotPotato::from_json_string(&demand).is_err());
+    }
+
+    /// Control lines share the server connection with the responses.
+    #[test]
+    fn control_lines_decode_as_a_single_type(control in control()) {
+        let control = control.to_json_string().unwrap();
+
+        prop_assert!(HotPotato::from_json_string(&control).is_err());
+        prop_assert!(Reply::from_json_string(&control).is_err());
+    }
+
+    /// The server tells requests and batches apart the same way.
+    #[test]
+    fn peer_lines_decode_as_a_single_type(request in server_request(), requests in prop::collection::vec(server_request(), 0..4), resource in ".*") {
+        let resource_request = ResourceRequest::new(&resource, request.clone()).to_json_string().unwrap();
+        let request = request.to_json_string().unwrap();
+        let batch_request = BatchRequest::new(requests, false).to_json_string().unwrap();
+
+        prop_assert!(BatchRequest::from_json_string(&request).is_err());
+        prop_assert!(ServerRequest::from_json_string(&batch_request).is_err());
+        prop_assert!(ResourceRequest::from_json_string(&request).is_err());
+        prop_assert!(ResourceRequest::from_json_string(&batch_request).is_err());
+        prop_assert!(ServerRequest::from_json_string(&resource_request).is_err());
+        prop_assert!(BatchRequest::from_json_string(&resource_request).is_err());
+        prop_assert!(PeerStatus::from_json_string(&request).is_err());
+        prop_assert!(PeerStatus::from_json_string(&batch_request).is_err());
+    }
+
+    /// Statuses share the server connection with the requests.
+    #[test]
+    fn peer_statuses_decode_as_a_single_type(status in peer_status()) {
+        let status = status.to_json_string().unwrap();
+
+        prop_assert!(ServerRequest::from_json_string(&status).is_err());
+        prop_assert!(BatchRequest::from_json_string(&status).is_err());
+    }
+
+    #[test]
+    fn to_response_matches_the_reference_model(request in server_request()) {
+        let (a, b) = operands(&request);
+        let response = request.to_response();
+
+        match (expected(&request), response) {
+            (Some(result), ServerResponse::Add(x, y, r))
+            | (Some(result), ServerResponse::Sub(x, y, r))
+            | (Some(result), ServerResponse::Mul(x, y, r))
+            | (Some(result), ServerResponse::Div(x, y, r)) => {
+                prop_assert_eq!((x, y, r), (a, b, result));
+            }
+            (None, ServerResponse::Err(x, y, _)) => prop_assert_eq!((x, y), (a, b)),
+            (expected, response) => prop_assert!(false, "expected {:?}, got {:?}", expected, response),
+        }
+    }
+
+    #[test]
+    fn to_response_keeps_the_operation(request in server_request()) {
+        let response = request.to_response();
+
+        let same_operation = matches!(
+            (&request, &response),
+            (_, ServerResponse::Err(..))
+                | (ServerRequest::Add(..), ServerResponse::Add(..))
+                | (ServerRequest::Sub(..), ServerResponse::Sub(..))
+                | (ServerRequest::Mul(..), ServerResponse::Mul(..))
+                | (ServerRequest::Div(..), ServerResponse::Div(..))
+        );
+        prop_assert!(same_operation);
+    }
+
+    #[test]
+    fn batches_answer_every_request_in_order(requests in prop::collection::vec(server_request(), 0..16)) {
+        let BatchResponse(responses) = BatchRequest::new(requests.clone(), false).to_response();
+        let expected = requests.iter().map(ServerRequest::to_response).collect::<Vec<_>>();
+
+        prop_assert_eq!(responses, expected);
+    }
+
+    #[test]
+    fn atomic_batches_fail_as_a_whole(requests in prop::collection::vec(server_request(), 0..16)) {
+        let BatchResponse(responses) = BatchRequest::new(requests.clone(), true).to_response();
+        let any_failure = requests.iter().any(|request| expected(request).is_none());
+
+        prop_assert_eq!(responses.len(), requests.len());
+        prop_assert!(responses.iter().all(|response| response.is_err() == any_failure));
+        for (request, response) in requests.iter().zip(&responses) {
+            prop_assert_eq!(response.operands(), operands(request));
+        }
+    }
+
+    /// The decoders read lines straight from sockets, so they must reject garbage gracefully.
+    #[test]
+    fn decoders_never_panic_on_arbitrary_input(line in ".*") {
+        let _ = StartFlag::from_json_string(&line);
+        let _ = Hello::from_json_string(&line);
+        let _ = HotPotato::from_json_string(&line);
+        let _ = HotPotatoState::from_json_string(&line);
+        let _ = Demand::from_json_string(&line);
+        let _ = FindHotPotato::from_json_string(&line);
+        let _ = ServerRequest::from_json_string(&line);
+        let _ = ServerResponse::from_json_string(&line);
+        let _ = BatchRequest::from_json_string(&line);
+        let _ = BatchResponse::from_json_string(&line);
+        let _ = Reply::from_json_string(&line);
+        let _ = Control::from_json_string(&line);
+        let _ = PeerStatus::from_json_string(&line);
+    }
+
+    #[test]
+    fn decoders_never_panic_on_mangled_messages(request in server_request(), cut in any::<prop::sample::Index>(), byte in any::<u8>()) {
+        let mut line = BatchRequest::new(vec![request], true).to_json_string().unwrap().into_bytes();
+        let i = cut.index(line.len());
+        line[i] = byte;
+        let line = String::from_utf8_lossy(&line);
+
+        let _ = ServerRequest::from_json_string(&line);
+        let _ = BatchRequest::from_json_string(&line);
+        let _ = HotPotato::from_json_string(line.get(..i).unwrap_or_default());
+    }
+}
+
+#[test]
+fn division_edge_cases_are_errors() {
+    assert!(ServerRequest::Div(i32::MIN, -1).to_response().is_err());
+    assert!(ServerRequest::Div(1, 0).to_response().is_err());
+    assert!(ServerRequest::Div(0, 0).to_response().is_err());
+    assert_eq!(
+        ServerRequest::Div(i32::MIN, 1).to_response(),
+        ServerResponse::Div(i32::MIN, 1, i32::MIN)
+    );
+    assert_eq!(
+        ServerRequest::Div(-7, 2).to_response(),
+        ServerResponse::Div(-7, 2, -3)
+    );
+}
+
+#[test]
+fn overflow_edge_cases_are_errors() {
+    assert!(ServerRequest::Add(i32::MAX, 1).to_response().is_err());
+    assert!(ServerRequest::Sub(i32::MIN, 1).to_response().is_err());
+    assert!(ServerRequest::Mul(i32::MIN, -1).to_response().is_err());
+    assert!(ServerRequest::Mul(65536, 65536).to_response().is_err());
+    assert_eq!(
+        ServerRequest::Sub(-1, i32::MAX).to_response(),
+        ServerResponse::Sub(-1, i32::MAX, i32::MIN)
+    );
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use std::time::Duration;
+use token_ring::{
+    checker::Checker,
+    config::Algorithm,
+    harness::RingBuilder,
+    message::{ServerRequest, ServerResponse},
+};
+use tokio::time::{sleep, timeout};
+
+#[tokio::test(start_paused = true)]
+async fn idle_rings_stop_throwing_the_hot_potato() {
+    let mut builder = RingBuilder::new(3);
+    builder.peer.algorithm = Algorithm::OnDemand;
+    builder.peer.workload = common::idle_workload();
+    let mut ring = builder
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+    let locks = ring.locks();
+    let mut events = Vec::new();
+
+    // each submitted request pulls the token to its peer
+    for (i, lock) in locks.iter().enumerate() {
+        let response = timeout(
+            Duration::from_secs(10),
+            lock.submit(ServerRequest::Add(i as i32, 1)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response, ServerResponse::Add(i as i32, 1, i as i32 + 1));
+    }
+
+    sleep(Duration::from_millis(200)).await;
+    ring.drain(&mut events);
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(ring.drain(&mut events), 0);
+
+    let response = timeout(
+        Duration::from_secs(10),
+        locks[1].submit(ServerRequest::Mul(6, 7)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response, ServerResponse::Mul(6, 7, 42));
+
+    events.extend(ring.stop().await);
+    Checker::default().check(&events).unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn resting_tokens_are_pulled_by_locks() {
+    let mut builder = RingBuilder::new(3);
+    builder.server.resources = vec!["a".to_string()];
+    builder.peer.algorithm = Algorithm::OnDemand;
+    builder.peer.workload = common::idle_workload();
+    let mut ring = builder
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+    let (locks, a) = (ring.locks(), ring.resource_locks("a"));
+    let mut events = Vec::new();
+
+    for lock in locks.iter().chain(&a) {
+        let guard = timeout(Duration::from_secs(10), lock.lock())
+            .await
+            .unwrap()
+            .unwrap();
+        guard.submit(ServerRequest::Sub(3, 1)).await.unwrap();
+    }
+
+    sleep(Duration::from_millis(200)).await;
+    ring.drain(&mut events);
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(ring.drain(&mut events), 0);
+
+    timeout(Duration::from_secs(10), a[2].lock())
+        .await
+        .unwrap()
+        .unwrap();
+
+    events.extend(ring.stop().await);
+    Checker::default().check(&events).unwrap();
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::time::Duration;
+use token_ring::{
+    checker::Checker,
+    harness::RingBuilder,
+    message::{ServerRequest, ServerResponse},
+    policy::PacingPolicy,
+};
+use tokio::time::{sleep, timeout, Instant};
+
+#[test]
+fn hop_delays_double_with_idle_rotations() {
+    let ms = Duration::from_millis;
+
+    let pacing = PacingPolicy::new(ms(10), ms(80));
+    let delays = (0..5).map(|rotations| pacing.hop_delay(rotations));
+    assert_eq!(
+        delays.collect::<Vec<_>>(),
+        [ms(10), ms(20), ms(40), ms(80), ms(80)]
+    );
+    assert_eq!(pacing.hop_delay(u64::MAX), ms(80));
+
+    // without a backoff bound every hop waits the minimum
+    let pacing = PacingPolicy::new(ms(10), Duration::ZERO);
+    assert_eq!(pacing.hop_delay(7), ms(10));
+
+    let pacing = PacingPolicy::default();
+    assert!(!pacing.is_enabled());
+    assert_eq!(pacing.hop_delay(7), Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_rings_slow_the_hot_potato_down() {
+    let pacing = PacingPolicy::new(Duration::from_millis(1), Duration::from_millis(100));
+    let mut builder = RingBuilder::new(3);
+    builder.peer.pacing_policy = pacing;
+    builder.peer.workload = common::idle_workload();
+    let mut ring = builder
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+    let locks = ring.locks();
+    let mut events = Vec::new();
+
+    timeout(
+        Duration::from_secs(10),
+        locks[0].submit(ServerRequest::Add(1, 1)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    // once backed off, each hop waits the maximum delay
+    sleep(Duration::from_secs(1)).await;
+    ring.drain(&mut events);
+    sleep(Duration::from_secs(1)).await;
+    let throws = ring.drain(&mut events);
+    // a hop waits 100 ms and takes another one
+    assert!((9..=10).contains(&throws), "{throws} throws in a second");
+
+    events.extend(ring.stop().await);
+    Checker::default().check(&events).unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn pending_requests_pull_a_backed_off_hot_potato() {
+    let pacing = PacingPolicy::new(Duration::from_millis(50), Duration::from_secs(5));
+    let mut builder = RingBuilder::new(3);
+    builder.peer.pacing_policy = pacing;
+    builder.peer.workload = common::idle_workload();
+    let ring = builder
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+    let locks = ring.locks();
+    let mut events = Vec::new();
+
+    timeout(
+        Duration::from_secs(10),
+        locks[0].submit(ServerRequest::Add(1, 1)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    sleep(Duration::from_secs(2)).await;
+
+    // the hot potato would wait most of a second per hop, the demand brings it right away
+    for (i, lock) in locks.iter().enumerate() {
+        let submitted = Instant::now();
+        let response = timeout(
+            Duration::from_secs(10),
+            lock.submit(ServerRequest::Mul(i as i32, 7)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response, ServerResponse::Mul(i as i32, 7, i as i32 * 7));
+        assert!(submitted.elapsed() < Duration::from_millis(20));
+    }
+
+    events.extend(ring.stop().await);
+    Checker::default().check(&events).unwrap();
+}
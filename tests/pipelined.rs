@@ -0,0 +1,193 @@
+mod common;
+
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+use token_ring::{
+    checker::{EventKind, EventRecorder, EventRx},
+    harness::RingBuilder,
+    identity::{answer, NodeId},
+    lock::RingLock,
+    message::{Hello, HotPotato, Reply, ResourceRequest, ServerRequest, ServerResponse, StartFlag},
+    peer::{Peer, RequestMode},
+    policy::HoldPolicy,
+    transport::{Connection, Listener, Memory, Transport},
+    workload::Workload,
+};
+use tokio::{
+    sync::mpsc,
+    time::{sleep, timeout, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+/// Starts a peer whose server is played by the test, once it holds the hot potato.
+async fn start_peer(
+    request_mode: RequestMode,
+    hold_policy: HoldPolicy,
+    shutdown: &CancellationToken,
+) -> (Connection, RingLock, EventRx) {
+    let transport = Memory::new();
+    let mut listener = transport.bind("server").await.unwrap();
+
+    let mut peer = Peer::new(
+        "peer-0".to_string(),
+        "server".to_string(),
+        "peer-0".to_string(),
+    );
+    peer.node_id = NodeId::new("peer-0");
+    peer.request_mode = request_mode;
+    peer.hold_policy = hold_policy;
+    peer.workload = common::idle_workload();
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    peer.recorder = EventRecorder::to_channel("peer-0", event_tx);
+    let lock = peer.ring_lock();
+    let peer_listener = peer.bind(&transport).await.unwrap();
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { peer.serve(&transport, peer_listener, shutdown).await });
+    }
+
+    let (mut server, _) = listener.accept().await.unwrap();
+    let hello = Hello::new(NodeId::new("server"), "server".to_string());
+    answer(&mut server, &hello, false).await.unwrap();
+    server
+        .send(StartFlag::new(1).to_json_string().unwrap())
+        .await
+        .unwrap();
+    let hot_potato = HotPotato {
+        epoch: 1,
+        holder: NodeId::new("peer-0"),
+        ..HotPotato::new()
+    };
+    server
+        .send(hot_potato.to_json_string().unwrap())
+        .await
+        .unwrap();
+
+    (server, lock, event_rx)
+}
+
+/// Waits for the next event of `kind`, returns when it was recorded.
+async fn next(event_rx: &mut EventRx, kind: EventKind) -> Instant {
+    loop {
+        let event = timeout(Duration::from_secs(10), event_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if event.kind == kind {
+            return Instant::now();
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn silent_servers_time_the_wait_out() {
+    let shutdown = CancellationToken::new();
+    let request_mode = RequestMode::Pipelined {
+        timeout: Duration::from_millis(250),
+    };
+    let (server, _, mut event_rx) =
+        start_peer(request_mode, HoldPolicy::default(), &shutdown).await;
+
+    // the peer sends its first request and keeps the hot potato until the timeout
+    let entered = next(&mut event_rx, EventKind::CriticalSectionEnter).await;
+    let exited = next(&mut event_rx, EventKind::CriticalSectionExit).await;
+    assert_eq!(exited - entered, Duration::from_millis(250));
+
+    shutdown.cancel();
+    drop(server);
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_wait_fits_in_the_hold_time() {
+    let shutdown = CancellationToken::new();
+    let request_mode = RequestMode::Pipelined {
+        timeout: Duration::from_millis(250),
+    };
+    let hold_policy = HoldPolicy::new(None, Some(Duration::from_millis(100)), Default::default());
+    let (server, _, mut event_rx) = start_peer(request_mode, hold_policy, &shutdown).await;
+
+    let entered = next(&mut event_rx, EventKind::CriticalSectionEnter).await;
+    let exited = next(&mut event_rx, EventKind::CriticalSectionExit).await;
+    assert_eq!(exited - entered, Duration::from_millis(100));
+
+    shutdown.cancel();
+    drop(server);
+}
+
+#[tokio::test(start_paused = true)]
+async fn replies_are_matched_by_request_id() {
+    let shutdown = CancellationToken::new();
+    let (mut server, lock, _) =
+        start_peer(RequestMode::default(), HoldPolicy::default(), &shutdown).await;
+    let submitted = tokio::spawn(async move { lock.submit(ServerRequest::Add(2, 3)).await });
+
+    // every request is answered after a reply to another id, which goes nowhere
+    let answering = tokio::spawn(async move {
+        while let Some(Ok(line)) = server.next().await {
+            let Ok(request) = ResourceRequest::from_json_string(&line) else {
+                continue;
+            };
+            let wrong = ServerResponse::Err(0, 0, "Not yours.".to_string());
+            for reply in [
+                Reply::new(NodeId::new("peer-0"), request.id + 1_000, vec![wrong]),
+                Reply::new(
+                    NodeId::new("peer-0"),
+                    request.id,
+                    vec![request.request.to_response()],
+                ),
+            ] {
+                server.send(reply.to_json_string().unwrap()).await.unwrap();
+            }
+        }
+    });
+
+    let response = timeout(Duration::from_secs(10), submitted)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(response, ServerResponse::Add(2, 3, 5));
+
+    shutdown.cancel();
+    answering.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn visits_last_until_their_own_responses_came() {
+    let mut builder = RingBuilder::new(3);
+    builder.peer.request_mode = RequestMode::Pipelined {
+        timeout: Duration::from_secs(10),
+    };
+    builder.peer.workload = Workload {
+        rate: 200.,
+        ..Default::default()
+    };
+    let mut ring = builder
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+
+    sleep(Duration::from_secs(1)).await;
+    let mut events = Vec::new();
+    ring.drain(&mut events);
+    events.extend(ring.stop().await);
+
+    // each visit receives as many responses as it sent requests before throwing
+    let mut visits = 0;
+    for node in ["peer-0", "peer-1", "peer-2"] {
+        let (mut sent, mut received) = (0, 0);
+        for event in events.iter().filter(|event| event.node == node) {
+            match &event.kind {
+                EventKind::CriticalSectionEnter => (sent, received) = (0, 0),
+                EventKind::RequestsSent(operations) => sent = *operations,
+                EventKind::ResponseReceived(_) => received += 1,
+                EventKind::CriticalSectionExit => {
+                    assert_eq!(received, sent, "{node}");
+                    visits += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    assert!(visits > 3);
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashMap, time::Duration};
+use token_ring::{
+    identity::NodeId,
+    policy::{parse_weight, HoldPolicy},
+};
+
+fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+#[test]
+fn unbounded_policies_allow_everything() {
+    let policy = HoldPolicy::default();
+
+    assert_eq!(policy.quota_for(&NodeId::new("peer-1")), None);
+    assert!(policy.allows(&NodeId::new("peer-1"), usize::MAX, Duration::MAX));
+}
+
+#[test]
+fn weights_multiply_the_operation_quota() {
+    let (light, heavy) = (NodeId::new("peer-1"), NodeId::new("peer-2"));
+    let policy = HoldPolicy::new(Some(2), None, HashMap::from([(heavy.clone(), 3)]));
+
+    assert_eq!(policy.quota_for(&light), Some(2));
+    assert_eq!(policy.quota_for(&heavy), Some(6));
+    assert!(policy.allows(&light, 1, Duration::MAX));
+    assert!(!policy.allows(&light, 2, Duration::ZERO));
+    assert!(policy.allows(&heavy, 5, Duration::MAX));
+    assert!(!policy.allows(&heavy, 6, Duration::ZERO));
+
+    let policy = HoldPolicy::new(Some(usize::MAX), None, HashMap::from([(heavy.clone(), 2)]));
+    assert_eq!(policy.quota_for(&heavy), Some(usize::MAX));
+}
+
+#[test]
+fn hold_times_end_the_visit() {
+    let peer = NodeId::new("peer-1");
+    let policy = HoldPolicy::new(None, Some(ms(10)), HashMap::new());
+
+    assert!(policy.allows(&peer, usize::MAX, ms(9)));
+    assert!(!policy.allows(&peer, 0, ms(10)));
+
+    let policy = HoldPolicy::new(Some(4), Some(ms(10)), HashMap::new());
+    assert!(!policy.allows(&peer, 4, ms(0)));
+    assert!(!policy.allows(&peer, 0, ms(10)));
+}
+
+#[test]
+fn weights_are_parsed_from_flags() {
+    assert_eq!(parse_weight("peer-2=3"), Ok((NodeId::new("peer-2"), 3)));
+    assert_eq!(parse_weight("a=b=2"), Ok((NodeId::new("a=b"), 2)));
+
+    assert!(parse_weight("peer-2").is_err());
+    assert!(parse_weight("peer-2=-1").is_err());
+    assert!(parse_weight("peer-2=0").is_err());
+}
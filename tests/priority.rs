@@ -0,0 +1,160 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use token_ring::{
+    harness::RingBuilder,
+    message::{Priority, ServerRequest, ServerResponse},
+    policy::PriorityPolicy,
+};
+use tokio::{
+    task::JoinSet,
+    time::{sleep, timeout},
+};
+
+#[tokio::test(start_paused = true)]
+async fn high_priority_locks_go_first_at_a_peer() {
+    let ring = RingBuilder::new(2)
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+    let locks = ring.locks();
+
+    let held = timeout(Duration::from_secs(10), locks[0].lock())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let low = {
+        let lock = locks[1].clone();
+        tokio::spawn(async move { lock.lock().await.map_err(|e| e.to_string()) })
+    };
+    sleep(Duration::from_millis(100)).await;
+    let high = {
+        let lock = locks[1].clone().with_priority(Priority::High);
+        tokio::spawn(async move { lock.lock().await.map_err(|e| e.to_string()) })
+    };
+    sleep(Duration::from_millis(100)).await;
+
+    // the high-priority lock is queued ahead of the one taken before it
+    drop(held);
+    let high = timeout(Duration::from_secs(10), high)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        high.submit(ServerRequest::Add(1, 2)).await.unwrap(),
+        ServerResponse::Add(1, 2, 3)
+    );
+    assert!(!low.is_finished());
+
+    drop(high);
+    timeout(Duration::from_secs(10), low)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    ring.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn demanded_tokens_skip_low_priority_work() {
+    let ring = RingBuilder::new(3)
+        .start_in_memory(Duration::from_millis(1))
+        .await
+        .unwrap();
+    let locks = ring.locks();
+
+    let held = timeout(Duration::from_secs(10), locks[0].lock())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // the demand of the last peer reaches the first one while it holds the token
+    let low = {
+        let lock = locks[1].clone();
+        tokio::spawn(async move { lock.lock().await.map_err(|e| e.to_string()) })
+    };
+    let high = {
+        let lock = locks[2].clone().with_priority(Priority::High);
+        tokio::spawn(async move { lock.lock().await.map_err(|e| e.to_string()) })
+    };
+    sleep(Duration::from_millis(300)).await;
+
+    drop(held);
+    let high = timeout(Duration::from_secs(10), high)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(!low.is_finished());
+
+    drop(high);
+    timeout(Duration::from_secs(10), low)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    ring.stop().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn low_priority_work_is_deferred_a_bounded_number_of_visits() {
+    for max_deferrals in [1, 3] {
+        let mut builder = RingBuilder::new(2);
+        builder.peer.priority_policy = PriorityPolicy::new(max_deferrals);
+        let ring = builder
+            .start_in_memory(Duration::from_millis(1))
+            .await
+            .unwrap();
+        let locks = ring.locks();
+
+        let held = timeout(Duration::from_secs(10), locks[1].lock())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the high-priority locks outnumber the deferrals, so the demand never runs out
+        let granted = Arc::new(AtomicUsize::new(0));
+        let mut high = JoinSet::new();
+        for _ in 0..=max_deferrals {
+            let lock = locks[1].clone().with_priority(Priority::High);
+            let granted = granted.clone();
+            high.spawn(async move {
+                let guard = lock.lock().await.unwrap();
+                granted.fetch_add(1, Ordering::SeqCst);
+                drop(guard);
+            });
+        }
+        let low = {
+            let lock = locks[0].clone();
+            let granted = granted.clone();
+            tokio::spawn(async move {
+                let guard = lock.lock().await.unwrap();
+                (granted.load(Ordering::SeqCst), guard)
+            })
+        };
+        sleep(Duration::from_millis(300)).await;
+
+        // each deferral lets a single high-priority lock through
+        drop(held);
+        let (high_first, low) = timeout(Duration::from_secs(10), low)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(high_first, max_deferrals);
+
+        drop(low);
+        timeout(Duration::from_secs(10), high.join_all())
+            .await
+            .unwrap();
+
+        ring.stop().await;
+    }
+}
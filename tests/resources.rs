@@ -0,0 +1,86 @@
+use std::time::Duration;
+use token_ring::{
+    checker::{Checker, Event, EventKind, Invariant},
+    harness::RingBuilder,
+    message::{ServerRequest, ServerResponse},
+};
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn resources_are_locked_independently() {
+    let mut builder = RingBuilder::new(3);
+    builder.server.resources = vec!["a".to_string(), "b".to_string()];
+    let ring = builder.start().await.unwrap();
+    let (default, a, b) = (
+        ring.locks(),
+        ring.resource_locks("a"),
+        ring.resource_locks("b"),
+    );
+
+    let a_guard = timeout(Duration::from_secs(10), a[0].lock())
+        .await
+        .unwrap()
+        .unwrap();
+    let response = a_guard.submit(ServerRequest::Add(1, 2)).await.unwrap();
+    assert_eq!(response, ServerResponse::Add(1, 2, 3));
+
+    // another peer gets "b" and the default token while the first one holds "a"
+    let b_guard = timeout(Duration::from_secs(10), b[1].lock())
+        .await
+        .unwrap()
+        .unwrap();
+    let default_guard = timeout(Duration::from_secs(10), default[2].lock())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(timeout(Duration::from_millis(200), a[1].lock())
+        .await
+        .is_err());
+
+    drop((b_guard, default_guard));
+    a_guard.release();
+    timeout(Duration::from_secs(10), a[1].lock())
+        .await
+        .unwrap()
+        .unwrap();
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn requests_are_executed_for_their_resource() {
+    let mut builder = RingBuilder::new(2);
+    builder.server.resources = vec!["a".to_string()];
+    let ring = builder.start().await.unwrap();
+
+    let response = timeout(
+        Duration::from_secs(10),
+        ring.resource_locks("a")[1].submit(ServerRequest::Mul(6, 7)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response, ServerResponse::Mul(6, 7, 42));
+
+    ring.stop().await;
+}
+
+#[test]
+fn mutual_exclusion_is_checked_per_resource() {
+    let events = [
+        Event::new(0, "peer-0", EventKind::token_received("a", 0, 1)),
+        Event::new(0, "peer-1", EventKind::token_received("b", 0, 1)),
+        Event::new(0, "peer-0", EventKind::critical_section_enter("a")),
+        Event::new(0, "peer-1", EventKind::critical_section_enter("b")),
+        Event::new(0, "peer-0", EventKind::critical_section_exit("a")),
+        Event::new(0, "peer-1", EventKind::critical_section_exit("b")),
+    ];
+    Checker::default().check(&events).unwrap();
+
+    let events = [
+        Event::new(0, "peer-0", EventKind::critical_section_enter("a")),
+        Event::new(0, "peer-1", EventKind::critical_section_enter("a")),
+    ];
+    let violation = Checker::default().check(&events).unwrap_err();
+    assert_eq!(violation.invariant, Invariant::MutualExclusion);
+}
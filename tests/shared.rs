@@ -0,0 +1,153 @@
+use std::time::Duration;
+use token_ring::{
+    admin::{request, AdminRequest, AdminResponse},
+    checker::{Checker, Event, EventKind, Invariant},
+    harness::RingBuilder,
+    message::{Operation, ServerRequest, ServerResponse},
+    server::Server,
+    transport::Tcp,
+};
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn readers_share_the_ring_while_writers_wait() {
+    let ring = RingBuilder::new(3).start().await.unwrap();
+    let locks = ring.locks();
+
+    // the peers' first requests are writes, no lease is taken while they wait
+    for lock in &locks {
+        timeout(
+            Duration::from_secs(10),
+            lock.submit(ServerRequest::Sub(1, 1)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    }
+
+    // both readers hold their lease at once
+    let first = timeout(Duration::from_secs(10), locks[0].lock_shared())
+        .await
+        .unwrap()
+        .unwrap();
+    let second = timeout(Duration::from_secs(10), locks[1].lock_shared())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        first.submit(ServerRequest::Add(1, 2)).await.unwrap(),
+        ServerResponse::Add(1, 2, 3)
+    );
+    assert_eq!(
+        second.submit(ServerRequest::Mul(6, 7)).await.unwrap(),
+        ServerResponse::Mul(6, 7, 42)
+    );
+
+    assert!(timeout(Duration::from_millis(300), locks[2].lock())
+        .await
+        .is_err());
+
+    drop((first, second));
+    timeout(Duration::from_secs(10), locks[2].lock())
+        .await
+        .unwrap()
+        .unwrap();
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn readers_only_get_the_shared_operations() {
+    let mut builder = RingBuilder::new(2);
+    builder.server.shared_operations = [Operation::Add].into();
+    builder.admin_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    let locks = ring.locks();
+
+    let response = timeout(
+        Duration::from_secs(10),
+        locks[0].submit_shared(ServerRequest::Add(2, 3)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response, ServerResponse::Add(2, 3, 5));
+
+    let response = timeout(
+        Duration::from_secs(10),
+        locks[1].submit_shared(ServerRequest::Div(9, 3)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(matches!(response, ServerResponse::Err(9, 3, _)));
+
+    // writers may request any operation
+    let response = timeout(
+        Duration::from_secs(10),
+        locks[1].submit(ServerRequest::Div(9, 3)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response, ServerResponse::Div(9, 3, 3));
+
+    // the rejected request isn't counted as shared work
+    let admin_address = ring.admin_address.clone().unwrap();
+    match request(&Tcp, &admin_address, &AdminRequest::Stats)
+        .await
+        .unwrap()
+    {
+        AdminResponse::Stats { stats, .. } => assert_eq!(stats.shared_requests, 1),
+        response => panic!("Unexpected response {response:?}"),
+    }
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn readers_get_the_read_only_operations_by_default() {
+    let default = Server::new(String::new(), 1).shared_operations;
+    assert_eq!(default, Operation::READ_ONLY.into());
+    let ring = RingBuilder::new(2).start().await.unwrap();
+    let locks = ring.locks();
+
+    let response = timeout(
+        Duration::from_secs(10),
+        locks[0].submit_shared(ServerRequest::Add(2, 3)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response, ServerResponse::Add(2, 3, 5));
+
+    ring.stop().await;
+}
+
+#[test]
+fn readers_may_only_overlap_each_other() {
+    let events = [
+        Event::new(0, "peer-0", EventKind::SharedSectionEnter(String::new())),
+        Event::new(0, "peer-1", EventKind::SharedSectionEnter(String::new())),
+        Event::new(0, "peer-0", EventKind::SharedSectionExit(String::new())),
+        Event::new(0, "peer-1", EventKind::SharedSectionExit(String::new())),
+        Event::new(0, "peer-2", EventKind::CriticalSectionEnter),
+        Event::new(0, "peer-2", EventKind::CriticalSectionExit),
+    ];
+    let summary = Checker::default().check(&events).unwrap();
+    assert_eq!(summary.shared_sections, 2);
+
+    let events = [
+        Event::new(0, "peer-0", EventKind::SharedSectionEnter("a".to_string())),
+        Event::new(0, "peer-1", EventKind::critical_section_enter("a")),
+    ];
+    let violation = Checker::default().check(&events).unwrap_err();
+    assert_eq!(violation.invariant, Invariant::MutualExclusion);
+
+    let events = [
+        Event::new(0, "peer-1", EventKind::CriticalSectionEnter),
+        Event::new(0, "peer-0", EventKind::SharedSectionEnter(String::new())),
+    ];
+    let violation = Checker::default().check(&events).unwrap_err();
+    assert_eq!(violation.invariant, Invariant::MutualExclusion);
+}
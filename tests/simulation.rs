@@ -0,0 +1,73 @@
+use std::time::Duration;
+use token_ring::{
+    checker::{Checker, EventKind},
+    simulation::{Simulation, SimulationConfig},
+    workload::Workload,
+};
+
+fn config(seed: u64) -> SimulationConfig {
+    SimulationConfig {
+        number_of_peers: 4,
+        seed,
+        duration: Duration::from_secs(2),
+        workload: Workload {
+            rate: 50.,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn trace(seed: u64) -> String {
+    Simulation::new(config(seed))
+        .unwrap()
+        .run()
+        .unwrap()
+        .to_json_string()
+        .unwrap()
+}
+
+#[test]
+fn same_seed_same_trace() {
+    assert_eq!(trace(7), trace(7));
+    assert_ne!(trace(7), trace(8));
+}
+
+#[test]
+fn simulated_rings_keep_the_invariants() {
+    let report = Simulation::new(config(1)).unwrap().run().unwrap();
+
+    assert!(report.rotations > 0);
+    assert!(report.responses(0).count() > 0);
+    // the latencies are the only time that passes
+    let first_throw = report
+        .events
+        .iter()
+        .find(|event| matches!(event.kind, EventKind::TokenSent { .. }))
+        .unwrap();
+    assert!(first_throw.at >= 1_000);
+    assert!(report
+        .events
+        .iter()
+        .all(|event| event.at <= Duration::from_secs(2).as_micros() as u64));
+
+    Checker::default().check(&report.events).unwrap();
+}
+
+#[test]
+fn unusable_configs_are_rejected() {
+    let config = SimulationConfig {
+        number_of_peers: 0,
+        ..Default::default()
+    };
+    assert!(Simulation::new(config).is_err());
+
+    let config = SimulationConfig {
+        workload: Workload {
+            rate: 0.,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(Simulation::new(config).is_err());
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::Remote;
+use std::time::Duration;
+use token_ring::{
+    harness::RingBuilder,
+    message::{ServerRequest, ServerResponse, Submission},
+    peer::Batching,
+    submission::{self, parse_operations, submit},
+    transport::{Memory, Tcp, Transport},
+};
+use tokio::{sync::mpsc, task::JoinSet, time::timeout};
+
+#[test]
+fn operations_are_parsed() {
+    assert_eq!("1 + 2".parse(), Ok(ServerRequest::Add(1, 2)));
+    assert_eq!("-3--4".parse(), Ok(ServerRequest::Sub(-3, -4)));
+    assert_eq!("6*7".parse(), Ok(ServerRequest::Mul(6, 7)));
+    assert_eq!(" 9 / -3 ".parse(), Ok(ServerRequest::Div(9, -3)));
+
+    for invalid in ["", "1 ^ 2", "a + 1", "1 +", "99999999999 * 2"] {
+        assert!(invalid.parse::<ServerRequest>().is_err(), "{invalid}");
+    }
+
+    assert_eq!(
+        parse_operations("# demo\n1 + 1\n\n  # twice\n2 * 2\n"),
+        Ok(vec![ServerRequest::Add(1, 1), ServerRequest::Mul(2, 2)])
+    );
+    let error = parse_operations("1 + 1\n\n1 % 2").unwrap_err();
+    assert!(error.starts_with("Line 3:"), "{error}");
+}
+
+#[tokio::test]
+async fn submitted_requests_are_answered_in_order() {
+    for batching in [Batching::Disabled, Batching::PartialFailure] {
+        let mut builder = RingBuilder::new(2);
+        builder.peer.batching = batching;
+        builder.submission_address = Some("127.0.0.1:0".to_string());
+        let ring = builder.start().await.unwrap();
+        let address = ring.submission_address.clone().unwrap();
+
+        let responses = timeout(
+            Duration::from_secs(10),
+            submit(
+                &Tcp,
+                &address,
+                &Submission::new(
+                    vec![
+                        ServerRequest::Add(1, 2),
+                        ServerRequest::Div(1, 0),
+                        ServerRequest::Mul(6, 7),
+                    ],
+                    "",
+                ),
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0], ServerResponse::Add(1, 2, 3));
+        assert!(matches!(responses[1], ServerResponse::Err(1, 0, _)));
+        assert_eq!(responses[2], ServerResponse::Mul(6, 7, 42));
+
+        ring.stop().await;
+    }
+}
+
+#[tokio::test]
+async fn concurrent_clients_get_their_own_responses() {
+    let mut builder = RingBuilder::new(3);
+    builder.submission_address = Some("127.0.0.1:0".to_string());
+    let ring = builder.start().await.unwrap();
+    let address = ring.submission_address.clone().unwrap();
+
+    let mut clients = JoinSet::new();
+    for i in 0..5 {
+        let address = address.clone();
+        clients.spawn(async move {
+            let submission = Submission::new(
+                vec![ServerRequest::Add(i, 100), ServerRequest::Sub(i, 100)],
+                "",
+            );
+            (i, submit(&Tcp, &address, &submission).await.unwrap())
+        });
+    }
+
+    while let Some(result) = timeout(Duration::from_secs(10), clients.join_next())
+        .await
+        .unwrap()
+    {
+        let (i, responses) = result.unwrap();
+        assert_eq!(
+            responses,
+            [
+                ServerResponse::Add(i, 100, i + 100),
+                ServerResponse::Sub(i, 100, i - 100)
+            ]
+        );
+    }
+
+    ring.stop().await;
+}
+
+#[tokio::test]
+async fn remote_clients_are_refused_without_tls() {
+    let transport = Memory::new();
+    let listener = transport.bind("submissions").await.unwrap();
+    let (submission_tx, mut submission_rx) = mpsc::unbounded_channel();
+    tokio::spawn(submission::serve(Remote(listener), submission_tx));
+
+    let submission = Submission::new(vec![ServerRequest::Add(1, 2)], "");
+    let result = timeout(
+        Duration::from_secs(10),
+        submit(&transport, "submissions", &submission),
+    )
+    .await
+    .unwrap();
+
+    assert!(result.is_err());
+    assert!(submission_rx.try_recv().is_err());
+}
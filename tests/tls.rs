@@ -0,0 +1,166 @@
+use futures::SinkExt;
+use std::{os::unix::fs::PermissionsExt, time::Duration};
+use tempfile::TempDir;
+use token_ring::{
+    fault::{FaultConfig, Faulty},
+    harness::Harness,
+    identity::{answer, greet, NodeId},
+    message::{Hello, HotPotato},
+    tls::{generate_certificates, Tls, TlsConfig},
+    transport::{Listener, Tcp, Transport},
+};
+use tokio::time::timeout;
+
+/// Certificates for `node_ids`, deleted with the returned directory.
+fn certificates(name: &str, node_ids: &[&str]) -> TempDir {
+    let directory = tempfile::Builder::new().prefix(name).tempdir().unwrap();
+    let node_ids = node_ids
+        .iter()
+        .map(|id| NodeId::new(id))
+        .collect::<Vec<_>>();
+    generate_certificates(directory.path().to_str().unwrap(), &node_ids).unwrap();
+
+    directory
+}
+
+fn tls(directory: &TempDir, node_id: &str) -> Tls<Tcp> {
+    let directory = directory.path().to_str().unwrap();
+    let config = TlsConfig::in_directory(directory, &NodeId::new(node_id));
+    Tls::new(Tcp, &config).unwrap()
+}
+
+#[test]
+fn private_keys_are_only_readable_by_their_owner() {
+    let directory = certificates("keys", &["peer-0"]);
+
+    for key in ["ca.key", "peer-0.key"] {
+        let mode = std::fs::metadata(directory.path().join(key))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600, "{key}");
+    }
+}
+
+#[tokio::test]
+async fn ring_runs_over_mutual_tls() {
+    let ids = ["server", "peer-0", "peer-1", "peer-2"];
+    let directory = certificates("ring", &ids);
+
+    let transports = ids.iter().map(|id| tls(&directory, id)).collect();
+    let report = Harness::new(3, 10)
+        .run_with_transports(transports, vec!["127.0.0.1:0".to_string(); 4])
+        .await
+        .unwrap();
+
+    assert_eq!(report.rotations, 10);
+}
+
+#[tokio::test]
+async fn certificates_reach_the_handshake_through_faulty_links() {
+    let ids = ["server", "peer-0", "peer-1"];
+    let directory = certificates("faulty", &ids);
+
+    let transports = ids
+        .iter()
+        .map(|id| Faulty::new(tls(&directory, id), FaultConfig::default(), 7))
+        .collect();
+    let report = Harness::new(2, 5)
+        .run_with_transports(transports, vec!["127.0.0.1:0".to_string(); 3])
+        .await
+        .unwrap();
+
+    assert_eq!(report.rotations, 5);
+}
+
+#[tokio::test]
+async fn plaintext_clients_cannot_inject_a_hot_potato() {
+    let directory = certificates("plaintext", &["peer-0", "peer-1"]);
+    let mut listener = tls(&directory, "peer-0").bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_address().unwrap();
+
+    let mut intruder = Tcp.connect(&address).await.unwrap();
+    intruder
+        .send(HotPotato::new().to_json_string().unwrap())
+        .await
+        .unwrap();
+
+    // the plaintext connection is rejected and the listener keeps accepting
+    let client = {
+        let transport = tls(&directory, "peer-1");
+        let address = address.clone();
+        tokio::spawn(async move { transport.connect(&address).await })
+    };
+    let (mut lines, _) = timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut client_lines = client.await.unwrap().unwrap();
+
+    let server_hello = Hello::new(NodeId::new("peer-0"), address);
+    let client_hello = Hello::new(NodeId::new("peer-1"), String::new());
+    let (remote, _) = tokio::join!(
+        answer(&mut lines, &server_hello, true),
+        greet(&mut client_lines, &client_hello, true),
+    );
+    assert_eq!(remote.unwrap().node_id, NodeId::new("peer-1"));
+}
+
+#[tokio::test]
+async fn silent_clients_dont_hold_up_other_handshakes() {
+    let directory = certificates("silent", &["peer-0", "peer-1"]);
+    let mut listener = tls(&directory, "peer-0").bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_address().unwrap();
+
+    // never starts its handshake
+    let _silent = Tcp.connect(&address).await.unwrap();
+
+    let client = {
+        let transport = tls(&directory, "peer-1");
+        let address = address.clone();
+        tokio::spawn(async move { transport.connect(&address).await })
+    };
+    timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    client.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn certificates_of_another_ca_are_rejected() {
+    let trusted = certificates("trusted", &["peer-0"]);
+    let untrusted = certificates("untrusted", &["peer-1"]);
+    let mut listener = tls(&trusted, "peer-0").bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_address().unwrap();
+
+    tokio::spawn(async move { listener.accept().await });
+    assert!(tls(&untrusted, "peer-1").connect(&address).await.is_err());
+}
+
+#[tokio::test]
+async fn node_ids_must_match_the_certificate() {
+    let directory = certificates("impostor", &["server", "mallory"]);
+    let mut listener = tls(&directory, "server").bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_address().unwrap();
+
+    let impostor = {
+        let transport = tls(&directory, "mallory");
+        let address = address.clone();
+        tokio::spawn(async move {
+            let mut lines = transport.connect(&address).await.unwrap();
+            let _ = greet(
+                &mut lines,
+                &Hello::new(NodeId::new("peer-0"), String::new()),
+                true,
+            )
+            .await;
+        })
+    };
+
+    let (mut lines, _) = listener.accept().await.unwrap();
+    let hello = Hello::new(NodeId::new("server"), address);
+    let result = answer(&mut lines, &hello, true).await;
+    assert!(result.is_err());
+    impostor.abort();
+}
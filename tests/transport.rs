@@ -0,0 +1,52 @@
+use futures::{SinkExt, StreamExt};
+use std::fs;
+use token_ring::transport::{Limited, Listener, Memory, Transport, Unix};
+
+#[tokio::test]
+async fn unix_sockets_left_behind_are_replaced() {
+    let directory = tempfile::tempdir().unwrap();
+    let address = directory.path().join("peer.sock");
+    let address = address.to_str().unwrap();
+
+    drop(Unix.bind(address).await.unwrap());
+    assert!(fs::metadata(address).is_ok());
+    Unix.bind(address).await.unwrap();
+}
+
+#[tokio::test]
+async fn unix_binds_leave_other_files_alone() {
+    let directory = tempfile::tempdir().unwrap();
+    let address = directory.path().join("notes.txt");
+    fs::write(&address, "keep me").unwrap();
+    let address = address.to_str().unwrap();
+
+    assert!(Unix.bind(address).await.is_err());
+    assert_eq!(fs::read_to_string(address).unwrap(), "keep me");
+
+    // nor do they take over a socket still listened on
+    let socket = directory.path().join("peer.sock");
+    let socket = socket.to_str().unwrap();
+    let _listener = Unix.bind(socket).await.unwrap();
+    assert!(Unix.bind(socket).await.is_err());
+}
+
+#[tokio::test]
+async fn limited_connections_discard_long_lines() {
+    let transport = Limited::new(Memory::new(), 8);
+    let mut listener = transport.bind("server").await.unwrap();
+    let mut client = transport.connect("server").await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+
+    client.send("x".repeat(9)).await.unwrap();
+    assert!(server.next().await.unwrap().is_err());
+    server.send("y".repeat(9)).await.unwrap();
+    assert!(client.next().await.unwrap().is_err());
+
+    // other connections keep the default limit
+    let memory = Memory::new();
+    let mut listener = memory.bind("server").await.unwrap();
+    let mut client = memory.connect("server").await.unwrap();
+    let (mut server, _) = listener.accept().await.unwrap();
+    client.send("x".repeat(9)).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), "x".repeat(9));
+}
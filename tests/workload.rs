@@ -0,0 +1,170 @@
+use rand::{rngs::SmallRng, SeedableRng};
+use std::{io::Write, time::Duration};
+use token_ring::{
+    message::ServerRequest,
+    workload::{Arrival, OperandDistribution, OperationMix, TraceEntry, Workload},
+};
+
+fn mix(add: f64, sub: f64, mul: f64, div: f64) -> OperationMix {
+    OperationMix { add, sub, mul, div }
+}
+
+#[test]
+fn operations_are_picked_by_weight() {
+    let mut rng = SmallRng::seed_from_u64(1);
+
+    let only_mul = mix(0., 0., 1., 0.);
+    for _ in 0..100 {
+        assert_eq!(only_mul.sample(&mut rng, 2, 3), ServerRequest::Mul(2, 3));
+    }
+
+    let mostly_add = mix(9., 0., 0., 1.);
+    let adds = (0..10_000)
+        .filter(|_| matches!(mostly_add.sample(&mut rng, 1, 1), ServerRequest::Add(..)))
+        .count();
+    assert!((8_500..9_500).contains(&adds), "{adds} additions");
+}
+
+#[test]
+fn operands_follow_their_distribution() {
+    let mut rng = SmallRng::seed_from_u64(2);
+
+    let uniform = OperandDistribution::Uniform { min: -3, max: 3 };
+    let operands = (0..1_000)
+        .map(|_| uniform.sample(&mut rng))
+        .collect::<Vec<_>>();
+    assert!(operands.iter().all(|operand| (-3..=3).contains(operand)));
+    assert!(operands.contains(&-3) && operands.contains(&3));
+
+    let constant = OperandDistribution::Normal {
+        mean: 42.,
+        std_dev: 0.,
+    };
+    assert!((0..100).all(|_| constant.sample(&mut rng) == 42));
+}
+
+#[test]
+fn generators_of_the_same_seed_agree() {
+    let workload = Workload::default();
+    let requests = |seed: u8| {
+        let mut generator = workload.generator("peer-0", &[seed; 32]).unwrap();
+        (0..20)
+            .map(|_| generator.next_requests().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(requests(1), requests(1));
+    assert_ne!(requests(1), requests(2));
+}
+
+#[test]
+fn traces_are_replayed_in_order_then_end() {
+    let entries = [
+        TraceEntry {
+            delay: 0.5,
+            request: ServerRequest::Add(1, 2),
+        },
+        TraceEntry {
+            delay: 0.,
+            request: ServerRequest::Div(8, 4),
+        },
+    ];
+    let mut trace = tempfile::NamedTempFile::new().unwrap();
+    for entry in &entries {
+        writeln!(trace, "{}", serde_json::to_string(entry).unwrap()).unwrap();
+    }
+    writeln!(trace).unwrap();
+
+    let workload = Workload {
+        trace: Some(trace.path().display().to_string()),
+        ..Default::default()
+    };
+    workload.validate().unwrap();
+    let mut generator = workload.generator("peer-0", &[0; 32]).unwrap();
+
+    assert_eq!(
+        generator.next_requests(),
+        Some((Duration::from_millis(500), vec![ServerRequest::Add(1, 2)]))
+    );
+    assert_eq!(
+        generator.next_requests(),
+        Some((Duration::ZERO, vec![ServerRequest::Div(8, 4)]))
+    );
+    assert_eq!(generator.next_requests(), None);
+}
+
+#[test]
+fn unusable_workloads_are_rejected() {
+    Workload::default().validate().unwrap();
+
+    let invalid = [
+        Workload {
+            rate: 0.,
+            ..Default::default()
+        },
+        Workload {
+            rate: f64::NAN,
+            ..Default::default()
+        },
+        Workload {
+            rates: [("peer-0".to_string(), -1.)].into(),
+            ..Default::default()
+        },
+        Workload {
+            operations: mix(0., 0., 0., 0.),
+            ..Default::default()
+        },
+        Workload {
+            operations: mix(1., -1., 0., 0.),
+            ..Default::default()
+        },
+        Workload {
+            operands: OperandDistribution::Uniform { min: 5, max: 4 },
+            ..Default::default()
+        },
+        Workload {
+            operands: OperandDistribution::Normal {
+                mean: 0.,
+                std_dev: -1.,
+            },
+            ..Default::default()
+        },
+        Workload {
+            operands: OperandDistribution::Normal {
+                mean: 0.,
+                std_dev: f64::NAN,
+            },
+            ..Default::default()
+        },
+        Workload {
+            operands: OperandDistribution::Normal {
+                mean: 0.,
+                std_dev: f64::INFINITY,
+            },
+            ..Default::default()
+        },
+        Workload {
+            operands: OperandDistribution::Normal {
+                mean: f64::INFINITY,
+                std_dev: 1.,
+            },
+            ..Default::default()
+        },
+        Workload {
+            arrival: Arrival::Pareto { shape: 0.5 },
+            ..Default::default()
+        },
+    ];
+    for workload in invalid {
+        assert!(workload.validate().is_err(), "{workload:?}");
+    }
+
+    let mut trace = tempfile::NamedTempFile::new().unwrap();
+    writeln!(trace, r#"{{"delay": -1.0, "request": {{"Add": [1, 2]}}}}"#).unwrap();
+    let workload = Workload {
+        trace: Some(trace.path().display().to_string()),
+        ..Default::default()
+    };
+    assert!(workload.validate().is_err());
+    assert!(workload.generator("peer-0", &[0; 32]).is_err());
+}